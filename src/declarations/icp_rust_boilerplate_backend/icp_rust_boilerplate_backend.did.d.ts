@@ -1,53 +1,817 @@
 import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 
+export type Amenity = { 'PowerOutlets' : null } |
+  { 'WiFi' : null } |
+  { 'AirConditioning' : null } |
+  { 'Toilet' : null };
+export type BlockReason = { 'Trip' : null } |
+  { 'Maintenance' : null } |
+  { 'Charter' : null };
+export interface BlockedInterval {
+  'end' : bigint,
+  'reference_id' : bigint,
+  'start' : bigint,
+  'bus_id' : bigint,
+  'reason' : BlockReason,
+}
 export interface Bus {
   'id' : bigint,
+  'vin' : [] | [string],
   'is_booked' : boolean,
+  'odometer_km' : bigint,
   'model' : string,
   'updated_at' : [] | [bigint],
   'owner' : string,
+  'fare' : bigint,
   'make' : string,
   'color' : string,
   'year' : number,
+  'registration_plate' : [] | [string],
+  'created_at' : bigint,
+  'amenities' : Array<Amenity>,
+  'capacity' : number,
+  'model_id' : [] | [bigint],
+}
+export interface BusModel {
+  'id' : bigint,
+  'model' : string,
+  'deck_count' : number,
+  'class_fares' : [] | [Array<ClassFare>],
+  'make' : string,
   'created_at' : bigint,
+  'seats' : Array<SeatTemplate>,
+  'wheelchair_spaces' : number,
+}
+export interface BusModelPayload {
+  'model' : string,
+  'deck_count' : number,
+  'class_fares' : [] | [Array<ClassFare>],
+  'make' : string,
+  'seats' : Array<SeatTemplate>,
+  'wheelchair_spaces' : number,
 }
 export interface BusPayload {
+  'vin' : string,
   'is_booked' : boolean,
   'model' : string,
   'owner' : string,
+  'fare' : bigint,
   'make' : string,
   'color' : string,
   'year' : number,
+  'registration_plate' : string,
+  'amenities' : Array<Amenity>,
+  'capacity' : number,
+  'model_id' : [] | [bigint],
+}
+export interface Charter {
+  'id' : bigint,
+  'status' : CharterStatus,
+  'updated_at' : [] | [bigint],
+  'quote' : [] | [CharterQuote],
+  'created_at' : bigint,
+  'end_time' : bigint,
+  'customer_id' : bigint,
+  'pickup' : string,
+  'passenger_count' : number,
+  'start_time' : bigint,
+  'drop_off' : string,
+}
+export interface CharterQuote {
+  'quoted_at' : bigint,
+  'valid_until' : bigint,
+  'notes' : string,
+  'price' : bigint,
+  'bus_id' : bigint,
+}
+export interface CharterQuotePayload {
+  'valid_until' : bigint,
+  'notes' : string,
+  'price' : bigint,
+  'bus_id' : bigint,
+}
+export interface CharterRequestPayload {
+  'end_time' : bigint,
+  'customer_id' : bigint,
+  'pickup' : string,
+  'passenger_count' : number,
+  'start_time' : bigint,
+  'drop_off' : string,
+}
+export type CharterStatus = { 'Quoted' : null } |
+  { 'Accepted' : null } |
+  { 'Declined' : null } |
+  { 'Requested' : null } |
+  { 'Cancelled' : null };
+export interface ClassAvailability {
+  'fare' : bigint,
+  'seat_class' : SeatClass,
+  'seats_available' : number,
+}
+export interface ClassFare {
+  'seat_class' : SeatClass,
+  'fare_percentage' : bigint,
+}
+export interface ComplianceAlert {
+  'expired' : boolean,
+  'document' : ComplianceDocument,
+  'bus_id' : bigint,
+}
+export interface ComplianceDocument {
+  'id' : bigint,
+  'issued_at' : bigint,
+  'kind' : ComplianceKind,
+  'created_at' : bigint,
+  'reference_number' : string,
+  'bus_id' : bigint,
+  'expires_at' : bigint,
+}
+export interface ComplianceDocumentPayload {
+  'issued_at' : bigint,
+  'kind' : ComplianceKind,
+  'reference_number' : string,
+  'expires_at' : bigint,
+}
+export type ComplianceKind = { 'Registration' : null } |
+  { 'Insurance' : null } |
+  { 'Roadworthiness' : null };
+export interface ComplianceReport {
+  'generated_at' : bigint,
+  'alerts' : Array<ComplianceAlert>,
+}
+export interface Customer {
+  'id' : bigint,
+  'principal' : [] | [Principal],
+  'contact' : string,
+  'name' : string,
+}
+export type Discount = { 'Fixed' : bigint } |
+  { 'Percentage' : number };
+export interface Driver {
+  'id' : bigint,
+  'license_number' : string,
+  'updated_at' : [] | [bigint],
+  'principal' : Principal,
+  'contact' : string,
+  'license_class' : LicenseClass,
+  'name' : string,
+  'created_at' : bigint,
+  'license_expires_at' : bigint,
+}
+export interface DriverPayload {
+  'license_number' : string,
+  'principal' : Principal,
+  'contact' : string,
+  'license_class' : LicenseClass,
+  'name' : string,
+  'license_expires_at' : bigint,
+}
+export interface DrivingRules {
+  'max_shift_gap' : bigint,
+  'max_driving_per_24h' : bigint,
+  'min_rest_between_shifts' : bigint,
+}
+export type Error = { 'InvalidInput' : { 'msg' : string } } |
+  { 'NotFound' : { 'msg' : string } } |
+  { 'Unauthorized' : { 'msg' : string } } |
+  { 'Conflict' : { 'msg' : string } };
+export type FareCategory = { 'Student' : null } |
+  { 'Senior' : null } |
+  { 'Adult' : null } |
+  { 'Child' : null };
+export interface GroupReservationPayload {
+  'from_stop' : [] | [bigint],
+  'trip_id' : [] | [bigint],
+  'redeem_points' : [] | [bigint],
+  'to_stop' : [] | [bigint],
+  'passengers' : Array<PassengerPayload>,
+  'customer_id' : bigint,
+  'bus_id' : bigint,
+  'promo_code' : [] | [string],
+}
+export interface GtfsAgency {
+  'url' : string,
+  'timezone' : string,
+  'name' : string,
+}
+export type GtfsFile = { 'Routes' : null } |
+  { 'Stops' : null } |
+  { 'CalendarDates' : null } |
+  { 'Calendar' : null } |
+  { 'Trips' : null } |
+  { 'StopTimes' : null };
+export interface GtfsImport {
+  'id' : bigint,
+  'bytes' : bigint,
+  'chunks' : bigint,
+  'started_at' : bigint,
+  'started_by' : Principal,
+}
+export interface GtfsImportOptions { 'bus_ids' : BigUint64Array | bigint[] }
+export interface GtfsImportReport {
+  'stops_created' : number,
+  'timetables_updated' : number,
+  'routes_created' : number,
+  'errors' : Array<GtfsLineError>,
+  'import_id' : bigint,
+  'finished' : boolean,
+  'errors_truncated' : boolean,
+  'timetables_created' : number,
+  'stops_updated' : number,
+  'routes_updated' : number,
+}
+export interface GtfsLineError {
+  'file' : GtfsFile,
+  'line' : number,
+  'message' : string,
+}
+export interface HttpRequest {
+  'url' : string,
+  'method' : string,
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+}
+export interface HttpResponse {
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+  'upgrade' : [] | [boolean],
+  'streaming_strategy' : [] | [StreamingStrategy],
+  'status_code' : number,
+}
+export interface Itinerary {
+  'fare' : bigint,
+  'transfers' : number,
+  'legs' : Array<JourneyLeg>,
+  'departure_time' : bigint,
+  'seats_available' : number,
+  'arrival_time' : bigint,
+}
+export interface JourneyLeg {
+  'from_stop' : bigint,
+  'trip_id' : bigint,
+  'to_stop' : bigint,
+  'fare' : bigint,
+  'route_id' : bigint,
+  'departure_time' : bigint,
+  'seats_available' : number,
+  'arrival_time' : bigint,
+  'bus_id' : bigint,
+}
+export type LicenseClass = { 'D' : null } |
+  { 'D1' : null };
+export interface LoyaltyAccount {
+  'lifetime_earned' : bigint,
+  'updated_at' : [] | [bigint],
+  'balance' : bigint,
+  'lifetime_expired' : bigint,
+  'created_at' : bigint,
+  'customer_id' : bigint,
+  'lifetime_redeemed' : bigint,
+}
+export interface LoyaltyEntry {
+  'id' : bigint,
+  'reservation_id' : [] | [bigint],
+  'kind' : LoyaltyEntryKind,
+  'created_at' : bigint,
+  'customer_id' : bigint,
+  'remaining' : bigint,
+  'expires_at' : [] | [bigint],
+  'points' : bigint,
+}
+export type LoyaltyEntryKind = { 'Refund' : null } |
+  { 'Accrual' : null } |
+  { 'Redemption' : null } |
+  { 'Expiry' : null };
+export interface LoyaltyStatement {
+  'entries' : Array<LoyaltyEntry>,
+  'account' : LoyaltyAccount,
+}
+export interface MaintenanceCompletionPayload {
+  'odometer_km' : bigint,
+  'next_due_at' : [] | [bigint],
+  'cost' : bigint,
+  'notes' : string,
+  'next_due_odometer_km' : [] | [bigint],
+}
+export interface MaintenancePayload {
+  'scheduled_start' : bigint,
+  'service_type' : ServiceType,
+  'scheduled_end' : bigint,
+  'notes' : string,
+  'bus_id' : bigint,
+}
+export interface MaintenanceRecord {
+  'id' : bigint,
+  'scheduled_start' : bigint,
+  'status' : MaintenanceStatus,
+  'odometer_km' : [] | [bigint],
+  'updated_at' : [] | [bigint],
+  'next_due_at' : [] | [bigint],
+  'service_type' : ServiceType,
+  'cost' : [] | [bigint],
+  'scheduled_end' : bigint,
+  'created_at' : bigint,
+  'notes' : string,
+  'completed_at' : [] | [bigint],
+  'bus_id' : bigint,
+  'next_due_odometer_km' : [] | [bigint],
+}
+export type MaintenanceStatus = { 'Scheduled' : null } |
+  { 'Cancelled' : null } |
+  { 'Completed' : null };
+export interface NewReportToken {
+  'token' : string,
+  'report_token' : ReportToken,
+}
+export interface OverdueBus {
+  'bus' : Bus,
+  'overdue_by_date' : boolean,
+  'overdue_by_mileage' : boolean,
+  'last_service' : MaintenanceRecord,
+}
+export interface Passenger {
+  'id' : number,
+  'cancelled_at' : [] | [bigint],
+  'fare' : bigint,
+  'name' : string,
+  'seat_class' : SeatClass,
+  'seat_number' : number,
+  'category' : FareCategory,
+}
+export interface PassengerPayload {
+  'name' : string,
+  'seat_class' : [] | [SeatClass],
+  'category' : FareCategory,
+}
+export interface PassengerRebooking {
+  'reservation_id' : bigint,
+  'name' : string,
+  'passenger_id' : number,
+  'outcome' : RebookingOutcome,
+}
+export interface PromoCode {
+  'travel_until' : [] | [bigint],
+  'updated_at' : [] | [bigint],
+  'max_uses' : [] | [number],
+  'bus_ids' : BigUint64Array | bigint[],
+  'code' : string,
+  'max_uses_per_customer' : [] | [number],
+  'uses' : number,
+  'created_at' : bigint,
+  'route_ids' : BigUint64Array | bigint[],
+  'discount_granted' : bigint,
+  'discount' : Discount,
+  'valid_from' : bigint,
+  'is_active' : boolean,
+  'budget' : [] | [bigint],
+  'expires_at' : bigint,
+  'travel_from' : [] | [bigint],
+}
+export interface PromoCodePayload {
+  'travel_until' : [] | [bigint],
+  'max_uses' : [] | [number],
+  'bus_ids' : BigUint64Array | bigint[],
+  'code' : string,
+  'max_uses_per_customer' : [] | [number],
+  'route_ids' : BigUint64Array | bigint[],
+  'discount' : Discount,
+  'valid_from' : bigint,
+  'budget' : [] | [bigint],
+  'expires_at' : bigint,
+  'travel_from' : [] | [bigint],
+}
+export type RebookingOutcome = {
+    'Rebooked' : {
+      'reservation_id' : bigint,
+      'trip_id' : bigint,
+      'departure_time' : bigint,
+      'seat_number' : number,
+    }
+  } |
+  { 'Refunded' : { 'amount' : bigint } };
+export interface ReportToken {
+  'id' : bigint,
+  'created_at' : bigint,
+  'label' : string,
+  'token_hash' : string,
 }
-export interface Customer { 'id' : bigint, 'contact' : string, 'name' : string }
-export type Error = { 'NotFound' : { 'msg' : string } };
 export interface Reservation {
+  'id' : bigint,
+  'status' : ReservationStatus,
   'reservation_time' : bigint,
+  'from_stop' : [] | [bigint],
+  'trip_id' : [] | [bigint],
+  'to_stop' : [] | [bigint],
+  'fare' : bigint,
+  'passengers' : Array<Passenger>,
   'customer_id' : bigint,
+  'discount' : bigint,
+  'points_redeemed' : bigint,
   'bus_id' : bigint,
+  'promo_code' : [] | [string],
 }
+export type ReservationStatus = { 'Refunded' : null } |
+  { 'Confirmed' : null } |
+  { 'Completed' : null };
 export type Result = { 'Ok' : null } |
   { 'Err' : Error };
-export type Result_1 = { 'Ok' : Bus } |
+export type Result_1 = { 'Ok' : Charter } |
+  { 'Err' : Error };
+export type Result_10 = { 'Ok' : BlockedInterval } |
+  { 'Err' : Error };
+export type Result_11 = { 'Ok' : MaintenanceRecord } |
+  { 'Err' : Error };
+export type Result_12 = { 'Ok' : Reservation } |
+  { 'Err' : Error };
+export type Result_13 = { 'Ok' : TripCancellationReport } |
+  { 'Err' : Error };
+export type Result_14 = { 'Ok' : PromoCode } |
+  { 'Err' : Error };
+export type Result_15 = { 'Ok' : NewReportToken } |
+  { 'Err' : Error };
+export type Result_16 = { 'Ok' : Customer } |
+  { 'Err' : Error };
+export type Result_17 = { 'Ok' : Array<Bus> } |
+  { 'Err' : Error };
+export type Result_18 = { 'Ok' : GtfsImportReport } |
+  { 'Err' : Error };
+export type Result_19 = { 'Ok' : Array<BlockedInterval> } |
+  { 'Err' : Error };
+export type Result_2 = { 'Ok' : Bus } |
+  { 'Err' : Error };
+export type Result_20 = { 'Ok' : string } |
+  { 'Err' : Error };
+export type Result_21 = { 'Ok' : Array<Charter> } |
+  { 'Err' : Error };
+export type Result_22 = { 'Ok' : VehiclePosition } |
+  { 'Err' : Error };
+export type Result_23 = { 'Ok' : LoyaltyAccount } |
+  { 'Err' : Error };
+export type Result_24 = { 'Ok' : LoyaltyStatement } |
+  { 'Err' : Error };
+export type Result_25 = { 'Ok' : Array<Trip> } |
+  { 'Err' : Error };
+export type Result_26 = { 'Ok' : Array<SeatMapEntry> } |
+  { 'Err' : Error };
+export type Result_27 = { 'Ok' : SegmentAvailability } |
+  { 'Err' : Error };
+export type Result_28 = { 'Ok' : TripProgress } |
+  { 'Err' : Error };
+export type Result_29 = { 'Ok' : VehicleEta } |
+  { 'Err' : Error };
+export type Result_3 = { 'Ok' : BusModel } |
+  { 'Err' : Error };
+export type Result_30 = { 'Ok' : boolean } |
+  { 'Err' : Error };
+export type Result_31 = { 'Ok' : Array<PromoCode> } |
+  { 'Err' : Error };
+export type Result_32 = { 'Ok' : Array<ReportToken> } |
+  { 'Err' : Error };
+export type Result_33 = { 'Ok' : ComplianceReport } |
+  { 'Err' : Error };
+export type Result_34 = { 'Ok' : ReportToken } |
+  { 'Err' : Error };
+export type Result_35 = { 'Ok' : TimetableReport } |
+  { 'Err' : Error };
+export type Result_36 = { 'Ok' : Array<Itinerary> } |
+  { 'Err' : Error };
+export type Result_37 = { 'Ok' : DrivingRules } |
+  { 'Err' : Error };
+export type Result_38 = { 'Ok' : GtfsAgency } |
+  { 'Err' : Error };
+export type Result_39 = { 'Ok' : GtfsImport } |
+  { 'Err' : Error };
+export type Result_4 = { 'Ok' : ComplianceDocument } |
   { 'Err' : Error };
-export type Result_2 = { 'Ok' : Customer } |
+export type Result_5 = { 'Ok' : Driver } |
   { 'Err' : Error };
-export type Result_3 = { 'Ok' : Reservation } |
+export type Result_6 = { 'Ok' : Route } |
   { 'Err' : Error };
-export type Result_4 = { 'Ok' : boolean } |
+export type Result_7 = { 'Ok' : Stop } |
   { 'Err' : Error };
+export type Result_8 = { 'Ok' : Timetable } |
+  { 'Err' : Error };
+export type Result_9 = { 'Ok' : Trip } |
+  { 'Err' : Error };
+export interface Route {
+  'id' : bigint,
+  'updated_at' : [] | [bigint],
+  'name' : string,
+  'created_at' : bigint,
+  'stops' : Array<RouteStop>,
+}
+export interface RoutePayload { 'name' : string, 'stops' : Array<RouteStop> }
+export interface RouteStop {
+  'departure_offset' : bigint,
+  'stop_id' : bigint,
+  'arrival_offset' : bigint,
+  'distance_m' : bigint,
+}
+export type SeatClass = { 'Premium' : null } |
+  { 'Sleeper' : null } |
+  { 'Standard' : null };
+export interface SeatMapEntry { 'seat' : SeatTemplate, 'is_occupied' : boolean }
+export interface SeatTemplate {
+  'row' : number,
+  'is_wheelchair_accessible' : boolean,
+  'deck' : number,
+  'seat_class' : SeatClass,
+  'seat_number' : number,
+  'column' : number,
+}
+export interface SegmentAvailability {
+  'from_stop' : bigint,
+  'trip_id' : bigint,
+  'to_stop' : bigint,
+  'classes' : Array<ClassAvailability>,
+  'departure_time' : bigint,
+  'arrival_time' : bigint,
+}
+export type ServiceType = { 'OilChange' : null } |
+  { 'Inspection' : null } |
+  { 'Repair' : null } |
+  { 'Brakes' : null } |
+  { 'Tyres' : null } |
+  { 'Other' : string };
+export interface SkippedDeparture {
+  'timetable_id' : bigint,
+  'departure_time' : bigint,
+  'reason' : string,
+}
+export interface Stop {
+  'id' : bigint,
+  'latitude' : number,
+  'name' : string,
+  'created_at' : bigint,
+  'longitude' : number,
+}
+export interface StopPayload {
+  'latitude' : number,
+  'name' : string,
+  'longitude' : number,
+}
+export interface StreamingCallbackHttpResponse {
+  'token' : [] | [StreamingCallbackToken],
+  'body' : Uint8Array | number[],
+}
+export interface StreamingCallbackToken {
+  'key' : BigUint64Array | bigint[],
+  'report' : string,
+  'query' : string,
+}
+export type StreamingStrategy = {
+    'Callback' : {
+      'token' : StreamingCallbackToken,
+      'callback' : [Principal, string],
+    }
+  };
+export interface Timetable {
+  'id' : bigint,
+  'updated_at' : [] | [bigint],
+  'exceptions' : BigUint64Array | bigint[],
+  'bus_ids' : BigUint64Array | bigint[],
+  'days_of_week' : Array<Weekday>,
+  'departure_times' : BigUint64Array | bigint[],
+  'route_id' : bigint,
+  'created_at' : bigint,
+  'valid_until' : bigint,
+  'valid_from' : bigint,
+}
+export interface TimetablePayload {
+  'exceptions' : BigUint64Array | bigint[],
+  'bus_ids' : BigUint64Array | bigint[],
+  'days_of_week' : Array<Weekday>,
+  'departure_times' : BigUint64Array | bigint[],
+  'route_id' : bigint,
+  'valid_until' : bigint,
+  'valid_from' : bigint,
+}
+export interface TimetableReport {
+  'generated_at' : bigint,
+  'skipped' : Array<SkippedDeparture>,
+  'trips_created' : number,
+}
+export interface Trip {
+  'id' : bigint,
+  'status' : TripStatus,
+  'timetable_id' : [] | [bigint],
+  'updated_at' : [] | [bigint],
+  'destination' : string,
+  'origin' : string,
+  'route_id' : [] | [bigint],
+  'departure_time' : bigint,
+  'created_at' : bigint,
+  'actual_departure_time' : [] | [bigint],
+  'driver_id' : [] | [bigint],
+  'actual_arrival_time' : [] | [bigint],
+  'delay_minutes' : number,
+  'arrival_time' : bigint,
+  'bus_id' : bigint,
+}
+export interface TripCancellationReport {
+  'trip' : Trip,
+  'passengers' : Array<PassengerRebooking>,
+}
+export interface TripEvent {
+  'id' : bigint,
+  'trip_id' : bigint,
+  'stop_id' : [] | [bigint],
+  'kind' : TripEventKind,
+  'recorded_at' : bigint,
+  'recorded_by' : Principal,
+  'delay_minutes' : [] | [number],
+  'reason' : [] | [string],
+}
+export type TripEventKind = { 'Arrived' : null } |
+  { 'Departed' : null } |
+  { 'Cancelled' : null } |
+  { 'Delayed' : null };
+export interface TripPayload {
+  'destination' : string,
+  'origin' : string,
+  'route_id' : [] | [bigint],
+  'departure_time' : bigint,
+  'arrival_time' : bigint,
+  'bus_id' : bigint,
+}
+export interface TripProgress {
+  'trip' : Trip,
+  'departure_delay_minutes' : [] | [bigint],
+  'events' : Array<TripEvent>,
+}
+export interface TripSearchPayload {
+  'departs_after' : bigint,
+  'destination' : [] | [string],
+  'departs_before' : bigint,
+  'origin' : [] | [string],
+  'seat_class' : [] | [SeatClass],
+  'required_amenities' : Array<Amenity>,
+}
+export interface TripSearchResult {
+  'fare' : bigint,
+  'trip' : Trip,
+  'seat_class' : SeatClass,
+  'amenities' : Array<Amenity>,
+  'seats_available' : number,
+}
+export type TripStatus = { 'Scheduled' : null } |
+  { 'Cancelled' : null } |
+  { 'InProgress' : null } |
+  { 'Completed' : null };
+export interface VehicleEta {
+  'estimated_arrival' : bigint,
+  'stop_id' : bigint,
+  'speed_mps' : [] | [number],
+  'position' : VehiclePosition,
+  'distance_remaining_m' : bigint,
+}
+export interface VehiclePosition {
+  'latitude' : number,
+  'trip_id' : bigint,
+  'heading' : [] | [number],
+  'speed_mps' : [] | [number],
+  'recorded_at' : bigint,
+  'longitude' : number,
+  'sequence' : bigint,
+}
+export interface VehiclePositionPayload {
+  'latitude' : number,
+  'heading' : [] | [number],
+  'speed_mps' : [] | [number],
+  'longitude' : number,
+}
+export type Weekday = { 'Saturday' : null } |
+  { 'Thursday' : null } |
+  { 'Sunday' : null } |
+  { 'Tuesday' : null } |
+  { 'Friday' : null } |
+  { 'Wednesday' : null } |
+  { 'Monday' : null };
 export interface _SERVICE {
-  'add_bus' : ActorMethod<[BusPayload], [] | [Bus]>,
+  'abort_gtfs_import' : ActorMethod<[bigint], Result>,
+  'accept_charter_quote' : ActorMethod<[bigint], Result_1>,
+  'add_bus' : ActorMethod<[BusPayload], Result_2>,
+  'add_bus_model' : ActorMethod<[BusModelPayload], Result_3>,
+  'add_compliance_document' : ActorMethod<
+    [bigint, ComplianceDocumentPayload],
+    Result_4
+  >,
   'add_customer' : ActorMethod<[string, string], [] | [Customer]>,
+  'add_driver' : ActorMethod<[DriverPayload], Result_5>,
+  'add_route' : ActorMethod<[RoutePayload], Result_6>,
+  'add_stop' : ActorMethod<[StopPayload], Result_7>,
+  'add_timetable' : ActorMethod<[TimetablePayload], Result_8>,
+  'add_trip' : ActorMethod<[TripPayload], Result_9>,
+  'assign_driver' : ActorMethod<[bigint, bigint], Result_9>,
+  'block_bus' : ActorMethod<[bigint, bigint, bigint], Result_10>,
+  'cancel_charter' : ActorMethod<[bigint], Result_1>,
+  'cancel_maintenance' : ActorMethod<[bigint], Result_11>,
+  'cancel_passenger' : ActorMethod<[bigint, number], Result_12>,
   'cancel_reservation' : ActorMethod<[bigint], Result>,
-  'delete_bus' : ActorMethod<[bigint], Result_1>,
-  'delete_customer' : ActorMethod<[bigint], Result_2>,
+  'cancel_trip' : ActorMethod<[bigint, string], Result_13>,
+  'complete_maintenance' : ActorMethod<
+    [bigint, MaintenanceCompletionPayload],
+    Result_11
+  >,
+  'complete_reservation' : ActorMethod<[bigint], Result_12>,
+  'create_promo_code' : ActorMethod<[PromoCodePayload], Result_14>,
+  'create_report_token' : ActorMethod<[string], Result_15>,
+  'deactivate_promo_code' : ActorMethod<[string], Result_14>,
+  'decline_charter_quote' : ActorMethod<[bigint], Result_1>,
+  'delete_bus' : ActorMethod<[bigint], Result_2>,
+  'delete_bus_model' : ActorMethod<[bigint], Result_3>,
+  'delete_customer' : ActorMethod<[bigint], Result_16>,
+  'delete_timetable' : ActorMethod<[bigint], Result_8>,
+  'find_available_buses' : ActorMethod<[bigint, bigint, number], Result_17>,
+  'finish_gtfs_import' : ActorMethod<[bigint, GtfsImportOptions], Result_18>,
   'generate_report' : ActorMethod<[], Array<Bus>>,
-  'get_bus' : ActorMethod<[bigint], Result_1>,
-  'get_customer' : ActorMethod<[bigint], Result_2>,
-  'get_reservation' : ActorMethod<[bigint], Result_3>,
-  'is_booked' : ActorMethod<[bigint], Result_4>,
-  'make_reservation' : ActorMethod<[bigint, bigint], Result_3>,
-  'update_bus' : ActorMethod<[bigint, BusPayload], Result_1>,
+  'get_bus' : ActorMethod<[bigint], Result_2>,
+  'get_bus_by_plate' : ActorMethod<[string], Result_2>,
+  'get_bus_by_vin' : ActorMethod<[string], Result_2>,
+  'get_bus_calendar' : ActorMethod<[bigint, bigint, bigint], Result_19>,
+  'get_bus_maintenance' : ActorMethod<[bigint], Array<MaintenanceRecord>>,
+  'get_bus_model' : ActorMethod<[bigint], Result_3>,
+  'get_charter' : ActorMethod<[bigint], Result_1>,
+  'get_compliance_alerts' : ActorMethod<[], ComplianceReport>,
+  'get_compliance_documents' : ActorMethod<[bigint], Array<ComplianceDocument>>,
+  'get_customer' : ActorMethod<[bigint], Result_16>,
+  'get_customer_calendar' : ActorMethod<[bigint], Result_20>,
+  'get_customer_calendar_link' : ActorMethod<[bigint], Result_20>,
+  'get_customer_charters' : ActorMethod<[bigint], Result_21>,
+  'get_driver' : ActorMethod<[bigint], Result_5>,
+  'get_driving_rules' : ActorMethod<[], DrivingRules>,
+  'get_gtfs_agency' : ActorMethod<[], GtfsAgency>,
+  'get_gtfs_import_report' : ActorMethod<[bigint], Result_18>,
+  'get_latest_position' : ActorMethod<[bigint], Result_22>,
+  'get_loyalty_account' : ActorMethod<[bigint], Result_23>,
+  'get_loyalty_statement' : ActorMethod<[bigint], Result_24>,
+  'get_my_assignments' : ActorMethod<[], Result_25>,
+  'get_overdue_buses' : ActorMethod<[bigint], Array<OverdueBus>>,
+  'get_promo_code' : ActorMethod<[string], Result_14>,
+  'get_reservation' : ActorMethod<[bigint], Result_12>,
+  'get_reservation_calendar' : ActorMethod<[bigint], Result_20>,
+  'get_reservation_calendar_link' : ActorMethod<[bigint], Result_20>,
+  'get_route' : ActorMethod<[bigint], Result_6>,
+  'get_seat_map' : ActorMethod<[bigint, [] | [bigint]], Result_26>,
+  'get_segment_availability' : ActorMethod<[bigint, bigint, bigint], Result_27>,
+  'get_stop' : ActorMethod<[bigint], Result_7>,
+  'get_timetable' : ActorMethod<[bigint], Result_8>,
+  'get_timetable_report' : ActorMethod<[], TimetableReport>,
+  'get_trip' : ActorMethod<[bigint], Result_9>,
+  'get_trip_progress' : ActorMethod<[bigint], Result_28>,
+  'get_vehicle_eta' : ActorMethod<[bigint, bigint], Result_29>,
+  'http_request' : ActorMethod<[HttpRequest], HttpResponse>,
+  'http_request_streaming_callback' : ActorMethod<
+    [StreamingCallbackToken],
+    StreamingCallbackHttpResponse
+  >,
+  'is_booked' : ActorMethod<[bigint], Result_30>,
+  'list_bus_models' : ActorMethod<[], Array<BusModel>>,
+  'list_promo_codes' : ActorMethod<[], Result_31>,
+  'list_report_tokens' : ActorMethod<[], Result_32>,
+  'list_routes' : ActorMethod<[], Array<Route>>,
+  'list_stops' : ActorMethod<[], Array<Stop>>,
+  'list_timetables' : ActorMethod<[], Array<Timetable>>,
+  'make_group_reservation' : ActorMethod<[GroupReservationPayload], Result_12>,
+  'make_reservation' : ActorMethod<
+    [
+      bigint,
+      bigint,
+      [] | [bigint],
+      [] | [bigint],
+      [] | [bigint],
+      [] | [string],
+      [] | [bigint],
+    ],
+    Result_12
+  >,
+  'push_vehicle_position' : ActorMethod<
+    [bigint, VehiclePositionPayload],
+    Result_22
+  >,
+  'quote_charter' : ActorMethod<[bigint, CharterQuotePayload], Result_1>,
+  'record_arrival' : ActorMethod<[bigint, [] | [bigint]], Result_9>,
+  'record_departure' : ActorMethod<[bigint, [] | [bigint]], Result_9>,
+  'record_odometer' : ActorMethod<[bigint, bigint], Result_2>,
+  'refresh_compliance_alerts' : ActorMethod<[], Result_33>,
+  'report_delay' : ActorMethod<[bigint, number, string], Result_9>,
+  'request_charter' : ActorMethod<[CharterRequestPayload], Result_1>,
+  'revoke_report_token' : ActorMethod<[bigint], Result_34>,
+  'run_timetables' : ActorMethod<[], Result_35>,
+  'schedule_maintenance' : ActorMethod<[MaintenancePayload], Result_11>,
+  'search_journeys' : ActorMethod<[bigint, bigint, bigint, number], Result_36>,
+  'search_trips' : ActorMethod<[TripSearchPayload], Array<TripSearchResult>>,
+  'set_driving_rules' : ActorMethod<[DrivingRules], Result_37>,
+  'set_gtfs_agency' : ActorMethod<[GtfsAgency], Result_38>,
+  'start_gtfs_import' : ActorMethod<[], Result_39>,
+  'unassign_driver' : ActorMethod<[bigint], Result_9>,
+  'unblock_bus' : ActorMethod<[bigint, bigint], Result_10>,
+  'update_bus' : ActorMethod<[bigint, BusPayload], Result_2>,
+  'update_driver' : ActorMethod<[bigint, DriverPayload], Result_5>,
+  'update_timetable' : ActorMethod<[bigint, TimetablePayload], Result_8>,
+  'upload_gtfs_chunk' : ActorMethod<
+    [bigint, GtfsFile, Uint8Array | number[]],
+    Result_39
+  >,
 }
@@ -1,52 +1,905 @@
 export const idlFactory = ({ IDL }) => {
+  const Error = IDL.Variant({
+    'InvalidInput' : IDL.Record({ 'msg' : IDL.Text }),
+    'NotFound' : IDL.Record({ 'msg' : IDL.Text }),
+    'Unauthorized' : IDL.Record({ 'msg' : IDL.Text }),
+    'Conflict' : IDL.Record({ 'msg' : IDL.Text }),
+  });
+  const Result = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : Error });
+  const CharterStatus = IDL.Variant({
+    'Quoted' : IDL.Null,
+    'Accepted' : IDL.Null,
+    'Declined' : IDL.Null,
+    'Requested' : IDL.Null,
+    'Cancelled' : IDL.Null,
+  });
+  const CharterQuote = IDL.Record({
+    'quoted_at' : IDL.Nat64,
+    'valid_until' : IDL.Nat64,
+    'notes' : IDL.Text,
+    'price' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+  });
+  const Charter = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : CharterStatus,
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'quote' : IDL.Opt(CharterQuote),
+    'created_at' : IDL.Nat64,
+    'end_time' : IDL.Nat64,
+    'customer_id' : IDL.Nat64,
+    'pickup' : IDL.Text,
+    'passenger_count' : IDL.Nat32,
+    'start_time' : IDL.Nat64,
+    'drop_off' : IDL.Text,
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : Charter, 'Err' : Error });
+  const Amenity = IDL.Variant({
+    'PowerOutlets' : IDL.Null,
+    'WiFi' : IDL.Null,
+    'AirConditioning' : IDL.Null,
+    'Toilet' : IDL.Null,
+  });
   const BusPayload = IDL.Record({
+    'vin' : IDL.Text,
     'is_booked' : IDL.Bool,
     'model' : IDL.Text,
     'owner' : IDL.Text,
+    'fare' : IDL.Nat64,
     'make' : IDL.Text,
     'color' : IDL.Text,
     'year' : IDL.Nat32,
+    'registration_plate' : IDL.Text,
+    'amenities' : IDL.Vec(Amenity),
+    'capacity' : IDL.Nat32,
+    'model_id' : IDL.Opt(IDL.Nat64),
   });
   const Bus = IDL.Record({
     'id' : IDL.Nat64,
+    'vin' : IDL.Opt(IDL.Text),
     'is_booked' : IDL.Bool,
+    'odometer_km' : IDL.Nat64,
     'model' : IDL.Text,
     'updated_at' : IDL.Opt(IDL.Nat64),
     'owner' : IDL.Text,
+    'fare' : IDL.Nat64,
     'make' : IDL.Text,
     'color' : IDL.Text,
     'year' : IDL.Nat32,
+    'registration_plate' : IDL.Opt(IDL.Text),
     'created_at' : IDL.Nat64,
+    'amenities' : IDL.Vec(Amenity),
+    'capacity' : IDL.Nat32,
+    'model_id' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : Bus, 'Err' : Error });
+  const SeatClass = IDL.Variant({
+    'Premium' : IDL.Null,
+    'Sleeper' : IDL.Null,
+    'Standard' : IDL.Null,
+  });
+  const ClassFare = IDL.Record({
+    'seat_class' : SeatClass,
+    'fare_percentage' : IDL.Nat64,
+  });
+  const SeatTemplate = IDL.Record({
+    'row' : IDL.Nat16,
+    'is_wheelchair_accessible' : IDL.Bool,
+    'deck' : IDL.Nat8,
+    'seat_class' : SeatClass,
+    'seat_number' : IDL.Nat32,
+    'column' : IDL.Nat16,
+  });
+  const BusModelPayload = IDL.Record({
+    'model' : IDL.Text,
+    'deck_count' : IDL.Nat8,
+    'class_fares' : IDL.Opt(IDL.Vec(ClassFare)),
+    'make' : IDL.Text,
+    'seats' : IDL.Vec(SeatTemplate),
+    'wheelchair_spaces' : IDL.Nat32,
   });
+  const BusModel = IDL.Record({
+    'id' : IDL.Nat64,
+    'model' : IDL.Text,
+    'deck_count' : IDL.Nat8,
+    'class_fares' : IDL.Opt(IDL.Vec(ClassFare)),
+    'make' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'seats' : IDL.Vec(SeatTemplate),
+    'wheelchair_spaces' : IDL.Nat32,
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : BusModel, 'Err' : Error });
+  const ComplianceKind = IDL.Variant({
+    'Registration' : IDL.Null,
+    'Insurance' : IDL.Null,
+    'Roadworthiness' : IDL.Null,
+  });
+  const ComplianceDocumentPayload = IDL.Record({
+    'issued_at' : IDL.Nat64,
+    'kind' : ComplianceKind,
+    'reference_number' : IDL.Text,
+    'expires_at' : IDL.Nat64,
+  });
+  const ComplianceDocument = IDL.Record({
+    'id' : IDL.Nat64,
+    'issued_at' : IDL.Nat64,
+    'kind' : ComplianceKind,
+    'created_at' : IDL.Nat64,
+    'reference_number' : IDL.Text,
+    'bus_id' : IDL.Nat64,
+    'expires_at' : IDL.Nat64,
+  });
+  const Result_4 = IDL.Variant({ 'Ok' : ComplianceDocument, 'Err' : Error });
   const Customer = IDL.Record({
     'id' : IDL.Nat64,
+    'principal' : IDL.Opt(IDL.Principal),
     'contact' : IDL.Text,
     'name' : IDL.Text,
   });
-  const Error = IDL.Variant({ 'NotFound' : IDL.Record({ 'msg' : IDL.Text }) });
-  const Result = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : Error });
-  const Result_1 = IDL.Variant({ 'Ok' : Bus, 'Err' : Error });
-  const Result_2 = IDL.Variant({ 'Ok' : Customer, 'Err' : Error });
+  const LicenseClass = IDL.Variant({ 'D' : IDL.Null, 'D1' : IDL.Null });
+  const DriverPayload = IDL.Record({
+    'license_number' : IDL.Text,
+    'principal' : IDL.Principal,
+    'contact' : IDL.Text,
+    'license_class' : LicenseClass,
+    'name' : IDL.Text,
+    'license_expires_at' : IDL.Nat64,
+  });
+  const Driver = IDL.Record({
+    'id' : IDL.Nat64,
+    'license_number' : IDL.Text,
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'principal' : IDL.Principal,
+    'contact' : IDL.Text,
+    'license_class' : LicenseClass,
+    'name' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'license_expires_at' : IDL.Nat64,
+  });
+  const Result_5 = IDL.Variant({ 'Ok' : Driver, 'Err' : Error });
+  const RouteStop = IDL.Record({
+    'departure_offset' : IDL.Nat64,
+    'stop_id' : IDL.Nat64,
+    'arrival_offset' : IDL.Nat64,
+    'distance_m' : IDL.Nat64,
+  });
+  const RoutePayload = IDL.Record({
+    'name' : IDL.Text,
+    'stops' : IDL.Vec(RouteStop),
+  });
+  const Route = IDL.Record({
+    'id' : IDL.Nat64,
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'name' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'stops' : IDL.Vec(RouteStop),
+  });
+  const Result_6 = IDL.Variant({ 'Ok' : Route, 'Err' : Error });
+  const StopPayload = IDL.Record({
+    'latitude' : IDL.Float64,
+    'name' : IDL.Text,
+    'longitude' : IDL.Float64,
+  });
+  const Stop = IDL.Record({
+    'id' : IDL.Nat64,
+    'latitude' : IDL.Float64,
+    'name' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'longitude' : IDL.Float64,
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : Stop, 'Err' : Error });
+  const Weekday = IDL.Variant({
+    'Saturday' : IDL.Null,
+    'Thursday' : IDL.Null,
+    'Sunday' : IDL.Null,
+    'Tuesday' : IDL.Null,
+    'Friday' : IDL.Null,
+    'Wednesday' : IDL.Null,
+    'Monday' : IDL.Null,
+  });
+  const TimetablePayload = IDL.Record({
+    'exceptions' : IDL.Vec(IDL.Nat64),
+    'bus_ids' : IDL.Vec(IDL.Nat64),
+    'days_of_week' : IDL.Vec(Weekday),
+    'departure_times' : IDL.Vec(IDL.Nat64),
+    'route_id' : IDL.Nat64,
+    'valid_until' : IDL.Nat64,
+    'valid_from' : IDL.Nat64,
+  });
+  const Timetable = IDL.Record({
+    'id' : IDL.Nat64,
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'exceptions' : IDL.Vec(IDL.Nat64),
+    'bus_ids' : IDL.Vec(IDL.Nat64),
+    'days_of_week' : IDL.Vec(Weekday),
+    'departure_times' : IDL.Vec(IDL.Nat64),
+    'route_id' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'valid_until' : IDL.Nat64,
+    'valid_from' : IDL.Nat64,
+  });
+  const Result_8 = IDL.Variant({ 'Ok' : Timetable, 'Err' : Error });
+  const TripPayload = IDL.Record({
+    'destination' : IDL.Text,
+    'origin' : IDL.Text,
+    'route_id' : IDL.Opt(IDL.Nat64),
+    'departure_time' : IDL.Nat64,
+    'arrival_time' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+  });
+  const TripStatus = IDL.Variant({
+    'Scheduled' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'InProgress' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const Trip = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : TripStatus,
+    'timetable_id' : IDL.Opt(IDL.Nat64),
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'destination' : IDL.Text,
+    'origin' : IDL.Text,
+    'route_id' : IDL.Opt(IDL.Nat64),
+    'departure_time' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'actual_departure_time' : IDL.Opt(IDL.Nat64),
+    'driver_id' : IDL.Opt(IDL.Nat64),
+    'actual_arrival_time' : IDL.Opt(IDL.Nat64),
+    'delay_minutes' : IDL.Nat32,
+    'arrival_time' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : Trip, 'Err' : Error });
+  const BlockReason = IDL.Variant({
+    'Trip' : IDL.Null,
+    'Maintenance' : IDL.Null,
+    'Charter' : IDL.Null,
+  });
+  const BlockedInterval = IDL.Record({
+    'end' : IDL.Nat64,
+    'reference_id' : IDL.Nat64,
+    'start' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+    'reason' : BlockReason,
+  });
+  const Result_10 = IDL.Variant({ 'Ok' : BlockedInterval, 'Err' : Error });
+  const MaintenanceStatus = IDL.Variant({
+    'Scheduled' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const ServiceType = IDL.Variant({
+    'OilChange' : IDL.Null,
+    'Inspection' : IDL.Null,
+    'Repair' : IDL.Null,
+    'Brakes' : IDL.Null,
+    'Tyres' : IDL.Null,
+    'Other' : IDL.Text,
+  });
+  const MaintenanceRecord = IDL.Record({
+    'id' : IDL.Nat64,
+    'scheduled_start' : IDL.Nat64,
+    'status' : MaintenanceStatus,
+    'odometer_km' : IDL.Opt(IDL.Nat64),
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'next_due_at' : IDL.Opt(IDL.Nat64),
+    'service_type' : ServiceType,
+    'cost' : IDL.Opt(IDL.Nat64),
+    'scheduled_end' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'notes' : IDL.Text,
+    'completed_at' : IDL.Opt(IDL.Nat64),
+    'bus_id' : IDL.Nat64,
+    'next_due_odometer_km' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_11 = IDL.Variant({ 'Ok' : MaintenanceRecord, 'Err' : Error });
+  const ReservationStatus = IDL.Variant({
+    'Refunded' : IDL.Null,
+    'Confirmed' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const FareCategory = IDL.Variant({
+    'Student' : IDL.Null,
+    'Senior' : IDL.Null,
+    'Adult' : IDL.Null,
+    'Child' : IDL.Null,
+  });
+  const Passenger = IDL.Record({
+    'id' : IDL.Nat32,
+    'cancelled_at' : IDL.Opt(IDL.Nat64),
+    'fare' : IDL.Nat64,
+    'name' : IDL.Text,
+    'seat_class' : SeatClass,
+    'seat_number' : IDL.Nat32,
+    'category' : FareCategory,
+  });
   const Reservation = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : ReservationStatus,
     'reservation_time' : IDL.Nat64,
+    'from_stop' : IDL.Opt(IDL.Nat64),
+    'trip_id' : IDL.Opt(IDL.Nat64),
+    'to_stop' : IDL.Opt(IDL.Nat64),
+    'fare' : IDL.Nat64,
+    'passengers' : IDL.Vec(Passenger),
+    'customer_id' : IDL.Nat64,
+    'discount' : IDL.Nat64,
+    'points_redeemed' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+    'promo_code' : IDL.Opt(IDL.Text),
+  });
+  const Result_12 = IDL.Variant({ 'Ok' : Reservation, 'Err' : Error });
+  const RebookingOutcome = IDL.Variant({
+    'Rebooked' : IDL.Record({
+      'reservation_id' : IDL.Nat64,
+      'trip_id' : IDL.Nat64,
+      'departure_time' : IDL.Nat64,
+      'seat_number' : IDL.Nat32,
+    }),
+    'Refunded' : IDL.Record({ 'amount' : IDL.Nat64 }),
+  });
+  const PassengerRebooking = IDL.Record({
+    'reservation_id' : IDL.Nat64,
+    'name' : IDL.Text,
+    'passenger_id' : IDL.Nat32,
+    'outcome' : RebookingOutcome,
+  });
+  const TripCancellationReport = IDL.Record({
+    'trip' : Trip,
+    'passengers' : IDL.Vec(PassengerRebooking),
+  });
+  const Result_13 = IDL.Variant({
+    'Ok' : TripCancellationReport,
+    'Err' : Error,
+  });
+  const MaintenanceCompletionPayload = IDL.Record({
+    'odometer_km' : IDL.Nat64,
+    'next_due_at' : IDL.Opt(IDL.Nat64),
+    'cost' : IDL.Nat64,
+    'notes' : IDL.Text,
+    'next_due_odometer_km' : IDL.Opt(IDL.Nat64),
+  });
+  const Discount = IDL.Variant({
+    'Fixed' : IDL.Nat64,
+    'Percentage' : IDL.Nat8,
+  });
+  const PromoCodePayload = IDL.Record({
+    'travel_until' : IDL.Opt(IDL.Nat64),
+    'max_uses' : IDL.Opt(IDL.Nat32),
+    'bus_ids' : IDL.Vec(IDL.Nat64),
+    'code' : IDL.Text,
+    'max_uses_per_customer' : IDL.Opt(IDL.Nat32),
+    'route_ids' : IDL.Vec(IDL.Nat64),
+    'discount' : Discount,
+    'valid_from' : IDL.Nat64,
+    'budget' : IDL.Opt(IDL.Nat64),
+    'expires_at' : IDL.Nat64,
+    'travel_from' : IDL.Opt(IDL.Nat64),
+  });
+  const PromoCode = IDL.Record({
+    'travel_until' : IDL.Opt(IDL.Nat64),
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'max_uses' : IDL.Opt(IDL.Nat32),
+    'bus_ids' : IDL.Vec(IDL.Nat64),
+    'code' : IDL.Text,
+    'max_uses_per_customer' : IDL.Opt(IDL.Nat32),
+    'uses' : IDL.Nat32,
+    'created_at' : IDL.Nat64,
+    'route_ids' : IDL.Vec(IDL.Nat64),
+    'discount_granted' : IDL.Nat64,
+    'discount' : Discount,
+    'valid_from' : IDL.Nat64,
+    'is_active' : IDL.Bool,
+    'budget' : IDL.Opt(IDL.Nat64),
+    'expires_at' : IDL.Nat64,
+    'travel_from' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_14 = IDL.Variant({ 'Ok' : PromoCode, 'Err' : Error });
+  const ReportToken = IDL.Record({
+    'id' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'label' : IDL.Text,
+    'token_hash' : IDL.Text,
+  });
+  const NewReportToken = IDL.Record({
+    'token' : IDL.Text,
+    'report_token' : ReportToken,
+  });
+  const Result_15 = IDL.Variant({ 'Ok' : NewReportToken, 'Err' : Error });
+  const Result_16 = IDL.Variant({ 'Ok' : Customer, 'Err' : Error });
+  const Result_17 = IDL.Variant({ 'Ok' : IDL.Vec(Bus), 'Err' : Error });
+  const GtfsImportOptions = IDL.Record({ 'bus_ids' : IDL.Vec(IDL.Nat64) });
+  const GtfsFile = IDL.Variant({
+    'Routes' : IDL.Null,
+    'Stops' : IDL.Null,
+    'CalendarDates' : IDL.Null,
+    'Calendar' : IDL.Null,
+    'Trips' : IDL.Null,
+    'StopTimes' : IDL.Null,
+  });
+  const GtfsLineError = IDL.Record({
+    'file' : GtfsFile,
+    'line' : IDL.Nat32,
+    'message' : IDL.Text,
+  });
+  const GtfsImportReport = IDL.Record({
+    'stops_created' : IDL.Nat32,
+    'timetables_updated' : IDL.Nat32,
+    'routes_created' : IDL.Nat32,
+    'errors' : IDL.Vec(GtfsLineError),
+    'import_id' : IDL.Nat64,
+    'finished' : IDL.Bool,
+    'errors_truncated' : IDL.Bool,
+    'timetables_created' : IDL.Nat32,
+    'stops_updated' : IDL.Nat32,
+    'routes_updated' : IDL.Nat32,
+  });
+  const Result_18 = IDL.Variant({ 'Ok' : GtfsImportReport, 'Err' : Error });
+  const Result_19 = IDL.Variant({
+    'Ok' : IDL.Vec(BlockedInterval),
+    'Err' : Error,
+  });
+  const ComplianceAlert = IDL.Record({
+    'expired' : IDL.Bool,
+    'document' : ComplianceDocument,
+    'bus_id' : IDL.Nat64,
+  });
+  const ComplianceReport = IDL.Record({
+    'generated_at' : IDL.Nat64,
+    'alerts' : IDL.Vec(ComplianceAlert),
+  });
+  const Result_20 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : Error });
+  const Result_21 = IDL.Variant({ 'Ok' : IDL.Vec(Charter), 'Err' : Error });
+  const DrivingRules = IDL.Record({
+    'max_shift_gap' : IDL.Nat64,
+    'max_driving_per_24h' : IDL.Nat64,
+    'min_rest_between_shifts' : IDL.Nat64,
+  });
+  const GtfsAgency = IDL.Record({
+    'url' : IDL.Text,
+    'timezone' : IDL.Text,
+    'name' : IDL.Text,
+  });
+  const VehiclePosition = IDL.Record({
+    'latitude' : IDL.Float64,
+    'trip_id' : IDL.Nat64,
+    'heading' : IDL.Opt(IDL.Float64),
+    'speed_mps' : IDL.Opt(IDL.Float64),
+    'recorded_at' : IDL.Nat64,
+    'longitude' : IDL.Float64,
+    'sequence' : IDL.Nat64,
+  });
+  const Result_22 = IDL.Variant({ 'Ok' : VehiclePosition, 'Err' : Error });
+  const LoyaltyAccount = IDL.Record({
+    'lifetime_earned' : IDL.Nat64,
+    'updated_at' : IDL.Opt(IDL.Nat64),
+    'balance' : IDL.Nat64,
+    'lifetime_expired' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'customer_id' : IDL.Nat64,
+    'lifetime_redeemed' : IDL.Nat64,
+  });
+  const Result_23 = IDL.Variant({ 'Ok' : LoyaltyAccount, 'Err' : Error });
+  const LoyaltyEntryKind = IDL.Variant({
+    'Refund' : IDL.Null,
+    'Accrual' : IDL.Null,
+    'Redemption' : IDL.Null,
+    'Expiry' : IDL.Null,
+  });
+  const LoyaltyEntry = IDL.Record({
+    'id' : IDL.Nat64,
+    'reservation_id' : IDL.Opt(IDL.Nat64),
+    'kind' : LoyaltyEntryKind,
+    'created_at' : IDL.Nat64,
+    'customer_id' : IDL.Nat64,
+    'remaining' : IDL.Nat64,
+    'expires_at' : IDL.Opt(IDL.Nat64),
+    'points' : IDL.Nat64,
+  });
+  const LoyaltyStatement = IDL.Record({
+    'entries' : IDL.Vec(LoyaltyEntry),
+    'account' : LoyaltyAccount,
+  });
+  const Result_24 = IDL.Variant({ 'Ok' : LoyaltyStatement, 'Err' : Error });
+  const Result_25 = IDL.Variant({ 'Ok' : IDL.Vec(Trip), 'Err' : Error });
+  const OverdueBus = IDL.Record({
+    'bus' : Bus,
+    'overdue_by_date' : IDL.Bool,
+    'overdue_by_mileage' : IDL.Bool,
+    'last_service' : MaintenanceRecord,
+  });
+  const SeatMapEntry = IDL.Record({
+    'seat' : SeatTemplate,
+    'is_occupied' : IDL.Bool,
+  });
+  const Result_26 = IDL.Variant({
+    'Ok' : IDL.Vec(SeatMapEntry),
+    'Err' : Error,
+  });
+  const ClassAvailability = IDL.Record({
+    'fare' : IDL.Nat64,
+    'seat_class' : SeatClass,
+    'seats_available' : IDL.Nat32,
+  });
+  const SegmentAvailability = IDL.Record({
+    'from_stop' : IDL.Nat64,
+    'trip_id' : IDL.Nat64,
+    'to_stop' : IDL.Nat64,
+    'classes' : IDL.Vec(ClassAvailability),
+    'departure_time' : IDL.Nat64,
+    'arrival_time' : IDL.Nat64,
+  });
+  const Result_27 = IDL.Variant({ 'Ok' : SegmentAvailability, 'Err' : Error });
+  const SkippedDeparture = IDL.Record({
+    'timetable_id' : IDL.Nat64,
+    'departure_time' : IDL.Nat64,
+    'reason' : IDL.Text,
+  });
+  const TimetableReport = IDL.Record({
+    'generated_at' : IDL.Nat64,
+    'skipped' : IDL.Vec(SkippedDeparture),
+    'trips_created' : IDL.Nat32,
+  });
+  const TripEventKind = IDL.Variant({
+    'Arrived' : IDL.Null,
+    'Departed' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Delayed' : IDL.Null,
+  });
+  const TripEvent = IDL.Record({
+    'id' : IDL.Nat64,
+    'trip_id' : IDL.Nat64,
+    'stop_id' : IDL.Opt(IDL.Nat64),
+    'kind' : TripEventKind,
+    'recorded_at' : IDL.Nat64,
+    'recorded_by' : IDL.Principal,
+    'delay_minutes' : IDL.Opt(IDL.Nat32),
+    'reason' : IDL.Opt(IDL.Text),
+  });
+  const TripProgress = IDL.Record({
+    'trip' : Trip,
+    'departure_delay_minutes' : IDL.Opt(IDL.Nat64),
+    'events' : IDL.Vec(TripEvent),
+  });
+  const Result_28 = IDL.Variant({ 'Ok' : TripProgress, 'Err' : Error });
+  const VehicleEta = IDL.Record({
+    'estimated_arrival' : IDL.Nat64,
+    'stop_id' : IDL.Nat64,
+    'speed_mps' : IDL.Opt(IDL.Float64),
+    'position' : VehiclePosition,
+    'distance_remaining_m' : IDL.Nat64,
+  });
+  const Result_29 = IDL.Variant({ 'Ok' : VehicleEta, 'Err' : Error });
+  const HttpRequest = IDL.Record({
+    'url' : IDL.Text,
+    'method' : IDL.Text,
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+  });
+  const StreamingCallbackToken = IDL.Record({
+    'key' : IDL.Vec(IDL.Nat64),
+    'report' : IDL.Text,
+    'query' : IDL.Text,
+  });
+  const StreamingCallbackHttpResponse = IDL.Record({
+    'token' : IDL.Opt(StreamingCallbackToken),
+    'body' : IDL.Vec(IDL.Nat8),
+  });
+  const StreamingStrategy = IDL.Variant({
+    'Callback' : IDL.Record({
+      'token' : StreamingCallbackToken,
+      'callback' : IDL.Func(
+          [StreamingCallbackToken],
+          [StreamingCallbackHttpResponse],
+          ['query'],
+        ),
+    }),
+  });
+  const HttpResponse = IDL.Record({
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'upgrade' : IDL.Opt(IDL.Bool),
+    'streaming_strategy' : IDL.Opt(StreamingStrategy),
+    'status_code' : IDL.Nat16,
+  });
+  const Result_30 = IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : Error });
+  const Result_31 = IDL.Variant({ 'Ok' : IDL.Vec(PromoCode), 'Err' : Error });
+  const Result_32 = IDL.Variant({ 'Ok' : IDL.Vec(ReportToken), 'Err' : Error });
+  const PassengerPayload = IDL.Record({
+    'name' : IDL.Text,
+    'seat_class' : IDL.Opt(SeatClass),
+    'category' : FareCategory,
+  });
+  const GroupReservationPayload = IDL.Record({
+    'from_stop' : IDL.Opt(IDL.Nat64),
+    'trip_id' : IDL.Opt(IDL.Nat64),
+    'redeem_points' : IDL.Opt(IDL.Nat64),
+    'to_stop' : IDL.Opt(IDL.Nat64),
+    'passengers' : IDL.Vec(PassengerPayload),
     'customer_id' : IDL.Nat64,
     'bus_id' : IDL.Nat64,
+    'promo_code' : IDL.Opt(IDL.Text),
+  });
+  const VehiclePositionPayload = IDL.Record({
+    'latitude' : IDL.Float64,
+    'heading' : IDL.Opt(IDL.Float64),
+    'speed_mps' : IDL.Opt(IDL.Float64),
+    'longitude' : IDL.Float64,
+  });
+  const CharterQuotePayload = IDL.Record({
+    'valid_until' : IDL.Nat64,
+    'notes' : IDL.Text,
+    'price' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+  });
+  const Result_33 = IDL.Variant({ 'Ok' : ComplianceReport, 'Err' : Error });
+  const CharterRequestPayload = IDL.Record({
+    'end_time' : IDL.Nat64,
+    'customer_id' : IDL.Nat64,
+    'pickup' : IDL.Text,
+    'passenger_count' : IDL.Nat32,
+    'start_time' : IDL.Nat64,
+    'drop_off' : IDL.Text,
+  });
+  const Result_34 = IDL.Variant({ 'Ok' : ReportToken, 'Err' : Error });
+  const Result_35 = IDL.Variant({ 'Ok' : TimetableReport, 'Err' : Error });
+  const MaintenancePayload = IDL.Record({
+    'scheduled_start' : IDL.Nat64,
+    'service_type' : ServiceType,
+    'scheduled_end' : IDL.Nat64,
+    'notes' : IDL.Text,
+    'bus_id' : IDL.Nat64,
+  });
+  const JourneyLeg = IDL.Record({
+    'from_stop' : IDL.Nat64,
+    'trip_id' : IDL.Nat64,
+    'to_stop' : IDL.Nat64,
+    'fare' : IDL.Nat64,
+    'route_id' : IDL.Nat64,
+    'departure_time' : IDL.Nat64,
+    'seats_available' : IDL.Nat32,
+    'arrival_time' : IDL.Nat64,
+    'bus_id' : IDL.Nat64,
+  });
+  const Itinerary = IDL.Record({
+    'fare' : IDL.Nat64,
+    'transfers' : IDL.Nat8,
+    'legs' : IDL.Vec(JourneyLeg),
+    'departure_time' : IDL.Nat64,
+    'seats_available' : IDL.Nat32,
+    'arrival_time' : IDL.Nat64,
+  });
+  const Result_36 = IDL.Variant({ 'Ok' : IDL.Vec(Itinerary), 'Err' : Error });
+  const TripSearchPayload = IDL.Record({
+    'departs_after' : IDL.Nat64,
+    'destination' : IDL.Opt(IDL.Text),
+    'departs_before' : IDL.Nat64,
+    'origin' : IDL.Opt(IDL.Text),
+    'seat_class' : IDL.Opt(SeatClass),
+    'required_amenities' : IDL.Vec(Amenity),
+  });
+  const TripSearchResult = IDL.Record({
+    'fare' : IDL.Nat64,
+    'trip' : Trip,
+    'seat_class' : SeatClass,
+    'amenities' : IDL.Vec(Amenity),
+    'seats_available' : IDL.Nat32,
+  });
+  const Result_37 = IDL.Variant({ 'Ok' : DrivingRules, 'Err' : Error });
+  const Result_38 = IDL.Variant({ 'Ok' : GtfsAgency, 'Err' : Error });
+  const GtfsImport = IDL.Record({
+    'id' : IDL.Nat64,
+    'bytes' : IDL.Nat64,
+    'chunks' : IDL.Nat64,
+    'started_at' : IDL.Nat64,
+    'started_by' : IDL.Principal,
   });
-  const Result_3 = IDL.Variant({ 'Ok' : Reservation, 'Err' : Error });
-  const Result_4 = IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : Error });
+  const Result_39 = IDL.Variant({ 'Ok' : GtfsImport, 'Err' : Error });
   return IDL.Service({
-    'add_bus' : IDL.Func([BusPayload], [IDL.Opt(Bus)], []),
+    'abort_gtfs_import' : IDL.Func([IDL.Nat64], [Result], []),
+    'accept_charter_quote' : IDL.Func([IDL.Nat64], [Result_1], []),
+    'add_bus' : IDL.Func([BusPayload], [Result_2], []),
+    'add_bus_model' : IDL.Func([BusModelPayload], [Result_3], []),
+    'add_compliance_document' : IDL.Func(
+        [IDL.Nat64, ComplianceDocumentPayload],
+        [Result_4],
+        [],
+      ),
     'add_customer' : IDL.Func([IDL.Text, IDL.Text], [IDL.Opt(Customer)], []),
+    'add_driver' : IDL.Func([DriverPayload], [Result_5], []),
+    'add_route' : IDL.Func([RoutePayload], [Result_6], []),
+    'add_stop' : IDL.Func([StopPayload], [Result_7], []),
+    'add_timetable' : IDL.Func([TimetablePayload], [Result_8], []),
+    'add_trip' : IDL.Func([TripPayload], [Result_9], []),
+    'assign_driver' : IDL.Func([IDL.Nat64, IDL.Nat64], [Result_9], []),
+    'block_bus' : IDL.Func([IDL.Nat64, IDL.Nat64, IDL.Nat64], [Result_10], []),
+    'cancel_charter' : IDL.Func([IDL.Nat64], [Result_1], []),
+    'cancel_maintenance' : IDL.Func([IDL.Nat64], [Result_11], []),
+    'cancel_passenger' : IDL.Func([IDL.Nat64, IDL.Nat32], [Result_12], []),
     'cancel_reservation' : IDL.Func([IDL.Nat64], [Result], []),
-    'delete_bus' : IDL.Func([IDL.Nat64], [Result_1], []),
-    'delete_customer' : IDL.Func([IDL.Nat64], [Result_2], []),
+    'cancel_trip' : IDL.Func([IDL.Nat64, IDL.Text], [Result_13], []),
+    'complete_maintenance' : IDL.Func(
+        [IDL.Nat64, MaintenanceCompletionPayload],
+        [Result_11],
+        [],
+      ),
+    'complete_reservation' : IDL.Func([IDL.Nat64], [Result_12], []),
+    'create_promo_code' : IDL.Func([PromoCodePayload], [Result_14], []),
+    'create_report_token' : IDL.Func([IDL.Text], [Result_15], []),
+    'deactivate_promo_code' : IDL.Func([IDL.Text], [Result_14], []),
+    'decline_charter_quote' : IDL.Func([IDL.Nat64], [Result_1], []),
+    'delete_bus' : IDL.Func([IDL.Nat64], [Result_2], []),
+    'delete_bus_model' : IDL.Func([IDL.Nat64], [Result_3], []),
+    'delete_customer' : IDL.Func([IDL.Nat64], [Result_16], []),
+    'delete_timetable' : IDL.Func([IDL.Nat64], [Result_8], []),
+    'find_available_buses' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64, IDL.Nat32],
+        [Result_17],
+        ['query'],
+      ),
+    'finish_gtfs_import' : IDL.Func(
+        [IDL.Nat64, GtfsImportOptions],
+        [Result_18],
+        [],
+      ),
     'generate_report' : IDL.Func([], [IDL.Vec(Bus)], ['query']),
-    'get_bus' : IDL.Func([IDL.Nat64], [Result_1], ['query']),
-    'get_customer' : IDL.Func([IDL.Nat64], [Result_2], ['query']),
-    'get_reservation' : IDL.Func([IDL.Nat64], [Result_3], ['query']),
-    'is_booked' : IDL.Func([IDL.Nat64], [Result_4], ['query']),
-    'make_reservation' : IDL.Func([IDL.Nat64, IDL.Nat64], [Result_3], []),
-    'update_bus' : IDL.Func([IDL.Nat64, BusPayload], [Result_1], []),
+    'get_bus' : IDL.Func([IDL.Nat64], [Result_2], ['query']),
+    'get_bus_by_plate' : IDL.Func([IDL.Text], [Result_2], ['query']),
+    'get_bus_by_vin' : IDL.Func([IDL.Text], [Result_2], ['query']),
+    'get_bus_calendar' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64, IDL.Nat64],
+        [Result_19],
+        ['query'],
+      ),
+    'get_bus_maintenance' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Vec(MaintenanceRecord)],
+        ['query'],
+      ),
+    'get_bus_model' : IDL.Func([IDL.Nat64], [Result_3], ['query']),
+    'get_charter' : IDL.Func([IDL.Nat64], [Result_1], ['query']),
+    'get_compliance_alerts' : IDL.Func([], [ComplianceReport], ['query']),
+    'get_compliance_documents' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Vec(ComplianceDocument)],
+        ['query'],
+      ),
+    'get_customer' : IDL.Func([IDL.Nat64], [Result_16], ['query']),
+    'get_customer_calendar' : IDL.Func([IDL.Nat64], [Result_20], ['query']),
+    'get_customer_calendar_link' : IDL.Func(
+        [IDL.Nat64],
+        [Result_20],
+        ['query'],
+      ),
+    'get_customer_charters' : IDL.Func([IDL.Nat64], [Result_21], ['query']),
+    'get_driver' : IDL.Func([IDL.Nat64], [Result_5], ['query']),
+    'get_driving_rules' : IDL.Func([], [DrivingRules], ['query']),
+    'get_gtfs_agency' : IDL.Func([], [GtfsAgency], ['query']),
+    'get_gtfs_import_report' : IDL.Func([IDL.Nat64], [Result_18], ['query']),
+    'get_latest_position' : IDL.Func([IDL.Nat64], [Result_22], ['query']),
+    'get_loyalty_account' : IDL.Func([IDL.Nat64], [Result_23], ['query']),
+    'get_loyalty_statement' : IDL.Func([IDL.Nat64], [Result_24], ['query']),
+    'get_my_assignments' : IDL.Func([], [Result_25], ['query']),
+    'get_overdue_buses' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Vec(OverdueBus)],
+        ['query'],
+      ),
+    'get_promo_code' : IDL.Func([IDL.Text], [Result_14], ['query']),
+    'get_reservation' : IDL.Func([IDL.Nat64], [Result_12], ['query']),
+    'get_reservation_calendar' : IDL.Func([IDL.Nat64], [Result_20], ['query']),
+    'get_reservation_calendar_link' : IDL.Func(
+        [IDL.Nat64],
+        [Result_20],
+        ['query'],
+      ),
+    'get_route' : IDL.Func([IDL.Nat64], [Result_6], ['query']),
+    'get_seat_map' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Nat64)],
+        [Result_26],
+        ['query'],
+      ),
+    'get_segment_availability' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64, IDL.Nat64],
+        [Result_27],
+        ['query'],
+      ),
+    'get_stop' : IDL.Func([IDL.Nat64], [Result_7], ['query']),
+    'get_timetable' : IDL.Func([IDL.Nat64], [Result_8], ['query']),
+    'get_timetable_report' : IDL.Func([], [TimetableReport], ['query']),
+    'get_trip' : IDL.Func([IDL.Nat64], [Result_9], ['query']),
+    'get_trip_progress' : IDL.Func([IDL.Nat64], [Result_28], ['query']),
+    'get_vehicle_eta' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64],
+        [Result_29],
+        ['query'],
+      ),
+    'http_request' : IDL.Func([HttpRequest], [HttpResponse], ['query']),
+    'http_request_streaming_callback' : IDL.Func(
+        [StreamingCallbackToken],
+        [StreamingCallbackHttpResponse],
+        ['query'],
+      ),
+    'is_booked' : IDL.Func([IDL.Nat64], [Result_30], ['query']),
+    'list_bus_models' : IDL.Func([], [IDL.Vec(BusModel)], ['query']),
+    'list_promo_codes' : IDL.Func([], [Result_31], ['query']),
+    'list_report_tokens' : IDL.Func([], [Result_32], ['query']),
+    'list_routes' : IDL.Func([], [IDL.Vec(Route)], ['query']),
+    'list_stops' : IDL.Func([], [IDL.Vec(Stop)], ['query']),
+    'list_timetables' : IDL.Func([], [IDL.Vec(Timetable)], ['query']),
+    'make_group_reservation' : IDL.Func(
+        [GroupReservationPayload],
+        [Result_12],
+        [],
+      ),
+    'make_reservation' : IDL.Func(
+        [
+          IDL.Nat64,
+          IDL.Nat64,
+          IDL.Opt(IDL.Nat64),
+          IDL.Opt(IDL.Nat64),
+          IDL.Opt(IDL.Nat64),
+          IDL.Opt(IDL.Text),
+          IDL.Opt(IDL.Nat64),
+        ],
+        [Result_12],
+        [],
+      ),
+    'push_vehicle_position' : IDL.Func(
+        [IDL.Nat64, VehiclePositionPayload],
+        [Result_22],
+        [],
+      ),
+    'quote_charter' : IDL.Func(
+        [IDL.Nat64, CharterQuotePayload],
+        [Result_1],
+        [],
+      ),
+    'record_arrival' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Nat64)],
+        [Result_9],
+        [],
+      ),
+    'record_departure' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Nat64)],
+        [Result_9],
+        [],
+      ),
+    'record_odometer' : IDL.Func([IDL.Nat64, IDL.Nat64], [Result_2], []),
+    'refresh_compliance_alerts' : IDL.Func([], [Result_33], []),
+    'report_delay' : IDL.Func([IDL.Nat64, IDL.Nat32, IDL.Text], [Result_9], []),
+    'request_charter' : IDL.Func([CharterRequestPayload], [Result_1], []),
+    'revoke_report_token' : IDL.Func([IDL.Nat64], [Result_34], []),
+    'run_timetables' : IDL.Func([], [Result_35], []),
+    'schedule_maintenance' : IDL.Func([MaintenancePayload], [Result_11], []),
+    'search_journeys' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64, IDL.Nat64, IDL.Nat8],
+        [Result_36],
+        ['query'],
+      ),
+    'search_trips' : IDL.Func(
+        [TripSearchPayload],
+        [IDL.Vec(TripSearchResult)],
+        ['query'],
+      ),
+    'set_driving_rules' : IDL.Func([DrivingRules], [Result_37], []),
+    'set_gtfs_agency' : IDL.Func([GtfsAgency], [Result_38], []),
+    'start_gtfs_import' : IDL.Func([], [Result_39], []),
+    'unassign_driver' : IDL.Func([IDL.Nat64], [Result_9], []),
+    'unblock_bus' : IDL.Func([IDL.Nat64, IDL.Nat64], [Result_10], []),
+    'update_bus' : IDL.Func([IDL.Nat64, BusPayload], [Result_2], []),
+    'update_driver' : IDL.Func([IDL.Nat64, DriverPayload], [Result_5], []),
+    'update_timetable' : IDL.Func(
+        [IDL.Nat64, TimetablePayload],
+        [Result_8],
+        [],
+      ),
+    'upload_gtfs_chunk' : IDL.Func(
+        [IDL.Nat64, GtfsFile, IDL.Vec(IDL.Nat8)],
+        [Result_39],
+        [],
+      ),
   });
 };
 export const init = ({ IDL }) => { return []; };
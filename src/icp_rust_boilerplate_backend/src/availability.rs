@@ -0,0 +1,118 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) enum BlockReason {
+    Trip,
+    Charter,
+    Maintenance,
+}
+
+// A period in which a bus can't take other work. Blocks on one bus never overlap.
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct BlockedInterval {
+    pub(crate) bus_id: u64,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) reason: BlockReason,
+    pub(crate) reference_id: u64, // trip, charter or maintenance id depending on `reason`
+}
+
+impl Storable for BlockedInterval {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for BlockedInterval {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Rejects the window if it overlaps anything already in the bus' calendar
+pub(crate) fn ensure_bus_available(bus_id: u64, start: u64, end: u64) -> Result<(), Error> {
+    match _find_overlapping_block(bus_id, start, end) {
+        Some(block) => {
+            let what = match block.reason {
+                BlockReason::Trip => "assigned to trip",
+                BlockReason::Charter => "chartered under charter",
+                BlockReason::Maintenance => "booked for maintenance record",
+            };
+            Err(Error::Conflict {
+                msg: format!(
+                    "bus with id={} is {} id={} in that window",
+                    bus_id, what, block.reference_id
+                ),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+// Blocks on a bus don't overlap, so ordered by start they are also ordered by end. The only
+// block that can overlap [start, end) is therefore the last one starting before `end`.
+pub(crate) fn _find_overlapping_block(bus_id: u64, start: u64, end: u64) -> Option<BlockedInterval> {
+    BUS_CALENDAR.with(|service| {
+        service
+            .borrow()
+            .iter_upper_bound(&(bus_id, end))
+            .next()
+            .map(|(_, block)| block)
+            .filter(|block| block.bus_id == bus_id && block.end > start)
+    })
+}
+
+// Callers must have checked the window with `ensure_bus_available`
+pub(crate) fn do_block_bus(block: BlockedInterval) {
+    BUS_CALENDAR.with(|service| {
+        service
+            .borrow_mut()
+            .insert((block.bus_id, block.start), block)
+    });
+}
+
+pub(crate) fn do_unblock_bus(bus_id: u64, start: u64) -> Option<BlockedInterval> {
+    BUS_CALENDAR.with(|service| service.borrow_mut().remove(&(bus_id, start)))
+}
+
+#[ic_cdk::query]
+fn get_bus_calendar(bus_id: u64, from: u64, to: u64) -> Result<Vec<BlockedInterval>, Error> {
+    if _get_bus(&bus_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", bus_id),
+        });
+    }
+    // Start from the block running at `from`, if any, then walk forward until `to`
+    let first_start = _find_overlapping_block(bus_id, from, from.saturating_add(1))
+        .map_or(from, |block| block.start);
+    Ok(BUS_CALENDAR.with(|service| {
+        service
+            .borrow()
+            .range((bus_id, first_start)..(bus_id, to))
+            .map(|(_, block)| block)
+            .collect()
+    }))
+}
+
+#[ic_cdk::query]
+fn find_available_buses(start: u64, end: u64, min_capacity: u32) -> Result<Vec<Bus>, Error> {
+    if end <= start {
+        return Err(Error::InvalidInput {
+            msg: "the requested window must end after it starts".to_string(),
+        });
+    }
+    Ok(BUS_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, bus)| bus)
+            .filter(|bus| {
+                bus.capacity >= min_capacity
+                    && _find_overlapping_block(bus.id, start, end).is_none()
+            })
+            .collect()
+    }))
+}
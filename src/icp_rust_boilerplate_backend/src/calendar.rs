@@ -0,0 +1,282 @@
+use super::*;
+use sha2::{Digest, Sha256};
+
+#[ic_cdk::query]
+fn get_reservation_calendar(id: u64) -> Result<String, Error> {
+    let reservation = get_reservation(id)?;
+    ensure_customer_caller(reservation.customer_id)?;
+    build_calendar(&[reservation], time())
+}
+
+#[ic_cdk::query]
+fn get_customer_calendar(customer_id: u64) -> Result<String, Error> {
+    ensure_customer_caller(customer_id)?;
+    _get_customer_calendar(customer_id, time())
+}
+
+// Paths calendar apps can subscribe to without signing in, for the customer to hand out
+#[ic_cdk::query]
+fn get_reservation_calendar_link(id: u64) -> Result<String, Error> {
+    let reservation = get_reservation(id)?;
+    ensure_customer_caller(reservation.customer_id)?;
+    Ok(format!(
+        "/api/reservations/{}/calendar.ics?token={}",
+        id,
+        calendar_token("reservation", id)?
+    ))
+}
+
+#[ic_cdk::query]
+fn get_customer_calendar_link(customer_id: u64) -> Result<String, Error> {
+    get_customer(customer_id)?;
+    ensure_customer_caller(customer_id)?;
+    Ok(format!(
+        "/api/customers/{}/calendar.ics?token={}",
+        customer_id,
+        calendar_token("customer", customer_id)?
+    ))
+}
+
+// A keyed hash of what the link is for, so links can't be guessed from ids
+fn calendar_token(kind: &str, id: u64) -> Result<String, Error> {
+    let secret = CALENDAR_SECRET.with(|cell| cell.borrow().get().clone());
+    if secret.is_empty() {
+        return Err(Error::NotFound {
+            msg: "calendar links aren't available yet, try again shortly".to_string(),
+        });
+    }
+    let digest = Sha256::new()
+        .chain_update(&secret)
+        .chain_update(format!("{}:{}", kind, id))
+        .finalize();
+    Ok(hex(&digest))
+}
+
+pub(crate) fn ensure_calendar_token(kind: &str, id: u64, token: &str) -> Result<(), Error> {
+    let expected = calendar_token(kind, id)?;
+    if constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: "the calendar link is not valid".to_string(),
+        })
+    }
+}
+
+// Draws the calendar secret unless there already is one, trying again later if it can't
+pub(crate) async fn do_draw_calendar_secret() {
+    if !CALENDAR_SECRET.with(|cell| cell.borrow().get().is_empty()) {
+        return;
+    }
+    match ic_cdk::api::management_canister::main::raw_rand().await {
+        Ok((bytes,)) => {
+            CALENDAR_SECRET
+                .with(|cell| cell.borrow_mut().set(bytes))
+                .expect("cannot store the calendar secret");
+        }
+        Err(_) => {
+            ic_cdk_timers::set_timer(Duration::from_secs(60), || {
+                ic_cdk::spawn(do_draw_calendar_secret())
+            });
+        }
+    }
+}
+
+// Confirmed trip reservations the customer hasn't arrived from yet
+pub(crate) fn _get_customer_calendar(customer_id: u64, now: u64) -> Result<String, Error> {
+    let customer = get_customer(customer_id)?;
+    let reservations: Vec<Reservation> = _get_customer_reservations(customer.id)
+        .into_iter()
+        .filter(|reservation| {
+            reservation.status == ReservationStatus::Confirmed
+                && _get_booked_journey(reservation).is_ok_and(|journey| journey.arrival_time > now)
+        })
+        .collect();
+    build_calendar(&reservations, now)
+}
+
+pub(crate) fn calendar_response(name: &str, calendar: String) -> Result<HttpResponse, Error> {
+    let mut response = HttpResponse::ok("text/calendar; charset=utf-8", calendar.into_bytes());
+    response.headers.push((
+        "Content-Disposition".to_string(),
+        format!("attachment; filename=\"{}.ics\"", name),
+    ));
+    Ok(response)
+}
+
+// When and where the passengers of a trip reservation board and alight
+struct BookedJourney {
+    departure_time: u64,
+    arrival_time: u64,
+    origin: String,
+    destination: String,
+    boarding_position: Option<(f64, f64)>, // latitude and longitude, when the trip has a route
+}
+
+fn _get_booked_journey(reservation: &Reservation) -> Result<BookedJourney, Error> {
+    let trip_id = reservation.trip_id.ok_or_else(|| Error::InvalidInput {
+        msg: format!(
+            "reservation id={} is not for a scheduled trip",
+            reservation.id
+        ),
+    })?;
+    let trip = get_trip(trip_id)?;
+    let mut journey = BookedJourney {
+        departure_time: trip.departure_time,
+        arrival_time: trip.arrival_time,
+        origin: trip.origin.clone(),
+        destination: trip.destination.clone(),
+        boarding_position: None,
+    };
+    if let Some(route) = trip.route_id.and_then(|id| _get_route(&id)) {
+        let (from, to) = route_legs(&route, reservation.from_stop, reservation.to_stop)?;
+        journey.departure_time = trip.departure_time + route.stops[from].departure_offset;
+        journey.arrival_time = trip.departure_time + route.stops[to].arrival_offset;
+        if let Some(stop) = _get_stop(&route.stops[from].stop_id) {
+            journey.origin = stop.name;
+            journey.boarding_position = Some((stop.latitude, stop.longitude));
+        }
+        if let Some(stop) = _get_stop(&route.stops[to].stop_id) {
+            journey.destination = stop.name;
+        }
+    }
+    Ok(journey)
+}
+
+// An iCalendar (RFC 5545) file with one event per reservation. Times are written in UTC.
+pub(crate) fn build_calendar(reservations: &[Reservation], now: u64) -> Result<String, Error> {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//icp_rust_boilerplate_backend//Bus bookings//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+    ];
+    for reservation in reservations {
+        let journey = _get_booked_journey(reservation)?;
+        let seats: Vec<String> = reservation
+            .active_passengers()
+            .map(|passenger| format!("{} ({})", passenger.seat_number, passenger.name))
+            .collect();
+        let description = format!(
+            "Booking reference: {}\nSeats: {}\nDeparts {} at {}\nArrives {} at {}",
+            reservation.id,
+            seats.join(", "),
+            journey.origin,
+            format_ical_time(journey.departure_time),
+            journey.destination,
+            format_ical_time(journey.arrival_time),
+        );
+        let status = match reservation.status {
+            ReservationStatus::Refunded => "CANCELLED",
+            ReservationStatus::Confirmed | ReservationStatus::Completed => "CONFIRMED",
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:reservation-{}@{}", reservation.id, ic_cdk::id()),
+            format!("DTSTAMP:{}", format_ical_time(now)),
+            format!("DTSTART:{}", format_ical_time(journey.departure_time)),
+            format!("DTEND:{}", format_ical_time(journey.arrival_time)),
+            format!(
+                "SUMMARY:{}",
+                ical_text(&format!(
+                    "Bus {} to {}",
+                    journey.origin, journey.destination
+                ))
+            ),
+            format!("LOCATION:{}", ical_text(&journey.origin)),
+            format!("DESCRIPTION:{}", ical_text(&description)),
+            format!("STATUS:{}", status),
+        ]);
+        if let Some((latitude, longitude)) = journey.boarding_position {
+            lines.push(format!("GEO:{:.6};{:.6}", latitude, longitude));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    Ok(lines.iter().map(|line| fold_ical_line(line)).collect())
+}
+
+// YYYYMMDDTHHMMSSZ
+fn format_ical_time(time: u64) -> String {
+    let seconds = time / 1_000_000_000;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Escapes a TEXT value
+fn ical_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Content lines are at most 75 octets; longer ones continue on lines starting with a space.
+// Folds never split a UTF-8 character.
+fn fold_ical_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut width = 0;
+    for character in line.chars() {
+        if width + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(character);
+        width += character.len_utf8();
+    }
+    folded + "\r\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_ical_text() {
+        assert_eq!(ical_text("a\\b; c, d\r\ne\nf"), "a\\\\b\\; c\\, d\\ne\\nf");
+    }
+
+    #[test]
+    fn folds_ical_lines_at_75_octets() {
+        assert_eq!(fold_ical_line("SUMMARY:short"), "SUMMARY:short\r\n");
+
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let folded = fold_ical_line(&line);
+        assert!(folded.ends_with("\r\n"));
+        let parts: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| part.len() <= 75));
+        assert!(parts[1..].iter().all(|part| part.starts_with(' ')));
+        let unfolded: String = parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| if index == 0 { *part } else { &part[1..] })
+            .collect();
+        assert_eq!(unfolded, line);
+    }
+
+    #[test]
+    fn accepts_only_the_calendar_token_for_the_same_link() {
+        assert!(calendar_token("customer", 1).is_err());
+        CALENDAR_SECRET.with(|cell| cell.borrow_mut().set(vec![7; 32]).ok());
+
+        let token = calendar_token("customer", 1).ok().unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(ensure_calendar_token("customer", 1, &token).is_ok());
+        assert!(ensure_calendar_token("customer", 2, &token).is_err());
+        assert!(ensure_calendar_token("reservation", 1, &token).is_err());
+        assert!(ensure_calendar_token("customer", 1, &token[..63]).is_err());
+        assert!(ensure_calendar_token("customer", 1, "").is_err());
+    }
+}
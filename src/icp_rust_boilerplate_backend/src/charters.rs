@@ -0,0 +1,312 @@
+use super::*;
+
+// Free text on a charter, kept short enough that a quoted charter fits Charter::MAX_SIZE
+const MAX_CHARTER_PLACE_LEN: usize = 256;
+const MAX_CHARTER_NOTES_LEN: usize = 512;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum CharterStatus {
+    Requested,
+    Quoted,
+    Accepted,
+    Declined,
+    Cancelled,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct CharterQuote {
+    bus_id: u64,
+    price: u64,
+    notes: String,
+    quoted_at: u64,
+    valid_until: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Charter {
+    id: u64,
+    customer_id: u64,
+    start_time: u64,
+    end_time: u64,
+    pickup: String,
+    drop_off: String,
+    passenger_count: u32,
+    quote: Option<CharterQuote>,
+    status: CharterStatus,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for Charter {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Charter {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct CharterRequestPayload {
+    customer_id: u64,
+    start_time: u64,
+    end_time: u64,
+    pickup: String,
+    drop_off: String,
+    passenger_count: u32,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct CharterQuotePayload {
+    bus_id: u64,
+    price: u64,
+    notes: String,
+    valid_until: u64,
+}
+
+#[ic_cdk::update]
+fn request_charter(payload: CharterRequestPayload) -> Result<Charter, Error> {
+    if _get_customer(&payload.customer_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a customer with id={} not found", payload.customer_id),
+        });
+    }
+    ensure_customer_caller(payload.customer_id)?;
+    let places = [payload.pickup.trim(), payload.drop_off.trim()];
+    if places
+        .iter()
+        .any(|place| place.is_empty() || place.len() > MAX_CHARTER_PLACE_LEN)
+    {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "pickup and drop-off must be between 1 and {} bytes",
+                MAX_CHARTER_PLACE_LEN
+            ),
+        });
+    }
+    if payload.end_time <= payload.start_time || payload.start_time <= time() {
+        return Err(Error::InvalidInput {
+            msg: "a charter must start in the future and end after it starts".to_string(),
+        });
+    }
+    if payload.passenger_count == 0 {
+        return Err(Error::InvalidInput {
+            msg: "a charter must carry at least one passenger".to_string(),
+        });
+    }
+    let charter = Charter {
+        id: next_id(),
+        customer_id: payload.customer_id,
+        start_time: payload.start_time,
+        end_time: payload.end_time,
+        pickup: places[0].to_string(),
+        drop_off: places[1].to_string(),
+        passenger_count: payload.passenger_count,
+        quote: None,
+        status: CharterStatus::Requested,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_insert_charter(&charter);
+    Ok(charter)
+}
+
+// An operator offers a bus and a price. The bus must be free for the whole charter window.
+#[ic_cdk::update]
+fn quote_charter(id: u64, payload: CharterQuotePayload) -> Result<Charter, Error> {
+    ensure_admin()?;
+    let mut charter = _get_charter(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a charter with id={} not found", id),
+    })?;
+    if charter.status != CharterStatus::Requested && charter.status != CharterStatus::Quoted {
+        return Err(Error::InvalidInput {
+            msg: format!("charter with id={} can no longer be quoted", id),
+        });
+    }
+    let bus = _get_bus(&payload.bus_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus with id={} not found", payload.bus_id),
+    })?;
+    if bus.capacity < charter.passenger_count {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "bus with id={} seats {} but the charter needs {}",
+                bus.id, bus.capacity, charter.passenger_count
+            ),
+        });
+    }
+    let now = time();
+    if payload.valid_until <= now {
+        return Err(Error::InvalidInput {
+            msg: "a quote must be valid until a future time".to_string(),
+        });
+    }
+    if payload.notes.len() > MAX_CHARTER_NOTES_LEN {
+        return Err(Error::InvalidInput {
+            msg: format!("quote notes can be at most {} bytes", MAX_CHARTER_NOTES_LEN),
+        });
+    }
+    ensure_bus_compliant(bus.id, charter.end_time)?;
+    ensure_bus_available(bus.id, charter.start_time, charter.end_time)?;
+    charter.quote = Some(CharterQuote {
+        bus_id: bus.id,
+        price: payload.price,
+        notes: payload.notes,
+        quoted_at: now,
+        valid_until: payload.valid_until,
+    });
+    charter.status = CharterStatus::Quoted;
+    charter.updated_at = Some(now);
+    do_insert_charter(&charter);
+    Ok(charter)
+}
+
+// Accepting books the quoted bus for the charter window, so availability is checked again
+#[ic_cdk::update]
+fn accept_charter_quote(id: u64) -> Result<Charter, Error> {
+    let mut charter = _get_charter(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a charter with id={} not found", id),
+    })?;
+    ensure_customer_caller(charter.customer_id)?;
+    let now = time();
+    let quote = match (&charter.status, &charter.quote) {
+        (CharterStatus::Quoted, Some(quote)) if quote.valid_until > now => quote.clone(),
+        (CharterStatus::Quoted, Some(_)) => {
+            return Err(Error::InvalidInput {
+                msg: format!("the quote for charter with id={} has expired", id),
+            })
+        }
+        _ => {
+            return Err(Error::InvalidInput {
+                msg: format!("charter with id={} has no quote to accept", id),
+            })
+        }
+    };
+    ensure_bus_available(quote.bus_id, charter.start_time, charter.end_time)?;
+    do_block_bus(BlockedInterval {
+        bus_id: quote.bus_id,
+        start: charter.start_time,
+        end: charter.end_time,
+        reason: BlockReason::Charter,
+        reference_id: id,
+    });
+    charter.status = CharterStatus::Accepted;
+    charter.updated_at = Some(now);
+    do_insert_charter(&charter);
+    Ok(charter)
+}
+
+#[ic_cdk::update]
+fn decline_charter_quote(id: u64) -> Result<Charter, Error> {
+    ensure_customer_caller(get_charter(id)?.customer_id)?;
+    update_charter_status(id, CharterStatus::Quoted, CharterStatus::Declined)
+}
+
+#[ic_cdk::update]
+fn cancel_charter(id: u64) -> Result<Charter, Error> {
+    let mut charter = _get_charter(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a charter with id={} not found", id),
+    })?;
+    ensure_customer_caller(charter.customer_id)?;
+    if charter.status == CharterStatus::Declined || charter.status == CharterStatus::Cancelled {
+        return Err(Error::InvalidInput {
+            msg: format!("charter with id={} is already closed", id),
+        });
+    }
+    if let (CharterStatus::Accepted, Some(quote)) = (&charter.status, &charter.quote) {
+        do_unblock_bus(quote.bus_id, charter.start_time);
+    }
+    charter.status = CharterStatus::Cancelled;
+    charter.updated_at = Some(time());
+    do_insert_charter(&charter);
+    Ok(charter)
+}
+
+#[ic_cdk::query]
+fn get_charter(id: u64) -> Result<Charter, Error> {
+    match _get_charter(&id) {
+        Some(charter) => {
+            ensure_customer_caller(charter.customer_id)?;
+            Ok(charter)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("a charter with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_customer_charters(customer_id: u64) -> Result<Vec<Charter>, Error> {
+    ensure_customer_caller(customer_id)?;
+    Ok(CHARTER_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, charter)| charter)
+            .filter(|charter| charter.customer_id == customer_id)
+            .collect()
+    }))
+}
+
+fn update_charter_status(
+    id: u64,
+    from: CharterStatus,
+    to: CharterStatus,
+) -> Result<Charter, Error> {
+    match _get_charter(&id) {
+        Some(mut charter) if charter.status == from => {
+            charter.status = to;
+            charter.updated_at = Some(time());
+            do_insert_charter(&charter);
+            Ok(charter)
+        }
+        Some(_) => Err(Error::InvalidInput {
+            msg: format!("charter with id={} is not awaiting a decision", id),
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("a charter with id={} not found", id),
+        }),
+    }
+}
+
+fn do_insert_charter(charter: &Charter) {
+    CHARTER_STORAGE.with(|service| service.borrow_mut().insert(charter.id, charter.clone()));
+}
+
+fn _get_charter(id: &u64) -> Option<Charter> {
+    CHARTER_STORAGE.with(|service| service.borrow().get(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_quoted_charter_with_the_longest_text_fits_its_record() {
+        let charter = Charter {
+            id: u64::MAX,
+            customer_id: u64::MAX,
+            start_time: u64::MAX,
+            end_time: u64::MAX,
+            pickup: "p".repeat(MAX_CHARTER_PLACE_LEN),
+            drop_off: "d".repeat(MAX_CHARTER_PLACE_LEN),
+            passenger_count: u32::MAX,
+            quote: Some(CharterQuote {
+                bus_id: u64::MAX,
+                price: u64::MAX,
+                notes: "n".repeat(MAX_CHARTER_NOTES_LEN),
+                quoted_at: u64::MAX,
+                valid_until: u64::MAX,
+            }),
+            status: CharterStatus::Cancelled,
+            created_at: u64::MAX,
+            updated_at: Some(u64::MAX),
+        };
+        assert!(charter.to_bytes().len() <= Charter::MAX_SIZE as usize);
+    }
+}
@@ -0,0 +1,206 @@
+use super::*;
+
+const COMPLIANCE_ALERT_WINDOW: u64 = 30 * NANOS_PER_DAY;
+pub(crate) const COMPLIANCE_SCAN_INTERVAL: u64 = 60 * 60 * 1_000_000_000;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum ComplianceKind {
+    Registration,
+    Insurance,
+    Roadworthiness,
+}
+
+const REQUIRED_COMPLIANCE: [ComplianceKind; 3] = [
+    ComplianceKind::Registration,
+    ComplianceKind::Insurance,
+    ComplianceKind::Roadworthiness,
+];
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct ComplianceDocument {
+    id: u64,
+    bus_id: u64,
+    kind: ComplianceKind,
+    reference_number: String,
+    issued_at: u64,
+    expires_at: u64,
+    created_at: u64,
+}
+
+impl Storable for ComplianceDocument {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for ComplianceDocument {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct ComplianceDocumentPayload {
+    kind: ComplianceKind,
+    reference_number: String,
+    issued_at: u64,
+    expires_at: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct ComplianceAlert {
+    bus_id: u64,
+    document: ComplianceDocument,
+    expired: bool,
+}
+
+// Result of the last periodic compliance scan
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct ComplianceReport {
+    generated_at: u64,
+    alerts: Vec<ComplianceAlert>,
+}
+
+impl Storable for ComplianceReport {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[ic_cdk::update]
+fn add_compliance_document(
+    bus_id: u64,
+    payload: ComplianceDocumentPayload,
+) -> Result<ComplianceDocument, Error> {
+    ensure_admin()?;
+    if _get_bus(&bus_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", bus_id),
+        });
+    }
+    if payload.expires_at <= payload.issued_at {
+        return Err(Error::InvalidInput {
+            msg: "a document must expire after it was issued".to_string(),
+        });
+    }
+    let reference_number = payload.reference_number.trim().to_string();
+    if reference_number.is_empty() {
+        return Err(Error::InvalidInput {
+            msg: "a document needs a reference number".to_string(),
+        });
+    }
+    let document = ComplianceDocument {
+        id: next_id(),
+        bus_id,
+        kind: payload.kind,
+        reference_number,
+        issued_at: payload.issued_at,
+        expires_at: payload.expires_at,
+        created_at: time(),
+    };
+    COMPLIANCE_STORAGE.with(|service| {
+        service
+            .borrow_mut()
+            .insert((bus_id, document.id), document.clone())
+    });
+    Ok(document)
+}
+
+#[ic_cdk::query]
+fn get_compliance_documents(bus_id: u64) -> Vec<ComplianceDocument> {
+    _get_compliance_documents(bus_id)
+}
+
+#[ic_cdk::query]
+fn get_compliance_alerts() -> ComplianceReport {
+    COMPLIANCE_REPORT.with(|report| report.borrow().get().clone())
+}
+
+// Rebuilds the alert list now instead of waiting for the next scheduled scan
+#[ic_cdk::update]
+fn refresh_compliance_alerts() -> Result<ComplianceReport, Error> {
+    ensure_admin()?;
+    Ok(do_scan_compliance(time()))
+}
+
+fn _get_compliance_documents(bus_id: u64) -> Vec<ComplianceDocument> {
+    COMPLIANCE_STORAGE.with(|service| {
+        service
+            .borrow()
+            .range((bus_id, 0)..=(bus_id, u64::MAX))
+            .map(|(_, document)| document)
+            .collect()
+    })
+}
+
+// The document of each kind that stays valid the longest is the one in force
+fn _get_current_compliance(bus_id: u64) -> BTreeMap<u8, ComplianceDocument> {
+    let mut current: BTreeMap<u8, ComplianceDocument> = BTreeMap::new();
+    for document in _get_compliance_documents(bus_id) {
+        let kind = document.kind as u8;
+        if current
+            .get(&kind)
+            .is_none_or(|existing| existing.expires_at < document.expires_at)
+        {
+            current.insert(kind, document);
+        }
+    }
+    current
+}
+
+// A bus may only be assigned work if all of its required documents are valid until `until`
+pub(crate) fn ensure_bus_compliant(bus_id: u64, until: u64) -> Result<(), Error> {
+    let current = _get_current_compliance(bus_id);
+    for kind in REQUIRED_COMPLIANCE {
+        match current.get(&(kind as u8)) {
+            Some(document) if document.expires_at >= until => {}
+            Some(document) => {
+                return Err(Error::InvalidInput {
+                    msg: format!(
+                        "bus with id={} has a document ({}) expiring before the assignment ends",
+                        bus_id, document.reference_number
+                    ),
+                })
+            }
+            None => {
+                return Err(Error::InvalidInput {
+                    msg: format!(
+                        "bus with id={} is missing a required compliance document",
+                        bus_id
+                    ),
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn do_scan_compliance(now: u64) -> ComplianceReport {
+    let bus_ids: Vec<u64> =
+        BUS_STORAGE.with(|service| service.borrow().iter().map(|(id, _)| id).collect());
+    let alerts = bus_ids
+        .into_iter()
+        .flat_map(|bus_id| _get_current_compliance(bus_id).into_values())
+        .filter(|document| document.expires_at < now + COMPLIANCE_ALERT_WINDOW)
+        .map(|document| ComplianceAlert {
+            bus_id: document.bus_id,
+            expired: document.expires_at <= now,
+            document,
+        })
+        .collect();
+    let report = ComplianceReport {
+        generated_at: now,
+        alerts,
+    };
+    COMPLIANCE_REPORT
+        .with(|cell| cell.borrow_mut().set(report.clone()))
+        .expect("cannot store the compliance report");
+    report
+}
@@ -0,0 +1,367 @@
+use super::*;
+
+// Buses seating more than this many passengers need a full category D licence
+const MINIBUS_MAX_SEATS: u32 = 16;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum LicenseClass {
+    D1, // minibuses up to MINIBUS_MAX_SEATS passengers
+    D,  // any bus
+}
+
+impl LicenseClass {
+    fn covers(&self, bus: &Bus) -> bool {
+        match self {
+            LicenseClass::D => true,
+            LicenseClass::D1 => bus.capacity <= MINIBUS_MAX_SEATS,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Driver {
+    pub(crate) id: u64,
+    principal: Principal,
+    name: String,
+    contact: String,
+    license_number: String,
+    license_class: LicenseClass,
+    license_expires_at: u64,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for Driver {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Driver {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Hours-of-service limits checked whenever a driver is assigned to a trip
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct DrivingRules {
+    max_driving_per_24h: u64, // nanoseconds of driving in any rolling 24 hour window
+    min_rest_between_shifts: u64,
+    max_shift_gap: u64, // trips closer together than this count as one shift
+}
+
+impl Default for DrivingRules {
+    fn default() -> Self {
+        const HOUR: u64 = 60 * 60 * 1_000_000_000;
+        DrivingRules {
+            max_driving_per_24h: 9 * HOUR,
+            min_rest_between_shifts: 11 * HOUR,
+            max_shift_gap: 3 * HOUR,
+        }
+    }
+}
+
+impl Storable for DrivingRules {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct DriverPayload {
+    principal: Principal,
+    name: String,
+    contact: String,
+    license_number: String,
+    license_class: LicenseClass,
+    license_expires_at: u64,
+}
+
+#[ic_cdk::update]
+fn add_driver(payload: DriverPayload) -> Result<Driver, Error> {
+    ensure_admin()?;
+    validate_driver_payload(&payload, None)?;
+    let driver = Driver {
+        id: next_id(),
+        principal: payload.principal,
+        name: payload.name.trim().to_string(),
+        contact: payload.contact,
+        license_number: payload.license_number.trim().to_uppercase(),
+        license_class: payload.license_class,
+        license_expires_at: payload.license_expires_at,
+        created_at: time(),
+        updated_at: None,
+    };
+    ensure_driver_fits(&driver)?;
+    do_insert_driver(&driver);
+    Ok(driver)
+}
+
+#[ic_cdk::update]
+fn update_driver(id: u64, payload: DriverPayload) -> Result<Driver, Error> {
+    ensure_admin()?;
+    let mut driver = _get_driver(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a driver with id={} not found", id),
+    })?;
+    validate_driver_payload(&payload, Some(id))?;
+    driver.principal = payload.principal;
+    driver.name = payload.name.trim().to_string();
+    driver.contact = payload.contact;
+    driver.license_number = payload.license_number.trim().to_uppercase();
+    driver.license_class = payload.license_class;
+    driver.license_expires_at = payload.license_expires_at;
+    driver.updated_at = Some(time());
+    ensure_driver_fits(&driver)?;
+    do_insert_driver(&driver);
+    Ok(driver)
+}
+
+#[ic_cdk::query]
+fn get_driver(id: u64) -> Result<Driver, Error> {
+    match _get_driver(&id) {
+        Some(driver) => Ok(driver),
+        None => Err(Error::NotFound {
+            msg: format!("a driver with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn assign_driver(trip_id: u64, driver_id: u64) -> Result<Trip, Error> {
+    ensure_admin()?;
+    let mut trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    if trip.status != TripStatus::Scheduled {
+        return Err(Error::InvalidInput {
+            msg: format!("trip with id={} is not scheduled", trip_id),
+        });
+    }
+    let driver = _get_driver(&driver_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a driver with id={} not found", driver_id),
+    })?;
+    let bus = _get_bus(&trip.bus_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus with id={} not found", trip.bus_id),
+    })?;
+    ensure_driver_qualified(&driver, &bus, trip.arrival_time)?;
+    ensure_driver_rested(driver_id, &trip)?;
+    trip.driver_id = Some(driver_id);
+    trip.updated_at = Some(time());
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+#[ic_cdk::update]
+fn unassign_driver(trip_id: u64) -> Result<Trip, Error> {
+    ensure_admin()?;
+    let mut trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    trip.driver_id = None;
+    trip.updated_at = Some(time());
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+// For the signed-in driver: their scheduled trips that haven't departed yet, soonest first
+#[ic_cdk::query]
+fn get_my_assignments() -> Result<Vec<Trip>, Error> {
+    let driver = _get_caller_driver()?;
+    let now = time();
+    let mut trips: Vec<Trip> = TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, trip)| trip)
+            .filter(|trip| {
+                trip.driver_id == Some(driver.id)
+                    && trip.status == TripStatus::Scheduled
+                    && trip.departure_time > now
+            })
+            .collect()
+    });
+    trips.sort_by_key(|trip| trip.departure_time);
+    Ok(trips)
+}
+
+fn validate_driver_payload(payload: &DriverPayload, driver_id: Option<u64>) -> Result<(), Error> {
+    if payload.name.trim().is_empty() || payload.license_number.trim().is_empty() {
+        return Err(Error::InvalidInput {
+            msg: "a driver needs a name and a license number".to_string(),
+        });
+    }
+    let taken = DRIVER_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .any(|(id, driver)| Some(id) != driver_id && driver.principal == payload.principal)
+    });
+    if taken {
+        return Err(Error::Conflict {
+            msg: format!(
+                "principal {} is already linked to another driver",
+                payload.principal
+            ),
+        });
+    }
+    Ok(())
+}
+
+// Name, contact and licence number are free text, so check the record fits before storing it
+fn ensure_driver_fits(driver: &Driver) -> Result<(), Error> {
+    if driver.to_bytes().len() > Driver::MAX_SIZE as usize {
+        return Err(Error::InvalidInput {
+            msg: "the driver's name, contact or license number is too long".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// The licence must cover the bus and still be valid when the trip ends
+fn ensure_driver_qualified(driver: &Driver, bus: &Bus, until: u64) -> Result<(), Error> {
+    if !driver.license_class.covers(bus) {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "driver with id={} is not licensed for a {}-seat bus",
+                driver.id, bus.capacity
+            ),
+        });
+    }
+    if driver.license_expires_at < until {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "license of driver with id={} expires before the trip ends",
+                driver.id
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_driving_rules() -> DrivingRules {
+    DRIVING_RULES.with(|rules| rules.borrow().get().clone())
+}
+
+#[ic_cdk::update]
+fn set_driving_rules(rules: DrivingRules) -> Result<DrivingRules, Error> {
+    ensure_admin()?;
+    if rules.max_driving_per_24h == 0 || rules.max_driving_per_24h > NANOS_PER_DAY {
+        return Err(Error::InvalidInput {
+            msg: "the daily driving limit must be between zero and 24 hours".to_string(),
+        });
+    }
+    if rules.max_shift_gap > rules.min_rest_between_shifts {
+        return Err(Error::InvalidInput {
+            msg: "a break within a shift can't be longer than the rest between shifts".to_string(),
+        });
+    }
+    DRIVING_RULES
+        .with(|cell| cell.borrow_mut().set(rules.clone()))
+        .expect("cannot store the driving rules");
+    Ok(rules)
+}
+
+// Checks the driver's schedule with `trip` added against the configured rules:
+// no overlapping trips, a cap on driving in any rolling 24 hours, and a minimum rest
+// between shifts. Gaps shorter than `max_shift_gap` are breaks within the same shift.
+fn ensure_driver_rested(driver_id: u64, trip: &Trip) -> Result<(), Error> {
+    let rules = DRIVING_RULES.with(|rules| rules.borrow().get().clone());
+    let mut schedule: Vec<Trip> = TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, other)| other)
+            .filter(|other| {
+                other.id != trip.id
+                    && other.driver_id == Some(driver_id)
+                    && other.status != TripStatus::Cancelled
+            })
+            .collect()
+    });
+    if let Some(other) = schedule.iter().find(|other| {
+        other.departure_time < trip.arrival_time && trip.departure_time < other.arrival_time
+    }) {
+        return Err(Error::Conflict {
+            msg: format!(
+                "driver with id={} is already driving trip id={} at that time",
+                driver_id, other.id
+            ),
+        });
+    }
+    schedule.push(trip.clone());
+    schedule.sort_by_key(|other| other.departure_time);
+
+    // The busiest 24 hour window always starts at some trip's departure
+    for window_start in schedule.iter().map(|other| other.departure_time) {
+        let window_end = window_start + NANOS_PER_DAY;
+        let driving: u64 = schedule
+            .iter()
+            .map(|other| {
+                other
+                    .arrival_time
+                    .min(window_end)
+                    .saturating_sub(other.departure_time.max(window_start))
+            })
+            .sum();
+        if driving > rules.max_driving_per_24h {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "driver with id={} would drive {} minutes in the 24 hours from {}, the limit is {}",
+                    driver_id,
+                    driving / 60_000_000_000,
+                    window_start,
+                    rules.max_driving_per_24h / 60_000_000_000
+                ),
+            });
+        }
+    }
+
+    for pair in schedule.windows(2) {
+        let rest = pair[1].departure_time - pair[0].arrival_time;
+        if rest >= rules.max_shift_gap && rest < rules.min_rest_between_shifts {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "driver with id={} would rest only {} minutes between trip id={} and trip id={}, the minimum between shifts is {}",
+                    driver_id,
+                    rest / 60_000_000_000,
+                    pair[0].id,
+                    pair[1].id,
+                    rules.min_rest_between_shifts / 60_000_000_000
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn _get_caller_driver() -> Result<Driver, Error> {
+    let caller = ic_cdk::caller();
+    DRIVER_STORAGE
+        .with(|service| {
+            service
+                .borrow()
+                .iter()
+                .map(|(_, driver)| driver)
+                .find(|driver| driver.principal == caller)
+        })
+        .ok_or_else(|| Error::Unauthorized {
+            msg: "the caller is not registered as a driver".to_string(),
+        })
+}
+
+fn do_insert_driver(driver: &Driver) {
+    DRIVER_STORAGE.with(|service| service.borrow_mut().insert(driver.id, driver.clone()));
+}
+
+fn _get_driver(id: &u64) -> Option<Driver> {
+    DRIVER_STORAGE.with(|service| service.borrow().get(id))
+}
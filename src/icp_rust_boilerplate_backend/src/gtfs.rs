@@ -0,0 +1,26 @@
+use super::*;
+
+mod feed;
+mod import;
+mod realtime;
+
+pub(crate) use feed::*;
+pub(crate) use import::*;
+pub(crate) use realtime::*;
+
+// YYYYMMDD for a day counted from 1 January 1970
+fn format_gtfs_date(day: u64) -> String {
+    let (year, month, day) = civil_from_days(day as i64);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+// HH:MM:SS from the start of the service day
+fn format_gtfs_time(offset: u64) -> String {
+    let seconds = offset / 1_000_000_000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
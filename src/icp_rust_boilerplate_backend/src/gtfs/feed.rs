@@ -0,0 +1,699 @@
+use super::*;
+
+// Operator details published in agency.txt
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsAgency {
+    name: String,
+    url: String,
+    timezone: String, // times in the feed are always UTC; this only labels them
+}
+
+impl Default for GtfsAgency {
+    fn default() -> Self {
+        GtfsAgency {
+            name: String::new(),
+            url: String::new(),
+            timezone: "Etc/UTC".to_string(),
+        }
+    }
+}
+
+impl Storable for GtfsAgency {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// The static feed being served: a zip snapshot kept in GTFS_FEED_CHUNKS
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct GtfsFeed {
+    snapshot: Option<u64>, // None until the first build finishes
+    built_at: u64,
+    size: u64,
+    files: Vec<GtfsFeedFile>,
+}
+
+// Where the contents of a file start in the zip; entries are stored uncompressed
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct GtfsFeedFile {
+    name: String,
+    offset: u64,
+    size: u64,
+}
+
+impl Storable for GtfsFeed {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct GtfsFeedChunk {
+    data: Vec<u8>,
+}
+
+impl Storable for GtfsFeedChunk {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsFeedChunk {
+    const MAX_SIZE: u32 = GTFS_FEED_CHUNK_SIZE as u32 + 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Steps of a feed build in the order they run. The file steps write their file a batch at a time.
+#[derive(Clone, Copy, PartialEq)]
+enum GtfsFeedStage {
+    Purge,
+    Agency,
+    Stops,
+    Routes,
+    Trips,
+    StopTimes,
+    CalendarDates,
+    Directory,
+}
+
+impl GtfsFeedStage {
+    fn file_name(self) -> Option<&'static str> {
+        match self {
+            GtfsFeedStage::Agency => Some("agency.txt"),
+            GtfsFeedStage::Stops => Some("stops.txt"),
+            GtfsFeedStage::Routes => Some("routes.txt"),
+            GtfsFeedStage::Trips => Some("trips.txt"),
+            GtfsFeedStage::StopTimes => Some("stop_times.txt"),
+            GtfsFeedStage::CalendarDates => Some("calendar_dates.txt"),
+            GtfsFeedStage::Purge | GtfsFeedStage::Directory => None,
+        }
+    }
+
+    fn next(self) -> GtfsFeedStage {
+        match self {
+            GtfsFeedStage::Purge => GtfsFeedStage::Agency,
+            GtfsFeedStage::Agency => GtfsFeedStage::Stops,
+            GtfsFeedStage::Stops => GtfsFeedStage::Routes,
+            GtfsFeedStage::Routes => GtfsFeedStage::Trips,
+            GtfsFeedStage::Trips => GtfsFeedStage::StopTimes,
+            GtfsFeedStage::StopTimes => GtfsFeedStage::CalendarDates,
+            GtfsFeedStage::CalendarDates | GtfsFeedStage::Directory => GtfsFeedStage::Directory,
+        }
+    }
+}
+
+// A zip entry written so far
+struct GtfsFeedEntry {
+    name: &'static str,
+    header_offset: u64,
+    offset: u64,
+    crc: u32,
+    size: u64,
+}
+
+// A snapshot being written. Bytes short of a full chunk wait in `pending`.
+pub(crate) struct GtfsFeedBuild {
+    snapshot: u64,
+    started_at: u64,
+    stage: GtfsFeedStage,
+    cursor: Option<u64>, // last id written to the current file
+    flushed: u64,
+    pending: Vec<u8>,
+    entry: Option<GtfsFeedEntry>,
+    entries: Vec<GtfsFeedEntry>,
+    trip_ids: BTreeSet<u64>,     // trips in trips.txt, for stop_times.txt
+    service_days: BTreeSet<u64>, // days with trips, for calendar_dates.txt
+}
+
+#[ic_cdk::query]
+fn get_gtfs_agency() -> GtfsAgency {
+    GTFS_AGENCY.with(|agency| agency.borrow().get().clone())
+}
+
+#[ic_cdk::update]
+fn set_gtfs_agency(agency: GtfsAgency) -> Result<GtfsAgency, Error> {
+    ensure_admin()?;
+    if agency.name.trim().is_empty() || agency.url.trim().is_empty() || agency.timezone.is_empty() {
+        return Err(Error::InvalidInput {
+            msg: "the agency needs a name, a URL and a timezone".to_string(),
+        });
+    }
+    GTFS_AGENCY
+        .with(|cell| cell.borrow_mut().set(agency.clone()))
+        .expect("cannot store the GTFS agency");
+    Ok(agency)
+}
+
+// Snapshots are stored in pieces of this size, and served this much per response
+const GTFS_FEED_CHUNK_SIZE: u64 = 64 * 1024;
+const GTFS_FEED_RESPONSE_SIZE: u64 = 1024 * 1024;
+pub(crate) const GTFS_FEED_BUILD_INTERVAL: u64 = 60 * 60 * 1_000_000_000;
+// Records written per batch of a build; a trip has a line per stop in stop_times.txt
+const GTFS_FEED_BATCH_SIZE: usize = 1000;
+const GTFS_FEED_TRIP_BATCH_SIZE: usize = 50;
+// Dropping a chunk reads it first, so old snapshots go a few at a time
+const GTFS_FEED_PURGE_BATCH_SIZE: usize = 64;
+
+fn _get_gtfs_feed() -> GtfsFeed {
+    GTFS_FEED.with(|feed| feed.borrow().get().clone())
+}
+
+// Starts writing a new snapshot of the feed unless one is already being written. The snapshot
+// being served stays up until the new one is done, and the one before it until the build
+// after, so downloads already under way can finish.
+pub(crate) fn do_start_gtfs_feed_build(now: u64) {
+    let started = GTFS_FEED_BUILD.with(|build| {
+        let mut build = build.borrow_mut();
+        if build.is_some() {
+            return false;
+        }
+        *build = Some(GtfsFeedBuild {
+            snapshot: next_id(),
+            started_at: now,
+            stage: GtfsFeedStage::Purge,
+            cursor: None,
+            flushed: 0,
+            pending: Vec::new(),
+            entry: None,
+            entries: Vec::new(),
+            trip_ids: BTreeSet::new(),
+            service_days: BTreeSet::new(),
+        });
+        true
+    });
+    if started {
+        do_schedule_gtfs_feed_batch();
+    }
+}
+
+fn do_schedule_gtfs_feed_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        let Some(mut build) = GTFS_FEED_BUILD.with(|build| build.borrow_mut().take()) else {
+            return;
+        };
+        if !do_build_gtfs_feed_batch(&mut build) {
+            GTFS_FEED_BUILD.with(|service| *service.borrow_mut() = Some(build));
+            do_schedule_gtfs_feed_batch();
+        }
+    });
+}
+
+// Moves a build on by a batch. Returns true once it is done, or has been given up because the
+// feed outgrew what a zip without extensions can hold.
+fn do_build_gtfs_feed_batch(build: &mut GtfsFeedBuild) -> bool {
+    if build.flushed + build.pending.len() as u64 > u64::from(u32::MAX) {
+        return true;
+    }
+    let Some(name) = build.stage.file_name() else {
+        if build.stage == GtfsFeedStage::Purge {
+            if do_purge_gtfs_feed_snapshots() {
+                build.stage = build.stage.next();
+            }
+            return false;
+        }
+        do_finish_gtfs_feed(build);
+        return true;
+    };
+    if build.entry.is_none() {
+        let header_offset = build.flushed + build.pending.len() as u64;
+        let (dos_time, dos_date) = dos_date_time(build.started_at);
+        let mut header = 0x0403_4b50u32.to_le_bytes().to_vec();
+        // The CRC and sizes are filled in once the contents are written
+        header.extend(zip_entry_fields(name, dos_time, dos_date, 0, 0));
+        header.extend_from_slice(name.as_bytes());
+        do_write_gtfs_feed(build, &header);
+        build.entry = Some(GtfsFeedEntry {
+            name,
+            header_offset,
+            offset: header_offset + header.len() as u64,
+            crc: 0,
+            size: 0,
+        });
+    }
+    let (text, done) = gtfs_feed_batch(build);
+    if let Some(entry) = build.entry.as_mut() {
+        entry.crc = crc32(entry.crc, text.as_bytes());
+        entry.size += text.len() as u64;
+    }
+    do_write_gtfs_feed(build, text.as_bytes());
+    if done {
+        if let Some(entry) = build.entry.take() {
+            let mut sizes = entry.crc.to_le_bytes().to_vec();
+            sizes.extend_from_slice(&(entry.size as u32).to_le_bytes()); // compressed size
+            sizes.extend_from_slice(&(entry.size as u32).to_le_bytes()); // uncompressed size
+            do_patch_gtfs_feed(build, entry.header_offset + 14, &sizes);
+            build.entries.push(entry);
+        }
+        build.stage = build.stage.next();
+        build.cursor = None;
+    }
+    false
+}
+
+// Drops a batch of chunks of the snapshots that aren't being served. Returns true once there
+// are none left.
+fn do_purge_gtfs_feed_snapshots() -> bool {
+    let published = _get_gtfs_feed().snapshot;
+    let keys: Vec<(u64, u64)> = GTFS_FEED_CHUNKS.with(|service| {
+        let service = service.borrow();
+        let keys = match published {
+            Some(snapshot) => service
+                .range(..(snapshot, 0))
+                .chain(service.range((snapshot + 1, 0)..))
+                .map(|(key, _)| key)
+                .take(GTFS_FEED_PURGE_BATCH_SIZE)
+                .collect(),
+            None => service
+                .iter()
+                .map(|(key, _)| key)
+                .take(GTFS_FEED_PURGE_BATCH_SIZE)
+                .collect(),
+        };
+        keys
+    });
+    GTFS_FEED_CHUNKS.with(|service| {
+        let mut service = service.borrow_mut();
+        for key in &keys {
+            service.remove(key);
+        }
+    });
+    keys.len() < GTFS_FEED_PURGE_BATCH_SIZE
+}
+
+// Appends to the snapshot, storing each chunk as it fills up
+fn do_write_gtfs_feed(build: &mut GtfsFeedBuild, bytes: &[u8]) {
+    build.pending.extend_from_slice(bytes);
+    while build.pending.len() as u64 >= GTFS_FEED_CHUNK_SIZE {
+        let rest = build.pending.split_off(GTFS_FEED_CHUNK_SIZE as usize);
+        let data = std::mem::replace(&mut build.pending, rest);
+        do_flush_gtfs_feed(build, data);
+    }
+}
+
+fn do_flush_gtfs_feed(build: &mut GtfsFeedBuild, data: Vec<u8>) {
+    let key = (build.snapshot, build.flushed / GTFS_FEED_CHUNK_SIZE);
+    build.flushed += data.len() as u64;
+    GTFS_FEED_CHUNKS.with(|service| service.borrow_mut().insert(key, GtfsFeedChunk { data }));
+}
+
+// Overwrites bytes already written, which may have been stored by now
+fn do_patch_gtfs_feed(build: &mut GtfsFeedBuild, offset: u64, bytes: &[u8]) {
+    for (index, byte) in bytes.iter().enumerate() {
+        let position = offset + index as u64;
+        if position >= build.flushed {
+            build.pending[(position - build.flushed) as usize] = *byte;
+            continue;
+        }
+        let key = (build.snapshot, position / GTFS_FEED_CHUNK_SIZE);
+        GTFS_FEED_CHUNKS.with(|service| {
+            let mut service = service.borrow_mut();
+            if let Some(mut chunk) = service.get(&key) {
+                chunk.data[(position % GTFS_FEED_CHUNK_SIZE) as usize] = *byte;
+                service.insert(key, chunk);
+            }
+        });
+    }
+}
+
+// Writes the zip's central directory and starts serving the snapshot
+fn do_finish_gtfs_feed(build: &mut GtfsFeedBuild) {
+    let (dos_time, dos_date) = dos_date_time(build.started_at);
+    let directory_offset = build.flushed + build.pending.len() as u64;
+    let mut directory = Vec::new();
+    for entry in &build.entries {
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        directory.extend(zip_entry_fields(
+            entry.name,
+            dos_time,
+            dos_date,
+            entry.crc,
+            entry.size as u32,
+        ));
+        directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        directory.extend_from_slice(&(entry.header_offset as u32).to_le_bytes());
+        directory.extend_from_slice(entry.name.as_bytes());
+    }
+    let entries = build.entries.len() as u16;
+    let directory_size = directory.len() as u32;
+    directory.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    directory.extend_from_slice(&0u16.to_le_bytes()); // this disk
+    directory.extend_from_slice(&0u16.to_le_bytes()); // disk with the directory
+    directory.extend_from_slice(&entries.to_le_bytes());
+    directory.extend_from_slice(&entries.to_le_bytes());
+    directory.extend_from_slice(&directory_size.to_le_bytes());
+    directory.extend_from_slice(&(directory_offset as u32).to_le_bytes());
+    directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    do_write_gtfs_feed(build, &directory);
+    let rest = std::mem::take(&mut build.pending);
+    do_flush_gtfs_feed(build, rest);
+
+    let feed = GtfsFeed {
+        snapshot: Some(build.snapshot),
+        built_at: build.started_at,
+        size: build.flushed,
+        files: build
+            .entries
+            .iter()
+            .map(|entry| GtfsFeedFile {
+                name: entry.name.to_string(),
+                offset: entry.offset,
+                size: entry.size,
+            })
+            .collect(),
+    };
+    GTFS_FEED
+        .with(|service| service.borrow_mut().set(feed))
+        .expect("cannot publish the GTFS feed");
+}
+
+// The next lines of the file being written, header first, and whether it is complete.
+// The schedule covers stops, routes and every trip on a route that runs today or later. Each
+// service day is its own service in calendar_dates.txt, since trips are stored as dated runs
+// rather than weekly patterns. Cancelled trips are left out.
+fn gtfs_feed_batch(build: &mut GtfsFeedBuild) -> (String, bool) {
+    let mut text = String::new();
+    let after = build
+        .cursor
+        .map_or(std::ops::Bound::Unbounded, std::ops::Bound::Excluded);
+    let range = (after, std::ops::Bound::Unbounded);
+    match build.stage {
+        GtfsFeedStage::Agency => {
+            let agency = get_gtfs_agency();
+            text += &csv_row(&["agency_id", "agency_name", "agency_url", "agency_timezone"]);
+            text += &csv_row(&["1", &agency.name, &agency.url, &agency.timezone]);
+            (text, true)
+        }
+        GtfsFeedStage::Stops => {
+            if build.cursor.is_none() {
+                text += &csv_row(&["stop_id", "stop_name", "stop_lat", "stop_lon"]);
+            }
+            let stops: Vec<Stop> = STOP_STORAGE.with(|service| {
+                service
+                    .borrow()
+                    .range(range)
+                    .take(GTFS_FEED_BATCH_SIZE)
+                    .map(|(_, stop)| stop)
+                    .collect()
+            });
+            for stop in &stops {
+                text += &csv_row(&[
+                    &stop.id.to_string(),
+                    &stop.name,
+                    &stop.latitude.to_string(),
+                    &stop.longitude.to_string(),
+                ]);
+            }
+            build.cursor = stops.last().map(|stop| stop.id).or(build.cursor);
+            (text, stops.len() < GTFS_FEED_BATCH_SIZE)
+        }
+        GtfsFeedStage::Routes => {
+            if build.cursor.is_none() {
+                text += &csv_row(&["route_id", "agency_id", "route_long_name", "route_type"]);
+            }
+            let routes: Vec<Route> = ROUTE_STORAGE.with(|service| {
+                service
+                    .borrow()
+                    .range(range)
+                    .take(GTFS_FEED_BATCH_SIZE)
+                    .map(|(_, route)| route)
+                    .collect()
+            });
+            for route in &routes {
+                // 3 is the GTFS route type for buses
+                text += &csv_row(&[&route.id.to_string(), "1", &route.name, "3"]);
+            }
+            build.cursor = routes.last().map(|route| route.id).or(build.cursor);
+            (text, routes.len() < GTFS_FEED_BATCH_SIZE)
+        }
+        GtfsFeedStage::Trips => {
+            if build.cursor.is_none() {
+                text += &csv_row(&["route_id", "service_id", "trip_id"]);
+            }
+            let today = build.started_at / NANOS_PER_DAY * NANOS_PER_DAY;
+            let trips: Vec<Trip> = TRIP_STORAGE.with(|service| {
+                service
+                    .borrow()
+                    .range(range)
+                    .take(GTFS_FEED_BATCH_SIZE)
+                    .map(|(_, trip)| trip)
+                    .collect()
+            });
+            for trip in &trips {
+                if trip.status == TripStatus::Cancelled || trip.departure_time < today {
+                    continue;
+                }
+                let Some(route_id) = trip.route_id.filter(|id| _get_route(id).is_some()) else {
+                    continue;
+                };
+                let day = trip.departure_time / NANOS_PER_DAY;
+                let service_id = format!("D{}", format_gtfs_date(day));
+                text += &csv_row(&[&route_id.to_string(), &service_id, &trip.id.to_string()]);
+                build.trip_ids.insert(trip.id);
+                build.service_days.insert(day);
+            }
+            build.cursor = trips.last().map(|trip| trip.id).or(build.cursor);
+            (text, trips.len() < GTFS_FEED_BATCH_SIZE)
+        }
+        GtfsFeedStage::StopTimes => {
+            if build.cursor.is_none() {
+                text += &csv_row(&[
+                    "trip_id",
+                    "arrival_time",
+                    "departure_time",
+                    "stop_id",
+                    "stop_sequence",
+                    "shape_dist_traveled",
+                ]);
+            }
+            let trip_ids: Vec<u64> = build
+                .trip_ids
+                .range(range)
+                .take(GTFS_FEED_TRIP_BATCH_SIZE)
+                .copied()
+                .collect();
+            for trip_id in &trip_ids {
+                let trip = _get_trip(trip_id);
+                let route = trip.as_ref().and_then(|trip| _get_route(&trip.route_id?));
+                let (Some(trip), Some(route)) = (trip, route) else {
+                    continue;
+                };
+                let start = trip.departure_time % NANOS_PER_DAY;
+                for (sequence, route_stop) in route.stops.iter().enumerate() {
+                    text += &csv_row(&[
+                        &trip.id.to_string(),
+                        &format_gtfs_time(start + route_stop.arrival_offset),
+                        &format_gtfs_time(start + route_stop.departure_offset),
+                        &route_stop.stop_id.to_string(),
+                        &(sequence + 1).to_string(),
+                        &route_stop.distance_m.to_string(),
+                    ]);
+                }
+            }
+            build.cursor = trip_ids.last().copied().or(build.cursor);
+            (text, trip_ids.len() < GTFS_FEED_TRIP_BATCH_SIZE)
+        }
+        GtfsFeedStage::CalendarDates => {
+            text += &csv_row(&["service_id", "date", "exception_type"]);
+            for day in &build.service_days {
+                let date = format_gtfs_date(*day);
+                text += &csv_row(&[&format!("D{}", date), &date, "1"]);
+            }
+            (text, true)
+        }
+        GtfsFeedStage::Purge | GtfsFeedStage::Directory => (text, true),
+    }
+}
+
+// Bytes `start..end` of a snapshot, up to what fits a response, and where the rest begins if
+// there is more. A snapshot that has since been dropped ends the download early.
+pub(crate) fn gtfs_feed_body(snapshot: u64, start: u64, end: u64) -> (Vec<u8>, Option<u64>) {
+    let stop = end.min(start.saturating_add(GTFS_FEED_RESPONSE_SIZE));
+    if start >= stop {
+        return (Vec::new(), None);
+    }
+    let mut body = Vec::with_capacity((stop - start) as usize);
+    GTFS_FEED_CHUNKS.with(|service| {
+        let chunks = (snapshot, start / GTFS_FEED_CHUNK_SIZE)
+            ..=(snapshot, (stop - 1) / GTFS_FEED_CHUNK_SIZE);
+        for ((_, index), chunk) in service.borrow().range(chunks) {
+            let chunk_start = index * GTFS_FEED_CHUNK_SIZE;
+            let from = start.max(chunk_start) - chunk_start;
+            let to = stop.min(chunk_start + chunk.data.len() as u64) - chunk_start;
+            if from < to {
+                body.extend_from_slice(&chunk.data[from as usize..to as usize]);
+            }
+        }
+    });
+    let complete = body.len() as u64 == stop - start;
+    (body, (complete && stop < end).then_some(stop))
+}
+
+// A file of the feed, or the whole zip, streamed from the snapshot being served
+pub(crate) fn gtfs_feed_response(file: &str) -> Result<HttpResponse, Error> {
+    let feed = _get_gtfs_feed();
+    let Some(snapshot) = feed.snapshot else {
+        return Err(Error::NotFound {
+            msg: "the feed hasn't been built yet".to_string(),
+        });
+    };
+    let (content_type, start, end) = if file == "gtfs.zip" {
+        ("application/zip", 0, feed.size)
+    } else {
+        let entry = feed
+            .files
+            .iter()
+            .find(|entry| entry.name == file)
+            .ok_or_else(|| Error::NotFound {
+                msg: format!("the feed has no file {}", file),
+            })?;
+        ("text/csv", entry.offset, entry.offset + entry.size)
+    };
+    let (body, next) = gtfs_feed_body(snapshot, start, end);
+    let mut response = HttpResponse::ok(content_type, body);
+    response.streaming_strategy = next.map(|position| StreamingStrategy::Callback {
+        callback: StreamingCallback::new(
+            ic_cdk::id(),
+            "http_request_streaming_callback".to_string(),
+        ),
+        token: StreamingCallbackToken {
+            report: format!("gtfs/{}", file),
+            query: format!("snapshot={}&end={}", snapshot, end),
+            key: vec![position],
+        },
+    });
+    Ok(response)
+}
+
+// Modification time and date of zip entries, in MS-DOS form
+fn dos_date_time(now: u64) -> (u16, u16) {
+    let seconds = now / 1_000_000_000;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+    let dos_time = (((time_of_day / 3600) << 11)
+        | ((time_of_day / 60 % 60) << 5)
+        | (time_of_day % 60 / 2)) as u16;
+    let dos_date = ((((year.max(1980) - 1980) as u32) << 9) | (month << 5) | day) as u16;
+    (dos_time, dos_date)
+}
+
+// Fields shared by the local header and the central directory entry of an uncompressed
+// ("stored") zip entry, which every unzip tool reads
+fn zip_entry_fields(name: &str, dos_time: u16, dos_date: u16, crc: u32, size: u32) -> Vec<u8> {
+    let mut fields = Vec::new();
+    fields.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+    fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+    fields.extend_from_slice(&0u16.to_le_bytes()); // stored, no compression
+    fields.extend_from_slice(&dos_time.to_le_bytes());
+    fields.extend_from_slice(&dos_date.to_le_bytes());
+    fields.extend_from_slice(&crc.to_le_bytes());
+    fields.extend_from_slice(&size.to_le_bytes()); // compressed size
+    fields.extend_from_slice(&size.to_le_bytes()); // uncompressed size
+    fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+    fields
+}
+
+// CRC-32 (IEEE), as zip uses it, carried on from the CRC of the bytes before (0 to start)
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_carries_on_across_pieces() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+        assert_eq!(crc32(0, b""), 0);
+    }
+
+    #[test]
+    fn builds_the_static_feed_as_a_zip_in_batches() {
+        STOP_STORAGE.with(|service| {
+            let mut service = service.borrow_mut();
+            for id in 0..3000 {
+                let stop = Stop {
+                    id,
+                    name: format!("Stop {:04} on the long way round", id),
+                    latitude: 1.5,
+                    longitude: -2.5,
+                    created_at: 0,
+                };
+                service.insert(id, stop);
+            }
+        });
+        let mut build = GtfsFeedBuild {
+            snapshot: 5,
+            started_at: 20_000 * NANOS_PER_DAY,
+            stage: GtfsFeedStage::Purge,
+            cursor: None,
+            flushed: 0,
+            pending: Vec::new(),
+            entry: None,
+            entries: Vec::new(),
+            trip_ids: BTreeSet::new(),
+            service_days: BTreeSet::new(),
+        };
+        while !do_build_gtfs_feed_batch(&mut build) {}
+
+        let feed = _get_gtfs_feed();
+        assert_eq!(feed.snapshot, Some(5));
+        assert!(feed.size > GTFS_FEED_CHUNK_SIZE);
+        let mut zip = Vec::new();
+        let mut next = Some(0);
+        while let Some(start) = next {
+            let (body, rest) = gtfs_feed_body(5, start, feed.size);
+            zip.extend(body);
+            next = rest;
+        }
+        assert_eq!(zip.len() as u64, feed.size);
+
+        // Every entry's local header has the CRC and size of its contents
+        let read_u32 = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+        assert_eq!(feed.files.len(), 6);
+        for file in &feed.files {
+            let header = file.offset as usize - 30 - file.name.len();
+            let contents = &zip[file.offset as usize..(file.offset + file.size) as usize];
+            assert_eq!(read_u32(header), 0x0403_4b50);
+            assert_eq!(read_u32(header + 14), crc32(0, contents));
+            assert_eq!(read_u32(header + 18) as u64, file.size);
+        }
+        let end = zip.len() - 22;
+        assert_eq!(read_u32(end), 0x0605_4b50);
+        assert_eq!(u16::from_le_bytes([zip[end + 10], zip[end + 11]]), 6);
+
+        let stops = &feed.files[1];
+        let (first, rest) = gtfs_feed_body(5, stops.offset, stops.offset + stops.size);
+        assert!(first.starts_with(b"stop_id,stop_name"));
+        assert!(rest.is_none());
+        let text = String::from_utf8(first).unwrap();
+        assert_eq!(text.lines().count(), 3001);
+        assert!(text.ends_with("2999,Stop 2999 on the long way round,1.5,-2.5\r\n"));
+    }
+}
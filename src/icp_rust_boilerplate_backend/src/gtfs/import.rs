@@ -0,0 +1,1562 @@
+use super::*;
+
+// Uploads are sent in pieces small enough for a single message
+const MAX_GTFS_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_GTFS_IMPORT_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_GTFS_ERRORS: usize = 500;
+const MAX_GTFS_ERROR_LEN: usize = 256;
+// Names longer than this wouldn't fit the stop or route they are stored in
+const MAX_GTFS_NAME_LEN: usize = 256;
+// Records read per batch of an import. Trips create routes and timetables, so take fewer.
+const GTFS_BATCH_SIZE: usize = 1000;
+const GTFS_TRIP_BATCH_SIZE: usize = 50;
+// GTFS times run past 24:00:00 for trips that carry on after midnight
+const MAX_GTFS_TIME_HOURS: u64 = 7 * 24;
+
+#[derive(
+    candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub(crate) enum GtfsFile {
+    Stops,
+    Routes,
+    Trips,
+    StopTimes,
+    Calendar,
+    CalendarDates,
+}
+
+impl GtfsFile {
+    fn file_name(&self) -> &'static str {
+        match self {
+            GtfsFile::Stops => "stops.txt",
+            GtfsFile::Routes => "routes.txt",
+            GtfsFile::Trips => "trips.txt",
+            GtfsFile::StopTimes => "stop_times.txt",
+            GtfsFile::Calendar => "calendar.txt",
+            GtfsFile::CalendarDates => "calendar_dates.txt",
+        }
+    }
+
+    fn required_columns(&self) -> &'static [&'static str] {
+        match self {
+            GtfsFile::Stops => &["stop_id", "stop_name", "stop_lat", "stop_lon"],
+            GtfsFile::Routes => &["route_id"],
+            GtfsFile::Trips => &["route_id", "service_id", "trip_id"],
+            GtfsFile::StopTimes => &[
+                "trip_id",
+                "arrival_time",
+                "departure_time",
+                "stop_id",
+                "stop_sequence",
+            ],
+            GtfsFile::Calendar => &[
+                "service_id",
+                "monday",
+                "tuesday",
+                "wednesday",
+                "thursday",
+                "friday",
+                "saturday",
+                "sunday",
+                "start_date",
+                "end_date",
+            ],
+            GtfsFile::CalendarDates => &["service_id", "date", "exception_type"],
+        }
+    }
+
+    fn optional_columns(&self) -> &'static [&'static str] {
+        match self {
+            GtfsFile::Stops => &["location_type"],
+            GtfsFile::Routes => &["route_short_name", "route_long_name"],
+            GtfsFile::StopTimes => &["shape_dist_traveled"],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsChunk {
+    file: GtfsFile,
+    data: Vec<u8>,
+}
+
+impl Storable for GtfsChunk {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsChunk {
+    const MAX_SIZE: u32 = MAX_GTFS_CHUNK_SIZE as u32 + 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// An upload in progress
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsImport {
+    id: u64,
+    started_by: Principal,
+    started_at: u64,
+    chunks: u64,
+    bytes: u64,
+}
+
+impl Storable for GtfsImport {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsImport {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct GtfsImportOptions {
+    bus_ids: Vec<u64>, // pool for the timetables created from GTFS trips
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsLineError {
+    file: GtfsFile,
+    line: u32, // 0 when the problem is with the file as a whole
+    message: String,
+}
+
+impl Storable for GtfsLineError {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsLineError {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default, Clone)]
+pub(crate) struct GtfsImportReport {
+    import_id: u64,
+    stops_created: u32,
+    stops_updated: u32,
+    routes_created: u32,
+    routes_updated: u32,
+    timetables_created: u32,
+    timetables_updated: u32,
+    errors: Vec<GtfsLineError>,
+    errors_truncated: bool, // more than MAX_GTFS_ERRORS errors were found
+    finished: bool,         // false while batches are still being imported
+}
+
+impl GtfsImportReport {
+    fn error(&mut self, file: GtfsFile, line: u32, message: impl Into<String>) {
+        if self.errors.len() < MAX_GTFS_ERRORS {
+            let mut message: String = message.into();
+            if message.len() > MAX_GTFS_ERROR_LEN {
+                let mut end = MAX_GTFS_ERROR_LEN;
+                while !message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                message.truncate(end);
+            }
+            self.errors.push(GtfsLineError {
+                file,
+                line,
+                message,
+            });
+        } else {
+            self.errors_truncated = true;
+        }
+    }
+}
+
+// Steps of an import in the order they run. The file steps read their file a batch at a time.
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum GtfsImportStage {
+    Stops,
+    Routes,
+    Calendar,
+    CalendarDates,
+    StopTimes,
+    Trips,
+    UnusedStopTimes, // reports stop times of trips trips.txt didn't list
+    CleanUp,
+    Finished,
+}
+
+impl GtfsImportStage {
+    fn file(&self) -> Option<GtfsFile> {
+        match self {
+            GtfsImportStage::Stops => Some(GtfsFile::Stops),
+            GtfsImportStage::Routes => Some(GtfsFile::Routes),
+            GtfsImportStage::Calendar => Some(GtfsFile::Calendar),
+            GtfsImportStage::CalendarDates => Some(GtfsFile::CalendarDates),
+            GtfsImportStage::StopTimes => Some(GtfsFile::StopTimes),
+            GtfsImportStage::Trips => Some(GtfsFile::Trips),
+            _ => None,
+        }
+    }
+
+    fn next(&self) -> GtfsImportStage {
+        match self {
+            GtfsImportStage::Stops => GtfsImportStage::Routes,
+            GtfsImportStage::Routes => GtfsImportStage::Calendar,
+            GtfsImportStage::Calendar => GtfsImportStage::CalendarDates,
+            GtfsImportStage::CalendarDates => GtfsImportStage::StopTimes,
+            GtfsImportStage::StopTimes => GtfsImportStage::Trips,
+            GtfsImportStage::Trips => GtfsImportStage::UnusedStopTimes,
+            GtfsImportStage::UnusedStopTimes => GtfsImportStage::CleanUp,
+            GtfsImportStage::CleanUp | GtfsImportStage::Finished => GtfsImportStage::Finished,
+        }
+    }
+}
+
+// Progress of an import between batches. The report's errors are kept in GTFS_IMPORT_ERRORS.
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsImportJob {
+    import_id: u64,
+    bus_ids: Vec<u64>,
+    stage: GtfsImportStage,
+    chunk: u64,  // upload order of the chunk the stage's next record starts in
+    offset: u64, // byte within that chunk where it starts
+    line: u32,   // line of the file it starts on
+    columns: Option<Vec<(String, u64)>>, // positions of the known columns once the header is read
+    report: GtfsImportReport,
+}
+
+impl Storable for GtfsImportJob {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsImportJob {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Rows of one file an import needs while reading another
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) enum GtfsStaged {
+    Route {
+        name: String,
+    },
+    Service {
+        days: Vec<Weekday>,
+        valid_from: u64,
+        valid_until: u64,
+        removed: Vec<u64>,
+        adds_dates: bool, // calendar_dates.txt adds days, which a timetable can't express
+    },
+    Trip {
+        number: u64, // groups the trip's rows in GTFS_STOP_TIMES
+        broken: bool,
+    },
+    RouteUpdated, // counted once however many trips use the route
+}
+
+impl Storable for GtfsStaged {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsStaged {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Stop times are kept per ((import, trip), stop_sequence)
+pub(crate) type GtfsStopTimeKey = ((u64, u64), u64);
+
+// A stop_times.txt row with its stop resolved
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct GtfsStopTime {
+    stop_id: u64,
+    arrival: u64,
+    departure: u64,
+    distance: Option<f64>, // shape_dist_traveled
+}
+
+impl Storable for GtfsStopTime {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for GtfsStopTime {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// A batch of a GTFS file: column positions from the header, then each record with its line
+// A CSV record and the line it starts on
+type CsvRecord = (u32, Vec<String>);
+
+struct GtfsTable {
+    columns: BTreeMap<String, usize>,
+    rows: Vec<CsvRecord>,
+}
+
+impl GtfsTable {
+    // Trimmed value of the column, None when the column is absent or the value empty
+    fn get<'a>(&self, row: &'a [String], column: &str) -> Option<&'a str> {
+        self.columns
+            .get(column)
+            .and_then(|index| row.get(*index))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+#[ic_cdk::update]
+fn start_gtfs_import() -> Result<GtfsImport, Error> {
+    ensure_admin()?;
+    let import = GtfsImport {
+        id: next_id(),
+        started_by: ic_cdk::caller(),
+        started_at: time(),
+        chunks: 0,
+        bytes: 0,
+    };
+    GTFS_IMPORTS.with(|service| service.borrow_mut().insert(import.id, import.clone()));
+    Ok(import)
+}
+
+// Pieces of the same file are joined in the order they are uploaded
+#[ic_cdk::update]
+fn upload_gtfs_chunk(import_id: u64, file: GtfsFile, data: Vec<u8>) -> Result<GtfsImport, Error> {
+    ensure_admin()?;
+    let mut import = _get_gtfs_import(&import_id)?;
+    ensure_gtfs_import_open(import_id)?;
+    if data.is_empty() || data.len() > MAX_GTFS_CHUNK_SIZE {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a chunk must hold between 1 and {} bytes",
+                MAX_GTFS_CHUNK_SIZE
+            ),
+        });
+    }
+    if import.bytes + data.len() as u64 > MAX_GTFS_IMPORT_SIZE {
+        return Err(Error::InvalidInput {
+            msg: format!("an import can be at most {} bytes", MAX_GTFS_IMPORT_SIZE),
+        });
+    }
+    import.bytes += data.len() as u64;
+    GTFS_CHUNKS.with(|service| {
+        service
+            .borrow_mut()
+            .insert((import_id, import.chunks), GtfsChunk { file, data })
+    });
+    import.chunks += 1;
+    GTFS_IMPORTS.with(|service| service.borrow_mut().insert(import_id, import.clone()));
+    Ok(import)
+}
+
+#[ic_cdk::update]
+fn abort_gtfs_import(import_id: u64) -> Result<(), Error> {
+    ensure_admin()?;
+    _get_gtfs_import(&import_id)?;
+    // A running import drops what it has staged on its own timer
+    match _get_gtfs_import_job(&import_id) {
+        Some(mut job) => {
+            job.stage = GtfsImportStage::CleanUp;
+            do_insert_gtfs_import_job(&job);
+            do_schedule_gtfs_batch();
+        }
+        None => do_remove_gtfs_import(import_id),
+    }
+    Ok(())
+}
+
+// Starts importing the uploaded files. Rows with errors are skipped and listed in the report;
+// everything else is imported. Each GTFS trip becomes a timetable with a single daily departure,
+// run by the given bus pool, and trips are generated from it as usual. GTFS times are taken as
+// UTC. The files are read in batches on a timer, so poll `get_gtfs_import_report` until the
+// report is finished.
+#[ic_cdk::update]
+fn finish_gtfs_import(
+    import_id: u64,
+    options: GtfsImportOptions,
+) -> Result<GtfsImportReport, Error> {
+    ensure_admin()?;
+    _get_gtfs_import(&import_id)?;
+    ensure_gtfs_import_open(import_id)?;
+    if options.bus_ids.is_empty() || options.bus_ids.len() > MAX_TIMETABLE_BUSES {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "an import needs between 1 and {} buses",
+                MAX_TIMETABLE_BUSES
+            ),
+        });
+    }
+    if let Some(bus_id) = options
+        .bus_ids
+        .iter()
+        .find(|bus_id| _get_bus(bus_id).is_none())
+    {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", bus_id),
+        });
+    }
+    let job = GtfsImportJob {
+        import_id,
+        bus_ids: options.bus_ids,
+        stage: GtfsImportStage::Stops,
+        chunk: 0,
+        offset: 0,
+        line: 1,
+        columns: None,
+        report: GtfsImportReport {
+            import_id,
+            ..Default::default()
+        },
+    };
+    do_insert_gtfs_import_job(&job);
+    do_schedule_gtfs_batch();
+    Ok(job.report)
+}
+
+#[ic_cdk::query]
+fn get_gtfs_import_report(import_id: u64) -> Result<GtfsImportReport, Error> {
+    ensure_admin()?;
+    let job = _get_gtfs_import_job(&import_id).ok_or_else(|| Error::NotFound {
+        msg: format!("no import with id={} has been finished", import_id),
+    })?;
+    Ok(_get_gtfs_import_report(&job))
+}
+
+// Uploads can't change once the import has started
+fn ensure_gtfs_import_open(import_id: u64) -> Result<(), Error> {
+    if _get_gtfs_import_job(&import_id).is_some() {
+        return Err(Error::Conflict {
+            msg: format!("import with id={} is already being imported", import_id),
+        });
+    }
+    Ok(())
+}
+
+fn _get_gtfs_import(import_id: &u64) -> Result<GtfsImport, Error> {
+    GTFS_IMPORTS
+        .with(|service| service.borrow().get(import_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("an import with id={} not found", import_id),
+        })
+}
+
+fn do_remove_gtfs_import(import_id: u64) {
+    GTFS_IMPORTS.with(|service| service.borrow_mut().remove(&import_id));
+    GTFS_CHUNKS.with(|service| {
+        let mut chunks = service.borrow_mut();
+        let keys: Vec<(u64, u64)> = chunks
+            .range((import_id, 0)..=(import_id, u64::MAX))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            chunks.remove(&key);
+        }
+    });
+}
+
+fn _get_gtfs_import_job(import_id: &u64) -> Option<GtfsImportJob> {
+    GTFS_IMPORT_JOBS.with(|service| service.borrow().get(import_id))
+}
+
+fn do_insert_gtfs_import_job(job: &GtfsImportJob) {
+    GTFS_IMPORT_JOBS.with(|service| service.borrow_mut().insert(job.import_id, job.clone()));
+}
+
+fn _get_gtfs_import_report(job: &GtfsImportJob) -> GtfsImportReport {
+    let errors = GTFS_IMPORT_ERRORS.with(|service| {
+        service
+            .borrow()
+            .range((job.import_id, 0)..=(job.import_id, u64::MAX))
+            .map(|(_, error)| error)
+            .collect()
+    });
+    GtfsImportReport {
+        errors,
+        ..job.report.clone()
+    }
+}
+
+// Each batch runs on a timer of its own and schedules the next, so imports carry on until
+// they're done and start again after an upgrade
+pub(crate) fn do_schedule_gtfs_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        if do_run_gtfs_batch(time()) {
+            do_schedule_gtfs_batch();
+        }
+    });
+}
+
+// Moves the oldest unfinished import on by a batch. Returns false when there was nothing to do.
+fn do_run_gtfs_batch(now: u64) -> bool {
+    let job = GTFS_IMPORT_JOBS.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, job)| job)
+            .find(|job| job.stage != GtfsImportStage::Finished)
+    });
+    let Some(mut job) = job else {
+        return false;
+    };
+    let mut report = _get_gtfs_import_report(&job);
+    let known_errors = report.errors.len();
+    match job.stage.file() {
+        Some(file) => do_import_gtfs_batch(&mut job, file, &mut report, now),
+        None if job.stage == GtfsImportStage::UnusedStopTimes => {
+            do_report_unused_gtfs_stop_times(&mut job, &mut report)
+        }
+        None => do_clean_up_gtfs_import(&mut job, &mut report, now),
+    }
+    GTFS_IMPORT_ERRORS.with(|service| {
+        let mut service = service.borrow_mut();
+        for (index, error) in report.errors.iter().enumerate().skip(known_errors) {
+            service.insert((job.import_id, index as u64), error.clone());
+        }
+    });
+    job.report = GtfsImportReport {
+        errors: Vec::new(),
+        ..report
+    };
+    do_insert_gtfs_import_job(&job);
+    true
+}
+
+fn do_next_gtfs_stage(job: &mut GtfsImportJob) {
+    job.stage = job.stage.next();
+    job.chunk = 0;
+    job.offset = 0;
+    job.line = 1;
+    job.columns = None;
+}
+
+// Reads the header of the stage's file, or the next batch of its records and imports them.
+// Files that are missing or can't be read are reported and skipped.
+fn do_import_gtfs_batch(
+    job: &mut GtfsImportJob,
+    file: GtfsFile,
+    report: &mut GtfsImportReport,
+    now: u64,
+) {
+    let Some(columns) = job.columns.clone() else {
+        match do_read_gtfs_records(job, file, 1) {
+            Ok(Some((header, _))) if !header.is_empty() => {
+                let columns: BTreeMap<String, usize> = header[0]
+                    .1
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| (name.trim().to_string(), index))
+                    .collect();
+                let missing: Vec<&str> = file
+                    .required_columns()
+                    .iter()
+                    .filter(|column| !columns.contains_key(**column))
+                    .copied()
+                    .collect();
+                if !missing.is_empty() {
+                    report.error(file, 1, format!("missing columns: {}", missing.join(", ")));
+                    return do_next_gtfs_stage(job);
+                }
+                // Only the columns the import reads are kept between batches
+                let known = file
+                    .required_columns()
+                    .iter()
+                    .chain(file.optional_columns());
+                job.columns = Some(
+                    known
+                        .filter_map(|column| {
+                            let index = columns.get(*column)?;
+                            Some((column.to_string(), *index as u64))
+                        })
+                        .collect(),
+                );
+            }
+            Ok(Some(_)) => {
+                report.error(file, 0, "the file is empty");
+                do_next_gtfs_stage(job);
+            }
+            Ok(None) => {
+                let optional = matches!(file, GtfsFile::Calendar | GtfsFile::CalendarDates);
+                if !optional {
+                    report.error(file, 0, format!("{} was not uploaded", file.file_name()));
+                }
+                do_next_gtfs_stage(job);
+            }
+            Err(message) => {
+                report.error(file, 0, message);
+                do_next_gtfs_stage(job);
+            }
+        }
+        return;
+    };
+    let limit = match file {
+        GtfsFile::Trips => GTFS_TRIP_BATCH_SIZE,
+        _ => GTFS_BATCH_SIZE,
+    };
+    let (rows, done) = match do_read_gtfs_records(job, file, limit) {
+        Ok(Some(batch)) => batch,
+        Ok(None) => (Vec::new(), true),
+        Err(message) => {
+            report.error(file, 0, message);
+            return do_next_gtfs_stage(job);
+        }
+    };
+    let table = GtfsTable {
+        columns: columns
+            .into_iter()
+            .map(|(name, index)| (name, index as usize))
+            .collect(),
+        rows,
+    };
+    let import_id = job.import_id;
+    match file {
+        GtfsFile::Stops => do_import_gtfs_stops(&table, report, now),
+        GtfsFile::Routes => do_stage_gtfs_routes(import_id, &table, report),
+        GtfsFile::Calendar => do_stage_gtfs_calendar(import_id, &table, report),
+        GtfsFile::CalendarDates => do_stage_gtfs_calendar_dates(import_id, &table, report),
+        GtfsFile::StopTimes => do_stage_gtfs_stop_times(import_id, &table, report),
+        GtfsFile::Trips => do_import_gtfs_trips(job, &table, report, now),
+    }
+    if done {
+        do_next_gtfs_stage(job);
+    }
+}
+
+// Up to `limit` records of `file` from where the job has got to, moving it past them, and
+// whether the file ends there. None if the file has no data from there on.
+fn do_read_gtfs_records(
+    job: &mut GtfsImportJob,
+    file: GtfsFile,
+    limit: usize,
+) -> Result<Option<(Vec<CsvRecord>, bool)>, String> {
+    GTFS_CHUNKS.with(|service| {
+        let service = service.borrow();
+        let mut chunks = service
+            .range((job.import_id, job.chunk)..=(job.import_id, u64::MAX))
+            .filter(|(_, chunk)| chunk.file == file);
+        let mut buffer: Vec<u8> = Vec::new();
+        // Where each chunk read starts in `buffer`: (upload order, position, bytes skipped)
+        let mut pieces: Vec<(u64, usize, usize)> = Vec::new();
+        loop {
+            let next = chunks.next();
+            let complete = next.is_none();
+            match next {
+                Some(((_, order), chunk)) => {
+                    let skip = if order == job.chunk {
+                        (job.offset as usize).min(chunk.data.len())
+                    } else {
+                        0
+                    };
+                    pieces.push((order, buffer.len(), skip));
+                    buffer.extend_from_slice(&chunk.data[skip..]);
+                }
+                None if pieces.is_empty() => return Ok(None),
+                None => {}
+            }
+            let text = match std::str::from_utf8(&buffer) {
+                Ok(text) => text,
+                // A character split between chunks is completed by the next one
+                Err(error) if !complete && error.error_len().is_none() => {
+                    std::str::from_utf8(&buffer[..error.valid_up_to()]).unwrap_or_default()
+                }
+                Err(_) => return Err("the file is not valid UTF-8".to_string()),
+            };
+            let (records, consumed, next_line) = parse_csv(text, job.line, limit, complete);
+            if records.len() < limit && !complete {
+                continue;
+            }
+            let done = complete && consumed == text.len();
+            let (order, start, skip) = pieces
+                .iter()
+                .rev()
+                .find(|(_, start, _)| *start <= consumed)
+                .copied()
+                .unwrap_or(pieces[0]);
+            job.chunk = order;
+            job.offset = (consumed - start + skip) as u64;
+            job.line = next_line;
+            return Ok(Some((records, done)));
+        }
+    })
+}
+
+fn _get_gtfs_staged(import_id: u64, key: &StringKey) -> Option<GtfsStaged> {
+    GTFS_STAGING.with(|service| service.borrow().get(&(import_id, key.clone())))
+}
+
+fn do_stage_gtfs(import_id: u64, key: StringKey, staged: GtfsStaged) {
+    GTFS_STAGING.with(|service| service.borrow_mut().insert((import_id, key), staged));
+}
+
+// The stop a GTFS stop_id was imported as, by this import or an earlier one
+fn _get_gtfs_stop(gtfs_id: &str) -> Option<Stop> {
+    let key = gtfs_key("stop", gtfs_id)?;
+    GTFS_IDS
+        .with(|service| service.borrow().get(&key))
+        .and_then(|id| _get_stop(&id))
+}
+
+// Upserts stops.txt rows. Stations and other entries with a location_type other than 0 are not
+// stops buses call at and are skipped.
+fn do_import_gtfs_stops(table: &GtfsTable, report: &mut GtfsImportReport, now: u64) {
+    for (line, row) in &table.rows {
+        let line = *line;
+        if table
+            .get(row, "location_type")
+            .is_some_and(|location_type| location_type != "0")
+        {
+            continue;
+        }
+        let (Some(gtfs_id), Some(name)) = (table.get(row, "stop_id"), table.get(row, "stop_name"))
+        else {
+            report.error(GtfsFile::Stops, line, "stop_id and stop_name are required");
+            continue;
+        };
+        if name.len() > MAX_GTFS_NAME_LEN {
+            report.error(
+                GtfsFile::Stops,
+                line,
+                format!("stop_name can be at most {} bytes long", MAX_GTFS_NAME_LEN),
+            );
+            continue;
+        }
+        let coordinates = (
+            table
+                .get(row, "stop_lat")
+                .and_then(|lat| lat.parse::<f64>().ok()),
+            table
+                .get(row, "stop_lon")
+                .and_then(|lon| lon.parse::<f64>().ok()),
+        );
+        let (Some(latitude), Some(longitude)) = coordinates else {
+            report.error(
+                GtfsFile::Stops,
+                line,
+                "stop_lat and stop_lon must be numbers",
+            );
+            continue;
+        };
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            report.error(GtfsFile::Stops, line, "stop coordinates are out of range");
+            continue;
+        }
+        let Some(key) = gtfs_key("stop", gtfs_id) else {
+            report.error(GtfsFile::Stops, line, "stop_id is too long");
+            continue;
+        };
+        let stop = match _get_gtfs_stop(gtfs_id) {
+            Some(stop) => {
+                report.stops_updated += 1;
+                Stop {
+                    name: name.to_string(),
+                    latitude,
+                    longitude,
+                    ..stop
+                }
+            }
+            None => {
+                report.stops_created += 1;
+                Stop {
+                    id: next_id(),
+                    name: name.to_string(),
+                    latitude,
+                    longitude,
+                    created_at: now,
+                }
+            }
+        };
+        STOP_STORAGE.with(|service| service.borrow_mut().insert(stop.id, stop.clone()));
+        GTFS_IDS.with(|service| service.borrow_mut().insert(key, stop.id));
+    }
+}
+
+// Keeps the name of each GTFS route_id for the trips that run on it
+fn do_stage_gtfs_routes(import_id: u64, table: &GtfsTable, report: &mut GtfsImportReport) {
+    for (line, row) in &table.rows {
+        let Some(gtfs_id) = table.get(row, "route_id") else {
+            report.error(GtfsFile::Routes, *line, "route_id is required");
+            continue;
+        };
+        let name = table
+            .get(row, "route_long_name")
+            .or_else(|| table.get(row, "route_short_name"));
+        let Some(name) = name else {
+            report.error(
+                GtfsFile::Routes,
+                *line,
+                "either route_short_name or route_long_name is required",
+            );
+            continue;
+        };
+        if name.len() > MAX_GTFS_NAME_LEN {
+            report.error(
+                GtfsFile::Routes,
+                *line,
+                format!(
+                    "route names can be at most {} bytes long",
+                    MAX_GTFS_NAME_LEN
+                ),
+            );
+            continue;
+        }
+        let Some(key) = gtfs_key("route", gtfs_id) else {
+            report.error(GtfsFile::Routes, *line, "route_id is too long");
+            continue;
+        };
+        let name = name.to_string();
+        do_stage_gtfs(import_id, key, GtfsStaged::Route { name });
+    }
+}
+
+// Keeps each calendar.txt service as a weekly pattern
+fn do_stage_gtfs_calendar(import_id: u64, table: &GtfsTable, report: &mut GtfsImportReport) {
+    const DAYS: [(&str, Weekday); 7] = [
+        ("monday", Weekday::Monday),
+        ("tuesday", Weekday::Tuesday),
+        ("wednesday", Weekday::Wednesday),
+        ("thursday", Weekday::Thursday),
+        ("friday", Weekday::Friday),
+        ("saturday", Weekday::Saturday),
+        ("sunday", Weekday::Sunday),
+    ];
+    for (line, row) in &table.rows {
+        let Some(service_id) = table.get(row, "service_id") else {
+            report.error(GtfsFile::Calendar, *line, "service_id is required");
+            continue;
+        };
+        let flags: Option<Vec<bool>> = DAYS
+            .iter()
+            .map(|(column, _)| match table.get(row, column) {
+                Some("1") => Some(true),
+                Some("0") => Some(false),
+                _ => None,
+            })
+            .collect();
+        let Some(flags) = flags else {
+            report.error(GtfsFile::Calendar, *line, "day columns must be 0 or 1");
+            continue;
+        };
+        let dates = (
+            table.get(row, "start_date").and_then(parse_gtfs_date),
+            table.get(row, "end_date").and_then(parse_gtfs_date),
+        );
+        let (Some(start), Some(end)) = dates else {
+            report.error(
+                GtfsFile::Calendar,
+                *line,
+                "start_date and end_date must be dates in YYYYMMDD form",
+            );
+            continue;
+        };
+        if end < start {
+            report.error(GtfsFile::Calendar, *line, "end_date is before start_date");
+            continue;
+        }
+        let Some(key) = gtfs_key("service", service_id) else {
+            report.error(GtfsFile::Calendar, *line, "service_id is too long");
+            continue;
+        };
+        let days = DAYS
+            .iter()
+            .zip(flags)
+            .filter(|(_, runs)| *runs)
+            .map(|((_, day), _)| *day)
+            .collect();
+        do_stage_gtfs(
+            import_id,
+            key,
+            GtfsStaged::Service {
+                days,
+                valid_from: start,
+                valid_until: end + NANOS_PER_DAY,
+                removed: Vec::new(),
+                adds_dates: false,
+            },
+        );
+    }
+}
+
+// Applies calendar_dates.txt to the weekly services. Removed days become timetable exceptions.
+// Added days can't be expressed as a timetable, so services that add any are not imported.
+fn do_stage_gtfs_calendar_dates(import_id: u64, table: &GtfsTable, report: &mut GtfsImportReport) {
+    for (line, row) in &table.rows {
+        let fields = (
+            table.get(row, "service_id"),
+            table.get(row, "date").and_then(parse_gtfs_date),
+            table.get(row, "exception_type"),
+        );
+        let (Some(service_id), Some(date), Some(exception_type @ ("1" | "2"))) = fields else {
+            report.error(
+                GtfsFile::CalendarDates,
+                *line,
+                "needs a service_id, a YYYYMMDD date and an exception_type of 1 or 2",
+            );
+            continue;
+        };
+        let key = gtfs_key("service", service_id);
+        let service = key
+            .as_ref()
+            .and_then(|key| _get_gtfs_staged(import_id, key));
+        let (Some(key), Some(GtfsStaged::Service { mut removed, .. })) = (key, service.clone())
+        else {
+            report.error(
+                GtfsFile::CalendarDates,
+                *line,
+                format!("service_id {} is not in calendar.txt", service_id),
+            );
+            continue;
+        };
+        let Some(GtfsStaged::Service {
+            days,
+            valid_from,
+            valid_until,
+            adds_dates,
+            ..
+        }) = service
+        else {
+            continue;
+        };
+        if exception_type == "1" {
+            report.error(
+                GtfsFile::CalendarDates,
+                *line,
+                "added service dates are not supported, so trips on the service are skipped",
+            );
+        } else if removed.len() >= MAX_TIMETABLE_EXCEPTIONS {
+            report.error(
+                GtfsFile::CalendarDates,
+                *line,
+                format!(
+                    "a service can have at most {} removed dates",
+                    MAX_TIMETABLE_EXCEPTIONS
+                ),
+            );
+            continue;
+        } else {
+            removed.push(date);
+        }
+        do_stage_gtfs(
+            import_id,
+            key,
+            GtfsStaged::Service {
+                days,
+                valid_from,
+                valid_until,
+                removed,
+                adds_dates: adds_dates || exception_type == "1",
+            },
+        );
+    }
+}
+
+// Keeps each trip's stop times, ordered by stop_sequence. A trip with a bad stop time is marked
+// broken and skipped when trips.txt is read.
+fn do_stage_gtfs_stop_times(import_id: u64, table: &GtfsTable, report: &mut GtfsImportReport) {
+    for (line, row) in &table.rows {
+        let Some(trip_id) = table.get(row, "trip_id") else {
+            report.error(GtfsFile::StopTimes, *line, "trip_id is required");
+            continue;
+        };
+        let Some(key) = gtfs_key("trip", trip_id) else {
+            report.error(GtfsFile::StopTimes, *line, "trip_id is too long");
+            continue;
+        };
+        let (number, broken) = match _get_gtfs_staged(import_id, &key) {
+            Some(GtfsStaged::Trip { number, broken }) => (number, broken),
+            _ => (next_id(), false),
+        };
+        let fields = (
+            table
+                .get(row, "stop_sequence")
+                .and_then(|sequence| sequence.parse::<u32>().ok()),
+            table.get(row, "stop_id").and_then(_get_gtfs_stop),
+            table.get(row, "arrival_time").and_then(parse_gtfs_time),
+            table.get(row, "departure_time").and_then(parse_gtfs_time),
+        );
+        let message = match fields {
+            (Some(sequence), Some(stop), Some(arrival), Some(departure)) => {
+                let stop_key = ((import_id, number), u64::from(sequence));
+                let repeated =
+                    GTFS_STOP_TIMES.with(|service| service.borrow().contains_key(&stop_key));
+                if repeated {
+                    "stop_sequence repeats an earlier stop time of the trip"
+                } else {
+                    let stop_time = GtfsStopTime {
+                        stop_id: stop.id,
+                        arrival,
+                        departure,
+                        distance: table
+                            .get(row, "shape_dist_traveled")
+                            .and_then(|distance| distance.parse::<f64>().ok()),
+                    };
+                    GTFS_STOP_TIMES
+                        .with(|service| service.borrow_mut().insert(stop_key, stop_time));
+                    do_stage_gtfs(import_id, key, GtfsStaged::Trip { number, broken });
+                    continue;
+                }
+            }
+            (None, ..) => "stop_sequence must be a non-negative integer",
+            (_, None, ..) => "stop_id is not an imported stop",
+            // Untimed stops would need interpolating, which isn't supported
+            _ => "arrival_time and departure_time must be given as HH:MM:SS",
+        };
+        report.error(GtfsFile::StopTimes, *line, message);
+        do_stage_gtfs(
+            import_id,
+            key,
+            GtfsStaged::Trip {
+                number,
+                broken: true,
+            },
+        );
+    }
+}
+
+// Turns each trip's stop times into a route (shared by trips with the same stop pattern) and a
+// timetable for the trip's service
+fn do_import_gtfs_trips(
+    job: &GtfsImportJob,
+    table: &GtfsTable,
+    report: &mut GtfsImportReport,
+    now: u64,
+) {
+    let import_id = job.import_id;
+    for (line, row) in &table.rows {
+        let line = *line;
+        let fields = (
+            table.get(row, "trip_id"),
+            table.get(row, "route_id"),
+            table.get(row, "service_id"),
+        );
+        let (Some(trip_id), Some(gtfs_route_id), Some(service_id)) = fields else {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                "trip_id, route_id and service_id are required",
+            );
+            continue;
+        };
+        let Some(timetable_key) = gtfs_key("trip", trip_id) else {
+            report.error(GtfsFile::Trips, line, "trip_id is too long");
+            continue;
+        };
+        // Whatever happens to the trip, its stop times have been used
+        let staged = _get_gtfs_staged(import_id, &timetable_key);
+        GTFS_STAGING.with(|service| {
+            service
+                .borrow_mut()
+                .remove(&(import_id, timetable_key.clone()))
+        });
+        let number = match staged {
+            Some(GtfsStaged::Trip { broken: true, .. }) => {
+                report.error(
+                    GtfsFile::Trips,
+                    line,
+                    "skipped because of errors in its stop times",
+                );
+                continue;
+            }
+            Some(GtfsStaged::Trip { number, .. }) => number,
+            _ => {
+                report.error(GtfsFile::Trips, line, "the trip has no stop times");
+                continue;
+            }
+        };
+        let route_name =
+            gtfs_key("route", gtfs_route_id).and_then(|key| _get_gtfs_staged(import_id, &key));
+        let Some(GtfsStaged::Route { name: route_name }) = route_name else {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                format!("route_id {} is not an imported route", gtfs_route_id),
+            );
+            continue;
+        };
+        let service =
+            gtfs_key("service", service_id).and_then(|key| _get_gtfs_staged(import_id, &key));
+        let Some(GtfsStaged::Service {
+            days,
+            valid_from,
+            valid_until,
+            removed,
+            adds_dates,
+        }) = service
+        else {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                format!("service_id {} has no weekly calendar", service_id),
+            );
+            continue;
+        };
+        if adds_dates {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                format!(
+                    "service_id {} adds dates in calendar_dates.txt, which isn't supported",
+                    service_id
+                ),
+            );
+            continue;
+        }
+        let stop_times: Vec<GtfsStopTime> = GTFS_STOP_TIMES.with(|service| {
+            service
+                .borrow()
+                .range(((import_id, number), 0)..=((import_id, number), u64::MAX))
+                .map(|(_, stop_time)| stop_time)
+                .collect()
+        });
+        if stop_times.len() > MAX_ROUTE_STOPS {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                format!("a trip can call at most at {} stops", MAX_ROUTE_STOPS),
+            );
+            continue;
+        }
+        let trip_calls: Option<Vec<(Stop, &GtfsStopTime)>> = stop_times
+            .iter()
+            .map(|stop_time| Some((_get_stop(&stop_time.stop_id)?, stop_time)))
+            .collect();
+        let Some(trip_calls) = trip_calls else {
+            report.error(GtfsFile::Trips, line, "a stop of the trip no longer exists");
+            continue;
+        };
+        let Some(start) = trip_calls.first().map(|(_, stop_time)| stop_time.departure) else {
+            report.error(GtfsFile::Trips, line, "the trip has no stop times");
+            continue;
+        };
+        if start >= NANOS_PER_DAY {
+            report.error(
+                GtfsFile::Trips,
+                line,
+                "trips leaving after midnight of their service day are not supported",
+            );
+            continue;
+        }
+        let use_shape = trip_calls
+            .iter()
+            .all(|(_, stop_time)| stop_time.distance.is_some());
+        let first_distance = trip_calls[0].1.distance.unwrap_or(0.0);
+        let mut travelled = 0.0;
+        let mut route_stops = Vec::new();
+        for (index, (stop, stop_time)) in trip_calls.iter().enumerate() {
+            if !use_shape && index > 0 {
+                let previous = &trip_calls[index - 1].0;
+                travelled += haversine_m(
+                    previous.latitude,
+                    previous.longitude,
+                    stop.latitude,
+                    stop.longitude,
+                );
+            }
+            let distance_m = match stop_time.distance {
+                Some(distance) if use_shape => (distance - first_distance).max(0.0),
+                _ => travelled,
+            };
+            route_stops.push(RouteStop {
+                stop_id: stop.id,
+                distance_m: distance_m.round() as u64,
+                arrival_offset: if index == 0 {
+                    0
+                } else {
+                    stop_time.arrival.saturating_sub(start)
+                },
+                departure_offset: stop_time.departure.saturating_sub(start),
+            });
+        }
+        if let Err(error) = validate_route_stops(&route_stops) {
+            report.error(GtfsFile::Trips, line, error_message(error));
+            continue;
+        }
+
+        // Trips with the same route_id and stop pattern share a route
+        let pattern = route_stops
+            .iter()
+            .map(|stop| {
+                format!(
+                    "{}:{}:{}:{}",
+                    stop.stop_id, stop.distance_m, stop.arrival_offset, stop.departure_offset
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let route_key = StringKey(format!(
+            "route/{:016x}",
+            fnv1a_hash(format!("{}|{}", gtfs_route_id, pattern).as_bytes())
+        ));
+        let existing = GTFS_IDS
+            .with(|service| service.borrow().get(&route_key))
+            .and_then(|id| _get_route(&id));
+        let route = match existing {
+            Some(mut route) => {
+                let seen_key = StringKey(format!("seen/{}", route.id));
+                if _get_gtfs_staged(import_id, &seen_key).is_none() {
+                    do_stage_gtfs(import_id, seen_key, GtfsStaged::RouteUpdated);
+                    report.routes_updated += 1;
+                    route.name = route_name;
+                    route.updated_at = Some(now);
+                    do_insert_route(&route);
+                }
+                route
+            }
+            None => {
+                report.routes_created += 1;
+                let route = Route {
+                    id: next_id(),
+                    name: route_name,
+                    stops: route_stops,
+                    created_at: now,
+                    updated_at: None,
+                };
+                let seen_key = StringKey(format!("seen/{}", route.id));
+                do_stage_gtfs(import_id, seen_key, GtfsStaged::RouteUpdated);
+                do_insert_route(&route);
+                GTFS_IDS.with(|service| service.borrow_mut().insert(route_key, route.id));
+                route
+            }
+        };
+
+        let payload = TimetablePayload {
+            route_id: route.id,
+            departure_times: vec![start],
+            days_of_week: days,
+            valid_from,
+            valid_until,
+            bus_ids: job.bus_ids.clone(),
+            exceptions: removed,
+        };
+        if let Err(error) = validate_timetable_payload(&payload) {
+            report.error(GtfsFile::Trips, line, error_message(error));
+            continue;
+        }
+        let existing = GTFS_IDS
+            .with(|service| service.borrow().get(&timetable_key))
+            .and_then(|id| _get_timetable(&id));
+        match existing {
+            Some(mut timetable) => {
+                report.timetables_updated += 1;
+                do_update_timetable(&mut timetable, payload, now);
+            }
+            None => {
+                report.timetables_created += 1;
+                let timetable = do_create_timetable(payload);
+                GTFS_IDS.with(|service| service.borrow_mut().insert(timetable_key, timetable.id));
+            }
+        }
+    }
+}
+
+// Trips still staged once trips.txt has been read had stop times but weren't listed in it
+fn do_report_unused_gtfs_stop_times(job: &mut GtfsImportJob, report: &mut GtfsImportReport) {
+    let import_id = job.import_id;
+    let unused: Vec<StringKey> = GTFS_STAGING.with(|service| {
+        service
+            .borrow()
+            .range(
+                (import_id, StringKey("trip/".to_string()))
+                    ..(import_id, StringKey("trip0".to_string())),
+            )
+            .take(GTFS_BATCH_SIZE)
+            .map(|((_, key), _)| key)
+            .collect()
+    });
+    if unused.len() < GTFS_BATCH_SIZE {
+        do_next_gtfs_stage(job);
+    }
+    for key in unused {
+        report.error(
+            GtfsFile::StopTimes,
+            0,
+            format!("trip_id {} is not in trips.txt", &key.0["trip/".len()..]),
+        );
+        GTFS_STAGING.with(|service| service.borrow_mut().remove(&(import_id, key)));
+    }
+}
+
+// Drops what the import staged and uploaded a batch at a time, then generates the trips of the
+// imported timetables
+fn do_clean_up_gtfs_import(job: &mut GtfsImportJob, report: &mut GtfsImportReport, now: u64) {
+    let import_id = job.import_id;
+    let staged: Vec<(u64, StringKey)> = GTFS_STAGING.with(|service| {
+        service
+            .borrow()
+            .range((import_id, StringKey::default())..(import_id + 1, StringKey::default()))
+            .take(GTFS_BATCH_SIZE)
+            .map(|(key, _)| key)
+            .collect()
+    });
+    if !staged.is_empty() {
+        GTFS_STAGING.with(|service| {
+            let mut service = service.borrow_mut();
+            for key in staged {
+                service.remove(&key);
+            }
+        });
+        return;
+    }
+    let stop_times: Vec<GtfsStopTimeKey> = GTFS_STOP_TIMES.with(|service| {
+        service
+            .borrow()
+            .range(((import_id, 0), 0)..((import_id + 1, 0), 0))
+            .take(GTFS_BATCH_SIZE)
+            .map(|(key, _)| key)
+            .collect()
+    });
+    if !stop_times.is_empty() {
+        GTFS_STOP_TIMES.with(|service| {
+            let mut service = service.borrow_mut();
+            for key in stop_times {
+                service.remove(&key);
+            }
+        });
+        return;
+    }
+    do_remove_gtfs_import(import_id);
+    do_run_timetables(now);
+    job.stage = GtfsImportStage::Finished;
+    report.finished = true;
+}
+
+// Index key for a GTFS identifier; None if it doesn't fit a StringKey
+fn gtfs_key(kind: &str, gtfs_id: &str) -> Option<StringKey> {
+    let key = format!("{}/{}", kind, gtfs_id);
+    (key.len() <= StringKey::MAX_SIZE as usize).then_some(StringKey(key))
+}
+
+// Splits CSV text into up to `limit` records, handling quoted fields (with "" escapes and line
+// breaks inside quotes), CRLF line endings and, at the start of a file, a byte order mark. Blank
+// lines are dropped. Each record comes with its line, counting from `first_line`. Unless the
+// text is `complete`, a record without its line break may be cut short and is left for later.
+// Also returns how many bytes the records took up and the line that follows them.
+fn parse_csv(
+    text: &str,
+    first_line: u32,
+    limit: usize,
+    complete: bool,
+) -> (Vec<CsvRecord>, usize, u32) {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = first_line;
+    let mut record_line = first_line;
+    let mut consumed = 0;
+    let mut consumed_line = first_line;
+    let start = if first_line == 1 && text.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    };
+    let mut chars = text[start..]
+        .char_indices()
+        .map(|(index, c)| (start + index, c))
+        .peekable();
+    while records.len() < limit {
+        let Some((index, c)) = chars.next() else {
+            break;
+        };
+        match c {
+            '"' if in_quotes && chars.peek().map(|(_, c)| *c) == Some('"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].trim().is_empty() {
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                record.clear();
+                line += 1;
+                record_line = line;
+                consumed = index + 1;
+                consumed_line = line;
+            }
+            '\n' => {
+                field.push(c);
+                line += 1;
+            }
+            _ => field.push(c),
+        }
+    }
+    if complete && records.len() < limit {
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push((record_line, record));
+        }
+        consumed = text.len();
+        consumed_line = line;
+    }
+    (records, consumed, consumed_line)
+}
+
+// YYYYMMDD to the start of that day
+fn parse_gtfs_date(value: &str) -> Option<u64> {
+    if value.len() != 8 || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = value[..4].parse().ok()?;
+    let month: u32 = value[4..6].parse().ok()?;
+    let day: u32 = value[6..].parse().ok()?;
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) as u64 * NANOS_PER_DAY)
+}
+
+// H:MM:SS after midnight of the service day; hours may go past 24
+fn parse_gtfs_time(value: &str) -> Option<u64> {
+    let mut parts = value.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || hours >= MAX_GTFS_TIME_HOURS || minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    hours
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1_000_000_000)
+}
+
+// 64-bit FNV-1a; unlike std's hasher it is stable across compiler versions
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gtfs_time_accepts_times_past_midnight_only() {
+        assert_eq!(
+            parse_gtfs_time("8:05:09"),
+            Some((8 * 3600 + 5 * 60 + 9) * 1_000_000_000)
+        );
+        assert_eq!(parse_gtfs_time("25:00:00"), Some(25 * 3600 * 1_000_000_000));
+        assert_eq!(parse_gtfs_time("12:60:00"), None);
+        assert_eq!(parse_gtfs_time("12:00"), None);
+        assert_eq!(parse_gtfs_time("12:00:00:00"), None);
+        assert_eq!(parse_gtfs_time("168:00:00"), None);
+        assert_eq!(parse_gtfs_time("18446744073709551615:00:00"), None);
+    }
+
+    #[test]
+    fn parse_csv_splits_records_in_batches() {
+        let text = "\u{feff}id,name\r\n1,\"Main \"\"Street\"\"\"\r\n\r\n2,\"two\nlines\"\n3,last";
+        let (records, consumed, line) = parse_csv(text, 1, 10, true);
+        assert_eq!(consumed, text.len());
+        assert_eq!(line, 6);
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["id".to_string(), "name".to_string()]),
+                (2, vec!["1".to_string(), "Main \"Street\"".to_string()]),
+                (4, vec!["2".to_string(), "two\nlines".to_string()]),
+                (6, vec!["3".to_string(), "last".to_string()]),
+            ]
+        );
+
+        // A batch stops after its limit and picks up where it left off
+        let (first, consumed, line) = parse_csv(text, 1, 2, true);
+        assert_eq!(first, records[..2]);
+        let (rest, _, _) = parse_csv(&text[consumed..], line, 10, true);
+        assert_eq!(rest, records[2..]);
+
+        // A record without its line break waits for more text
+        let (records, consumed, line) = parse_csv("a,b\nc,\"d\n", 1, 10, false);
+        assert_eq!(records, vec![(1, vec!["a".to_string(), "b".to_string()])]);
+        assert_eq!((consumed, line), (4, 2));
+
+        // Only the start of a file has a byte order mark
+        let (records, _, _) = parse_csv("\u{feff}x", 3, 10, true);
+        assert_eq!(records, vec![(3, vec!["\u{feff}x".to_string()])]);
+    }
+
+    #[test]
+    fn reads_gtfs_records_across_chunks() {
+        let text = "stop_id,stop_name\ns1,Gare \u{e9}st\ns2,Quai\n";
+        let split = text.find('\u{e9}').unwrap() + 1;
+        GTFS_CHUNKS.with(|service| {
+            let mut service = service.borrow_mut();
+            let chunk = |file, data: &[u8]| GtfsChunk {
+                file,
+                data: data.to_vec(),
+            };
+            service.insert((7, 0), chunk(GtfsFile::Stops, &text.as_bytes()[..split]));
+            service.insert((7, 1), chunk(GtfsFile::Routes, b"route_id\n"));
+            service.insert((7, 2), chunk(GtfsFile::Stops, &text.as_bytes()[split..]));
+        });
+        let mut job = GtfsImportJob {
+            import_id: 7,
+            bus_ids: vec![1],
+            stage: GtfsImportStage::Stops,
+            chunk: 0,
+            offset: 0,
+            line: 1,
+            columns: None,
+            report: GtfsImportReport::default(),
+        };
+
+        let (header, done) = do_read_gtfs_records(&mut job, GtfsFile::Stops, 1)
+            .ok()
+            .flatten()
+            .unwrap();
+        assert_eq!(header[0].1, vec!["stop_id", "stop_name"]);
+        assert!(!done);
+        let (rows, done) = do_read_gtfs_records(&mut job, GtfsFile::Stops, 1)
+            .ok()
+            .flatten()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![(2, vec!["s1".to_string(), "Gare \u{e9}st".to_string()])]
+        );
+        assert!(!done);
+        assert_eq!(job.chunk, 2);
+        let (rows, done) = do_read_gtfs_records(&mut job, GtfsFile::Stops, 10)
+            .ok()
+            .flatten()
+            .unwrap();
+        assert_eq!(rows, vec![(3, vec!["s2".to_string(), "Quai".to_string()])]);
+        assert!(done);
+    }
+}
@@ -0,0 +1,337 @@
+use super::*;
+
+// Live status of today's trips as a GTFS-Realtime TripUpdates feed. Cancelled trips are marked
+// CANCELED; delayed and running trips carry the reported delay and the actual times drivers
+// recorded at stops. Ids match the static feed served under /gtfs/.
+pub(crate) fn build_trip_updates_feed(now: u64) -> Vec<u8> {
+    let mut feed = gtfs_rt_feed_header(now);
+    for trip in _get_realtime_trips(now) {
+        let cancelled = trip.status == TripStatus::Cancelled;
+        if !cancelled && trip.status != TripStatus::InProgress && trip.delay_minutes == 0 {
+            continue;
+        }
+        let route = trip.route_id.and_then(|route_id| _get_route(&route_id));
+        let mut update = Proto::default();
+        update.message(1, gtfs_rt_trip_descriptor(&trip, cancelled));
+        if !cancelled {
+            let delay = i64::from(trip.delay_minutes).saturating_mul(60);
+            let recorded: Vec<TripEvent> = _get_trip_events(trip.id)
+                .into_iter()
+                .filter(|event| {
+                    event.stop_id.is_some()
+                        && matches!(event.kind, TripEventKind::Departed | TripEventKind::Arrived)
+                })
+                .collect();
+            if let Some(route) = &route {
+                // The delay at the first stop lets consumers propagate it down the trip
+                if recorded.is_empty() {
+                    let mut departure = Proto::default();
+                    departure.int(1, delay);
+                    let mut stop_time = Proto::default();
+                    stop_time
+                        .uint(1, 1)
+                        .message(3, departure)
+                        .string(4, &route.stops[0].stop_id.to_string());
+                    update.message(2, stop_time);
+                }
+                for stop_time in gtfs_rt_stop_time_updates(&trip, route, &recorded) {
+                    update.message(2, stop_time);
+                }
+            }
+            update.int(5, delay);
+        }
+        update
+            .message(3, gtfs_rt_vehicle_descriptor(trip.bus_id))
+            .uint(
+                4,
+                trip.updated_at.unwrap_or(trip.created_at) / 1_000_000_000,
+            );
+        let mut entity = Proto::default();
+        entity
+            .string(1, &format!("trip-{}", trip.id))
+            .message(3, update);
+        feed.message(2, entity);
+    }
+    feed.0
+}
+
+// A StopTimeUpdate for each stop with recorded times, in stop order, carrying the latest
+// arrival and departure recorded there
+fn gtfs_rt_stop_time_updates(trip: &Trip, route: &Route, events: &[TripEvent]) -> Vec<Proto> {
+    let mut recorded: BTreeMap<usize, (Option<u64>, Option<u64>)> = BTreeMap::new();
+    for event in events {
+        let Some(position) = route
+            .stops
+            .iter()
+            .position(|route_stop| Some(route_stop.stop_id) == event.stop_id)
+        else {
+            continue;
+        };
+        let times = recorded.entry(position).or_default();
+        match event.kind {
+            TripEventKind::Arrived => times.0 = Some(event.recorded_at),
+            TripEventKind::Departed => times.1 = Some(event.recorded_at),
+            _ => {}
+        }
+    }
+    recorded
+        .into_iter()
+        .map(|(position, (arrival, departure))| {
+            let route_stop = &route.stops[position];
+            let mut stop_time = Proto::default();
+            stop_time.uint(1, position as u64 + 1);
+            if let Some(recorded_at) = arrival {
+                let scheduled = trip.departure_time + route_stop.arrival_offset;
+                stop_time.message(2, gtfs_rt_stop_time_event(scheduled, recorded_at));
+            }
+            if let Some(recorded_at) = departure {
+                let scheduled = trip.departure_time + route_stop.departure_offset;
+                stop_time.message(3, gtfs_rt_stop_time_event(scheduled, recorded_at));
+            }
+            stop_time.string(4, &route_stop.stop_id.to_string());
+            stop_time
+        })
+        .collect()
+}
+
+// The delay against the schedule and the recorded time, both in seconds
+fn gtfs_rt_stop_time_event(scheduled: u64, recorded_at: u64) -> Proto {
+    let mut stop_event = Proto::default();
+    stop_event
+        .int(1, (recorded_at as i64 - scheduled as i64) / 1_000_000_000)
+        .int(2, (recorded_at / 1_000_000_000) as i64);
+    stop_event
+}
+
+// The latest reported position of every trip under way as a GTFS-Realtime VehiclePositions feed
+pub(crate) fn build_vehicle_positions_feed(now: u64) -> Vec<u8> {
+    let mut feed = gtfs_rt_feed_header(now);
+    for trip in _get_realtime_trips(now) {
+        if trip.status != TripStatus::InProgress {
+            continue;
+        }
+        let Some(latest) = _get_recent_positions(trip.id).pop() else {
+            continue;
+        };
+        let mut position = Proto::default();
+        position
+            .float(1, latest.latitude as f32)
+            .float(2, latest.longitude as f32);
+        if let Some(heading) = latest.heading {
+            position.float(3, heading as f32);
+        }
+        if let Some(speed) = latest.speed_mps {
+            position.float(5, speed as f32);
+        }
+        let mut vehicle = Proto::default();
+        vehicle
+            .message(1, gtfs_rt_trip_descriptor(&trip, false))
+            .message(2, position)
+            .uint(5, latest.recorded_at / 1_000_000_000)
+            .message(8, gtfs_rt_vehicle_descriptor(trip.bus_id));
+        let mut entity = Proto::default();
+        entity
+            .string(1, &format!("vehicle-{}", trip.id))
+            .message(4, vehicle);
+        feed.message(2, entity);
+    }
+    feed.0
+}
+
+// Trips departing between the start of today and a day from now
+fn _get_realtime_trips(now: u64) -> Vec<Trip> {
+    let today = now / NANOS_PER_DAY * NANOS_PER_DAY;
+    TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, trip)| trip)
+            .filter(|trip| {
+                trip.route_id.is_some()
+                    && trip.status != TripStatus::Completed
+                    && trip.departure_time >= today
+                    && trip.departure_time < now + NANOS_PER_DAY
+            })
+            .collect()
+    })
+}
+
+// FeedMessage with a full-dataset header; entities are appended as field 2
+fn gtfs_rt_feed_header(now: u64) -> Proto {
+    let mut header = Proto::default();
+    header
+        .string(1, "2.0")
+        .uint(2, 0)
+        .uint(3, now / 1_000_000_000);
+    let mut feed = Proto::default();
+    feed.message(1, header);
+    feed
+}
+
+fn gtfs_rt_trip_descriptor(trip: &Trip, cancelled: bool) -> Proto {
+    let mut descriptor = Proto::default();
+    descriptor
+        .string(1, &trip.id.to_string())
+        .string(2, &format_gtfs_time(trip.departure_time % NANOS_PER_DAY))
+        .string(3, &format_gtfs_date(trip.departure_time / NANOS_PER_DAY))
+        // 0 is SCHEDULED and 3 CANCELED
+        .uint(4, if cancelled { 3 } else { 0 });
+    if let Some(route_id) = trip.route_id {
+        descriptor.string(5, &route_id.to_string());
+    }
+    descriptor
+}
+
+fn gtfs_rt_vehicle_descriptor(bus_id: u64) -> Proto {
+    let mut descriptor = Proto::default();
+    descriptor.string(1, &bus_id.to_string());
+    if let Some(bus) = _get_bus(&bus_id) {
+        descriptor.string(2, &format!("{} {}", bus.make, bus.model));
+        if let Some(plate) = &bus.registration_plate {
+            descriptor.string(3, plate);
+        }
+    }
+    descriptor
+}
+
+// Just enough of the protobuf wire format to write GTFS-Realtime messages
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field << 3 | u32::from(wire_type)));
+    }
+
+    fn uint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.key(field, 0);
+        self.varint(value);
+        self
+    }
+
+    // int32 and int64; negative values take ten bytes
+    fn int(&mut self, field: u32, value: i64) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    fn float(&mut self, field: u32, value: f32) -> &mut Self {
+        self.key(field, 5);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, message: Proto) -> &mut Self {
+        self.bytes(field, &message.0)
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proto_writes_varints_and_negative_ints() {
+        let mut proto = Proto::default();
+        proto.uint(1, 150);
+        assert_eq!(proto.0, vec![0x08, 0x96, 0x01]);
+
+        let mut proto = Proto::default();
+        proto.int(2, -1);
+        let mut expected = vec![0x10];
+        expected.extend([0xff; 9]);
+        expected.push(0x01);
+        assert_eq!(proto.0, expected);
+
+        let mut proto = Proto::default();
+        proto.string(4, "12").uint(16, 0);
+        assert_eq!(proto.0, vec![0x22, 0x02, b'1', b'2', 0x80, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn merges_the_times_recorded_at_a_stop() {
+        let second = 1_000_000_000;
+        let route_stop = |stop_id, offset| RouteStop {
+            stop_id,
+            distance_m: 0,
+            arrival_offset: offset,
+            departure_offset: offset + 60 * second,
+        };
+        let route = Route {
+            id: 1,
+            name: "Harbour line".to_string(),
+            stops: vec![route_stop(10, 0), route_stop(20, 600 * second)],
+            created_at: 0,
+            updated_at: None,
+        };
+        let trip = Trip {
+            id: 2,
+            bus_id: 1,
+            origin: "Harbour".to_string(),
+            destination: "Airport".to_string(),
+            departure_time: 1000 * second,
+            arrival_time: 1660 * second,
+            status: TripStatus::InProgress,
+            driver_id: None,
+            route_id: Some(1),
+            delay_minutes: 0,
+            actual_departure_time: None,
+            actual_arrival_time: None,
+            timetable_id: None,
+            created_at: 0,
+            updated_at: None,
+        };
+        let event = |id, kind, stop_id, recorded_at| TripEvent {
+            id,
+            trip_id: 2,
+            kind,
+            stop_id: Some(stop_id),
+            delay_minutes: None,
+            reason: None,
+            recorded_by: Principal::anonymous(),
+            recorded_at: recorded_at * second,
+        };
+        let events = [
+            event(1, TripEventKind::Departed, 10, 1090),
+            event(2, TripEventKind::Departed, 20, 1700),
+            event(3, TripEventKind::Arrived, 20, 1630),
+            event(4, TripEventKind::Departed, 20, 1710),
+        ];
+
+        let updates: Vec<Vec<u8>> = gtfs_rt_stop_time_updates(&trip, &route, &events)
+            .into_iter()
+            .map(|update| update.0)
+            .collect();
+
+        let mut first = Proto::default();
+        first
+            .uint(1, 1)
+            .message(3, gtfs_rt_stop_time_event(1060 * second, 1090 * second))
+            .string(4, "10");
+        let mut second_stop = Proto::default();
+        second_stop
+            .uint(1, 2)
+            .message(2, gtfs_rt_stop_time_event(1600 * second, 1630 * second))
+            .message(3, gtfs_rt_stop_time_event(1660 * second, 1710 * second))
+            .string(4, "20");
+        assert_eq!(updates, vec![first.0, second_stop.0]);
+    }
+}
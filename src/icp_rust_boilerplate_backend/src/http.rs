@@ -0,0 +1,829 @@
+use super::*;
+
+// Request and response records of the HTTP gateway interface
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+pub(crate) struct HttpResponse {
+    status_code: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    upgrade: Option<bool>, // asks the gateway to repeat the request as an update call
+    pub(crate) streaming_strategy: Option<StreamingStrategy>, // set when the body is only the first chunk
+}
+
+#[derive(candid::CandidType, Deserialize)]
+pub(crate) enum StreamingStrategy {
+    Callback {
+        callback: StreamingCallback,
+        token: StreamingCallbackToken,
+    },
+}
+
+candid::define_function!(pub(crate) StreamingCallback : (StreamingCallbackToken) -> (StreamingCallbackHttpResponse) query);
+
+// Where a streamed download carries on: after the row of a CSV report with this key, the report
+// rebuilt from its name and query, or at byte `key[0]` of a static feed snapshot
+// ("gtfs/<file>", with the snapshot and where the file ends in the query)
+#[derive(candid::CandidType, Deserialize, Clone)]
+pub(crate) struct StreamingCallbackToken {
+    pub(crate) report: String,
+    pub(crate) query: String,
+    pub(crate) key: Vec<u64>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+pub(crate) struct StreamingCallbackHttpResponse {
+    body: Vec<u8>,
+    token: Option<StreamingCallbackToken>,
+}
+
+impl HttpResponse {
+    pub(crate) fn ok(content_type: &str, body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            status_code: 200,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body,
+            upgrade: None,
+            streaming_strategy: None,
+        }
+    }
+
+    fn no_content() -> HttpResponse {
+        HttpResponse {
+            status_code: 204,
+            headers: Vec::new(),
+            body: Vec::new(),
+            upgrade: None,
+            streaming_strategy: None,
+        }
+    }
+}
+
+struct HttpRoute {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    params: &'static [HttpParamDoc],
+    response: HttpBody,
+    handler: fn(&HttpCall) -> Result<HttpResponse, Error>,
+}
+
+// What a route answers with when it succeeds, for the OpenAPI document
+enum HttpBody {
+    Json(fn() -> candid::types::Type),
+    Raw(&'static [&'static str]), // content types
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HttpParamIn {
+    Path,
+    Query,
+}
+
+// A path or query parameter, typed by what it parses into. Routes declare the parameters they
+// take with `doc()` and handlers read them through the same constants, so the OpenAPI document
+// describes exactly what is read.
+pub(crate) struct HttpParam<T> {
+    name: &'static str,
+    location: HttpParamIn,
+    required: bool,
+    value: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: HttpParamValue> HttpParam<T> {
+    const fn path(name: &'static str) -> Self {
+        HttpParam {
+            name,
+            location: HttpParamIn::Path,
+            required: true,
+            value: std::marker::PhantomData,
+        }
+    }
+
+    const fn query(name: &'static str, required: bool) -> Self {
+        HttpParam {
+            name,
+            location: HttpParamIn::Query,
+            required,
+            value: std::marker::PhantomData,
+        }
+    }
+
+    const fn doc(&self) -> HttpParamDoc {
+        HttpParamDoc {
+            name: self.name,
+            location: self.location,
+            required: self.required,
+            schema: T::schema,
+        }
+    }
+}
+
+// A parameter as a route declares it
+struct HttpParamDoc {
+    name: &'static str,
+    location: HttpParamIn,
+    required: bool,
+    schema: fn() -> serde_json::Value,
+}
+
+pub(crate) trait HttpParamValue: Sized {
+    const EXPECTED: &'static str; // for the error when a value doesn't parse
+    fn parse(value: &str) -> Option<Self>;
+    fn schema() -> serde_json::Value;
+}
+
+impl HttpParamValue for u64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn schema() -> serde_json::Value {
+        serde_json::json!({ "type": "integer", "format": "int64", "minimum": 0 })
+    }
+}
+
+impl HttpParamValue for String {
+    const EXPECTED: &'static str = "text";
+
+    fn parse(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+
+    fn schema() -> serde_json::Value {
+        serde_json::json!({ "type": "string" })
+    }
+}
+
+const PARAM_ID: HttpParam<u64> = HttpParam::path("id");
+const PARAM_FILE: HttpParam<String> = HttpParam::path("file");
+pub(crate) const PARAM_TOKEN: HttpParam<String> = HttpParam::query("token", true);
+pub(crate) const PARAM_TRIP_ID: HttpParam<u64> = HttpParam::query("trip_id", false);
+const PARAM_FROM_STOP: HttpParam<u64> = HttpParam::query("from_stop", true);
+const PARAM_TO_STOP: HttpParam<u64> = HttpParam::query("to_stop", true);
+pub(crate) const PARAM_MODEL_ID: HttpParam<u64> = HttpParam::query("model_id", false);
+pub(crate) const PARAM_BUS_ID: HttpParam<u64> = HttpParam::query("bus_id", false);
+pub(crate) const PARAM_CUSTOMER_ID: HttpParam<u64> = HttpParam::query("customer_id", false);
+// Booking time bounds of reports, `to` exclusive
+pub(crate) const PARAM_FROM: HttpParam<u64> = HttpParam::query("from", false);
+pub(crate) const PARAM_TO: HttpParam<u64> = HttpParam::query("to", false);
+
+// What a route handler gets to see of a request
+pub(crate) struct HttpCall<'a> {
+    declared: &'static [HttpParamDoc],
+    params: BTreeMap<&'static str, &'a str>,
+    pub(crate) query: BTreeMap<String, String>,
+}
+
+impl HttpCall<'_> {
+    // Reading a parameter the route doesn't declare is a bug in the route table
+    pub(crate) fn param<T: HttpParamValue>(&self, param: &HttpParam<T>) -> Result<Option<T>, Error> {
+        assert!(
+            self.declared
+                .iter()
+                .any(|doc| doc.name == param.name && doc.location == param.location),
+            "the route doesn't declare the {} parameter",
+            param.name
+        );
+        let value = match param.location {
+            HttpParamIn::Path => self.params.get(param.name).copied(),
+            HttpParamIn::Query => self.query.get(param.name).map(String::as_str),
+        };
+        value
+            .map(|value| {
+                T::parse(value).ok_or_else(|| Error::InvalidInput {
+                    msg: format!("{} must be {}", param.name, T::EXPECTED),
+                })
+            })
+            .transpose()
+    }
+
+    pub(crate) fn require<T: HttpParamValue>(&self, param: &HttpParam<T>) -> Result<T, Error> {
+        self.param(param)?.ok_or_else(|| Error::InvalidInput {
+            msg: format!("the {} parameter is required", param.name),
+        })
+    }
+}
+
+// Everything served over HTTP. Paths match segment by segment and `{name}` segments capture
+// path parameters. Calls come through the gateway as the anonymous principal, so only public
+// reads and downloads authorised by a token in the query are served; bookings, customer records
+// and fleet changes go through the Candid interface.
+const HTTP_ROUTES: &[HttpRoute] = &[
+    HttpRoute {
+        method: "GET",
+        path: "/api/buses/{id}",
+        summary: "Get a bus",
+        params: &[PARAM_ID.doc()],
+        response: HttpBody::Json(<Bus as CandidType>::ty),
+        handler: |call| json_response(&get_bus(call.require(&PARAM_ID)?)?),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/buses/{id}/seats",
+        summary: "Seat map of a bus, for a trip when trip_id is given",
+        params: &[PARAM_ID.doc(), PARAM_TRIP_ID.doc()],
+        response: HttpBody::Json(<Vec<SeatMapEntry> as CandidType>::ty),
+        handler: |call| {
+            json_response(&get_seat_map(
+                call.require(&PARAM_ID)?,
+                call.param(&PARAM_TRIP_ID)?,
+            )?)
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/trips/{id}",
+        summary: "Get a trip",
+        params: &[PARAM_ID.doc()],
+        response: HttpBody::Json(<Trip as CandidType>::ty),
+        handler: |call| json_response(&get_trip(call.require(&PARAM_ID)?)?),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/trips/{id}/progress",
+        summary: "Events recorded for a trip and its departure delay",
+        params: &[PARAM_ID.doc()],
+        response: HttpBody::Json(<TripProgress as CandidType>::ty),
+        handler: |call| json_response(&get_trip_progress(call.require(&PARAM_ID)?)?),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/trips/{id}/availability",
+        summary: "Free seats between two stops, given as from_stop and to_stop",
+        params: &[PARAM_ID.doc(), PARAM_FROM_STOP.doc(), PARAM_TO_STOP.doc()],
+        response: HttpBody::Json(<SegmentAvailability as CandidType>::ty),
+        handler: |call| {
+            json_response(&get_segment_availability(
+                call.require(&PARAM_ID)?,
+                call.require(&PARAM_FROM_STOP)?,
+                call.require(&PARAM_TO_STOP)?,
+            )?)
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/gtfs/{file}",
+        summary: "GTFS static feed, as gtfs.zip or one of its files",
+        params: &[PARAM_FILE.doc()],
+        response: HttpBody::Raw(&["application/zip", "text/csv"]),
+        handler: |call| gtfs_feed_response(&call.require(&PARAM_FILE)?),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/gtfs-rt/trip-updates",
+        summary: "GTFS-Realtime trip updates",
+        params: &[],
+        response: HttpBody::Raw(&["application/x-protobuf"]),
+        handler: |_| {
+            Ok(HttpResponse::ok(
+                "application/x-protobuf",
+                build_trip_updates_feed(time()),
+            ))
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/gtfs-rt/vehicle-positions",
+        summary: "GTFS-Realtime vehicle positions",
+        params: &[],
+        response: HttpBody::Raw(&["application/x-protobuf"]),
+        handler: |_| {
+            Ok(HttpResponse::ok(
+                "application/x-protobuf",
+                build_vehicle_positions_feed(time()),
+            ))
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/reservations/{id}/calendar.ics",
+        summary: "A reservation as an iCalendar event, with the token of its calendar link",
+        params: &[PARAM_ID.doc(), PARAM_TOKEN.doc()],
+        response: HttpBody::Raw(&["text/calendar"]),
+        handler: |call| {
+            let id = call.require(&PARAM_ID)?;
+            ensure_calendar_token("reservation", id, &call.require(&PARAM_TOKEN)?)?;
+            let reservation = get_reservation(id)?;
+            calendar_response(
+                &format!("reservation-{}", id),
+                build_calendar(&[reservation], time())?,
+            )
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/api/customers/{id}/calendar.ics",
+        summary: "A customer's upcoming trips as iCalendar events, with their calendar link token",
+        params: &[PARAM_ID.doc(), PARAM_TOKEN.doc()],
+        response: HttpBody::Raw(&["text/calendar"]),
+        handler: |call| {
+            let id = call.require(&PARAM_ID)?;
+            ensure_calendar_token("customer", id, &call.require(&PARAM_TOKEN)?)?;
+            calendar_response(
+                &format!("customer-{}", id),
+                _get_customer_calendar(id, time())?,
+            )
+        },
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/reports/buses.csv",
+        summary: "The fleet report as CSV",
+        params: &[PARAM_TOKEN.doc(), PARAM_MODEL_ID.doc()],
+        response: HttpBody::Raw(&["text/csv"]),
+        handler: |call| csv_report_response("buses", call),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/reports/reservations.csv",
+        summary: "Reservations as CSV, filtered by booking time, bus, trip or customer",
+        params: &[
+            PARAM_TOKEN.doc(),
+            PARAM_FROM.doc(),
+            PARAM_TO.doc(),
+            PARAM_BUS_ID.doc(),
+            PARAM_TRIP_ID.doc(),
+            PARAM_CUSTOMER_ID.doc(),
+        ],
+        response: HttpBody::Raw(&["text/csv"]),
+        handler: |call| csv_report_response("reservations", call),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/reports/revenue.csv",
+        summary: "Revenue per booking day and bus as CSV",
+        params: &[
+            PARAM_TOKEN.doc(),
+            PARAM_FROM.doc(),
+            PARAM_TO.doc(),
+            PARAM_BUS_ID.doc(),
+            PARAM_TRIP_ID.doc(),
+            PARAM_CUSTOMER_ID.doc(),
+        ],
+        response: HttpBody::Raw(&["text/csv"]),
+        handler: |call| csv_report_response("revenue", call),
+    },
+    HttpRoute {
+        method: "GET",
+        path: "/openapi.json",
+        summary: "This description of the HTTP interface",
+        params: &[],
+        response: HttpBody::Raw(&["application/json"]),
+        handler: |_| {
+            Ok(HttpResponse::ok(
+                "application/json",
+                build_openapi_document().to_string().into_bytes(),
+            ))
+        },
+    },
+];
+
+// OpenAPI 3 description of HTTP_ROUTES. Schemas are derived from the Candid types the routes
+// take and return, following how serde_json writes them.
+fn build_openapi_document() -> serde_json::Value {
+    let error_schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "error": {
+                "type": "string",
+                "enum": ["NotFound", "InvalidInput", "Unauthorized", "Conflict"]
+            },
+            "message": { "type": "string" }
+        },
+        "required": ["error", "message"]
+    });
+    let mut paths = serde_json::Map::new();
+    for route in HTTP_ROUTES {
+        let parameters: Vec<serde_json::Value> = route
+            .params
+            .iter()
+            .map(|param| {
+                let location = match param.location {
+                    HttpParamIn::Path => "path",
+                    HttpParamIn::Query => "query",
+                };
+                serde_json::json!({
+                    "name": param.name,
+                    "in": location,
+                    "required": param.required,
+                    "schema": (param.schema)()
+                })
+            })
+            .collect();
+        let success = match route.response {
+            HttpBody::Json(ty) => (
+                "200",
+                serde_json::json!({
+                    "description": "OK",
+                    "content": { "application/json": { "schema": json_schema(&ty()) } }
+                }),
+            ),
+            HttpBody::Raw(content_types) => {
+                let content: serde_json::Map<String, serde_json::Value> = content_types
+                    .iter()
+                    .map(|content_type| {
+                        let schema = serde_json::json!({ "type": "string", "format": "binary" });
+                        (
+                            content_type.to_string(),
+                            serde_json::json!({ "schema": schema }),
+                        )
+                    })
+                    .collect();
+                (
+                    "200",
+                    serde_json::json!({ "description": "OK", "content": content }),
+                )
+            }
+        };
+        let operation = serde_json::json!({
+            "summary": route.summary,
+            "parameters": parameters,
+            "responses": {
+                success.0: success.1,
+                "default": {
+                    "description": "Error",
+                    "content": { "application/json": { "schema": error_schema } }
+                }
+            }
+        });
+        paths
+            .entry(route.path)
+            .or_insert_with(|| serde_json::json!({}))[route.method.to_lowercase()] = operation;
+    }
+    serde_json::json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Bus booking canister",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": paths
+    })
+}
+
+// JSON schema for the way serde_json writes a value of the Candid type: variants without data
+// become strings and the rest single-key objects
+fn json_schema(ty: &candid::types::Type) -> serde_json::Value {
+    use candid::types::{Label, TypeInner};
+    let label = |label: &Label| match label {
+        Label::Named(name) => name.clone(),
+        Label::Id(id) | Label::Unnamed(id) => id.to_string(),
+    };
+    match ty.as_ref() {
+        TypeInner::Null => serde_json::json!({ "nullable": true }),
+        TypeInner::Bool => serde_json::json!({ "type": "boolean" }),
+        TypeInner::Nat | TypeInner::Nat8 | TypeInner::Nat16 | TypeInner::Nat32 => {
+            serde_json::json!({ "type": "integer", "minimum": 0 })
+        }
+        TypeInner::Nat64 => {
+            serde_json::json!({ "type": "integer", "format": "int64", "minimum": 0 })
+        }
+        TypeInner::Int | TypeInner::Int8 | TypeInner::Int16 | TypeInner::Int32 => {
+            serde_json::json!({ "type": "integer" })
+        }
+        TypeInner::Int64 => serde_json::json!({ "type": "integer", "format": "int64" }),
+        TypeInner::Float32 => serde_json::json!({ "type": "number", "format": "float" }),
+        TypeInner::Float64 => serde_json::json!({ "type": "number", "format": "double" }),
+        TypeInner::Text | TypeInner::Principal => serde_json::json!({ "type": "string" }),
+        TypeInner::Opt(inner) => {
+            let mut schema = json_schema(inner);
+            schema["nullable"] = serde_json::json!(true);
+            schema
+        }
+        TypeInner::Vec(inner) => {
+            serde_json::json!({ "type": "array", "items": json_schema(inner) })
+        }
+        // Tuples are written as arrays
+        TypeInner::Record(fields)
+            if fields
+                .iter()
+                .any(|field| matches!(*field.id, Label::Unnamed(_))) =>
+        {
+            serde_json::json!({ "type": "array" })
+        }
+        TypeInner::Record(fields) => {
+            let properties: serde_json::Map<String, serde_json::Value> = fields
+                .iter()
+                .map(|field| (label(&field.id), json_schema(&field.ty)))
+                .collect();
+            let required: Vec<String> = fields
+                .iter()
+                .filter(|field| !matches!(field.ty.as_ref(), TypeInner::Opt(_)))
+                .map(|field| label(&field.id))
+                .collect();
+            serde_json::json!({ "type": "object", "properties": properties, "required": required })
+        }
+        TypeInner::Variant(fields) => {
+            let (units, values): (Vec<_>, Vec<_>) = fields
+                .iter()
+                .partition(|field| matches!(field.ty.as_ref(), TypeInner::Null));
+            let mut options: Vec<serde_json::Value> = values
+                .iter()
+                .map(|field| {
+                    let name = label(&field.id);
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": { name.clone(): json_schema(&field.ty) },
+                        "required": [name]
+                    })
+                })
+                .collect();
+            if !units.is_empty() {
+                let names: Vec<String> = units.iter().map(|field| label(&field.id)).collect();
+                options.insert(0, serde_json::json!({ "type": "string", "enum": names }));
+            }
+            if options.len() == 1 {
+                options.remove(0)
+            } else {
+                serde_json::json!({ "oneOf": options })
+            }
+        }
+        _ => serde_json::json!({}),
+    }
+}
+
+// Responses are not certified, so they have to be fetched through the raw gateway domain
+#[ic_cdk::query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    let (path, query) = request
+        .url
+        .split_once('?')
+        .unwrap_or((request.url.as_str(), ""));
+    let mut allowed = Vec::new();
+    for route in HTTP_ROUTES {
+        let Some(params) = match_http_path(route.path, path) else {
+            continue;
+        };
+        if route.method != request.method {
+            allowed.push(route.method);
+            continue;
+        }
+        let call = HttpCall {
+            declared: route.params,
+            params,
+            query: parse_query_string(query),
+        };
+        return (route.handler)(&call).unwrap_or_else(error_response);
+    }
+    if allowed.is_empty() {
+        return error_response(Error::NotFound {
+            msg: format!("no route for {}", path),
+        });
+    }
+    HttpResponse {
+        status_code: 405,
+        headers: vec![("Allow".to_string(), allowed.join(", "))],
+        ..HttpResponse::no_content()
+    }
+}
+
+// Captures of `{name}` segments, or None if the path doesn't fit the template
+fn match_http_path<'a>(
+    template: &'static str,
+    path: &'a str,
+) -> Option<BTreeMap<&'static str, &'a str>> {
+    let mut params = BTreeMap::new();
+    let mut segments = path.trim_end_matches('/').split('/');
+    for expected in template.split('/') {
+        let segment = segments.next()?;
+        match expected
+            .strip_prefix('{')
+            .and_then(|name| name.strip_suffix('}'))
+        {
+            Some(name) if !segment.is_empty() => {
+                params.insert(name, segment);
+            }
+            Some(_) => return None,
+            None if expected == segment => {}
+            None => return None,
+        }
+    }
+    segments.next().is_none().then_some(params)
+}
+
+fn parse_query_string(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::ok(
+        "application/json",
+        serde_json::to_vec(value).expect("candid types serialize to JSON"),
+    ))
+}
+
+// Error variants map to status codes; the body names the variant and carries its message
+fn error_response(error: Error) -> HttpResponse {
+    let (status_code, kind) = match &error {
+        Error::NotFound { .. } => (404, "NotFound"),
+        Error::InvalidInput { .. } => (400, "InvalidInput"),
+        Error::Unauthorized { .. } => (403, "Unauthorized"),
+        Error::Conflict { .. } => (409, "Conflict"),
+    };
+    let body = serde_json::json!({ "error": kind, "message": error_message(error) });
+    HttpResponse {
+        status_code,
+        ..HttpResponse::ok("application/json", body.to_string().into_bytes())
+    }
+}
+
+// Each chunk rebuilds the report and carries on after the last row sent, so bookings made in
+// between show up only if they sort after it
+#[ic_cdk::query]
+fn http_request_streaming_callback(token: StreamingCallbackToken) -> StreamingCallbackHttpResponse {
+    if token.report.starts_with("gtfs/") {
+        let query = parse_query_string(&token.query);
+        let number = |name: &str| query.get(name).and_then(|value| value.parse::<u64>().ok());
+        let (body, next) = match (number("snapshot"), number("end")) {
+            (Some(snapshot), Some(end)) => {
+                gtfs_feed_body(snapshot, token.key.first().copied().unwrap_or(end), end)
+            }
+            _ => (Vec::new(), None),
+        };
+        return StreamingCallbackHttpResponse {
+            body,
+            token: next.map(|position| StreamingCallbackToken {
+                key: vec![position],
+                ..token
+            }),
+        };
+    }
+    let path = format!("/reports/{}.csv", token.report);
+    let Some(route) = HTTP_ROUTES.iter().find(|route| route.path == path) else {
+        return StreamingCallbackHttpResponse {
+            body: Vec::new(),
+            token: None,
+        };
+    };
+    let call = HttpCall {
+        declared: route.params,
+        params: BTreeMap::new(),
+        query: parse_query_string(&token.query),
+    };
+    let Ok(csv) = build_csv_report(&token.report, &call) else {
+        return StreamingCallbackHttpResponse {
+            body: Vec::new(),
+            token: None,
+        };
+    };
+    let (body, next) = csv_chunk(&csv, Some(&token.key));
+    StreamingCallbackHttpResponse {
+        body,
+        token: next.map(|key| StreamingCallbackToken { key, ..token }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_http_paths_segment_by_segment() {
+        let params = match_http_path(
+            "/api/reservations/{id}/passengers/{passenger_id}",
+            "/api/reservations/12/passengers/3/",
+        );
+        assert_eq!(
+            params.unwrap().into_iter().collect::<Vec<_>>(),
+            vec![("id", "12"), ("passenger_id", "3")]
+        );
+        assert!(match_http_path("/api/buses/{id}", "/api/buses").is_none());
+        assert!(match_http_path("/api/buses/{id}", "/api/buses//").is_none());
+        assert!(match_http_path("/api/buses/{id}", "/api/buses/1/seats").is_none());
+        assert!(match_http_path("/api/buses", "/api/customers").is_none());
+    }
+
+    #[test]
+    fn percent_decodes_query_values() {
+        assert_eq!(percent_decode("a%20b+c%2Bd"), "a b c+d");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        // Escapes that aren't followed by two hex digits stay as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%FF"), "\u{fffd}");
+        assert_eq!(
+            parse_query_string("from=1&to=&bus_id")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                ("bus_id".to_string(), String::new()),
+                ("from".to_string(), "1".to_string()),
+                ("to".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn routes_describe_the_parameters_they_read() {
+        for route in HTTP_ROUTES {
+            let in_path: Vec<&str> = route
+                .path
+                .split('/')
+                .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+                .collect();
+            let declared: Vec<&str> = route
+                .params
+                .iter()
+                .filter(|param| param.location == HttpParamIn::Path)
+                .map(|param| param.name)
+                .collect();
+            assert_eq!(in_path, declared, "{} {}", route.method, route.path);
+        }
+
+        let document = build_openapi_document();
+        let paths = &document["paths"];
+        let revenue = paths["/reports/revenue.csv"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(revenue.iter().any(|param| param["name"] == "customer_id"));
+        let calendar = &paths["/api/customers/{id}/calendar.ics"]["get"]["parameters"];
+        assert_eq!(calendar[1]["name"], "token");
+        assert_eq!(calendar[1]["required"], true);
+        // Nothing that writes is served to the anonymous gateway caller
+        assert!(paths
+            .as_object()
+            .unwrap()
+            .values()
+            .all(|operations| operations.as_object().unwrap().keys().all(|method| method == "get")));
+        let feed = &paths["/gtfs/{file}"]["get"]["responses"]["200"]["content"];
+        assert!(feed.get("application/zip").is_some() && feed.get("text/csv").is_some());
+    }
+
+    #[test]
+    fn serves_reports_to_report_token_holders() {
+        do_add_report_token("Spreadsheet".to_string(), "c0ffee", 0);
+        let get = |url: &str| {
+            http_request(HttpRequest {
+                method: "GET".to_string(),
+                url: url.to_string(),
+                headers: Vec::new(),
+                body: Vec::new(),
+            })
+        };
+
+        let response = get("/reports/buses.csv?token=c0ffee");
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with(b"id,registration_plate,vin,"));
+        assert!(response.streaming_strategy.is_none());
+
+        assert_eq!(get("/reports/revenue.csv?token=c0ffef").status_code, 403);
+        assert_eq!(get("/reports/reservations.csv").status_code, 400);
+    }
+
+    #[test]
+    fn reads_parameters_through_their_declaration() {
+        const DECLARED: &[HttpParamDoc] = &[PARAM_ID.doc(), PARAM_TRIP_ID.doc()];
+        let call = HttpCall {
+            declared: DECLARED,
+            params: BTreeMap::from([("id", "7")]),
+            query: BTreeMap::from([("trip_id".to_string(), "x".to_string())]),
+        };
+        assert_eq!(call.require(&PARAM_ID).ok(), Some(7));
+        assert!(call.param(&PARAM_TRIP_ID).is_err());
+        let result = std::panic::catch_unwind(|| call.param(&PARAM_BUS_ID).ok());
+        assert!(result.is_err());
+    }
+}
@@ -1,12 +1,52 @@
 #[macro_use]
 extern crate serde;
-use candid::{Decode, Encode};
+use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk::api::time;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
 use std::{borrow::Cow, cell::RefCell};
 use std::borrow::{Borrow, BorrowMut};
 
+mod availability;
+mod calendar;
+mod charters;
+mod compliance;
+mod drivers;
+mod gtfs;
+mod http;
+mod loyalty;
+mod maintenance;
+mod positions;
+mod promo;
+mod rebooking;
+mod reports;
+mod routes;
+mod search;
+mod timetables;
+mod trip_events;
+mod util;
+
+use availability::*;
+use calendar::*;
+use charters::*;
+use compliance::*;
+use drivers::*;
+use gtfs::*;
+use http::*;
+use loyalty::*;
+use maintenance::*;
+use positions::*;
+use promo::*;
+use rebooking::*;
+use reports::*;
+use routes::*;
+use search::*;
+use timetables::*;
+use trip_events::*;
+use util::*;
+
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
 
@@ -21,10 +61,18 @@ struct Bus {
     updated_at: Option<u64>,
     owner: String,
     is_booked: bool, // New field for booking status
+    fare: u64,
+    capacity: u32,
+    odometer_km: u64,
+    // Unset on buses carried over from the first release until an update gives them one
+    registration_plate: Option<String>,
+    vin: Option<String>,
+    model_id: Option<u64>, // catalog entry the seat layout comes from
+    amenities: Vec<Amenity>,
 }
 
 impl Storable for Bus {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -34,7 +82,7 @@ impl Storable for Bus {
 }
 
 impl BoundedStorable for Bus {
-    const MAX_SIZE: u32 = 1024;
+    const MAX_SIZE: u32 = 4096;
     const IS_FIXED_SIZE: bool = false;
 }
 
@@ -48,10 +96,236 @@ thread_local! {
             .expect("Cannot create a counter")
     );
 
+    // Buses lived in MemoryId 1 until they outgrew its 1 KiB records; see `do_migrate_records_v1`
     static BUS_STORAGE: RefCell<StableBTreeMap<u64, Bus, Memory>> =
         RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)))
+        ));
+
+    static PROMO_CODE_STORAGE: RefCell<StableBTreeMap<StringKey, PromoCode, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+        ));
+
+    // Number of times each customer has redeemed a promo code, keyed by (code, customer_id)
+    static PROMO_REDEMPTIONS: RefCell<StableBTreeMap<(StringKey, u64), u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+        ));
+
+    static LOYALTY_ACCOUNTS: RefCell<StableBTreeMap<u64, LoyaltyAccount, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+        ));
+
+    // Statement entries keyed by (customer_id, entry_id) so a customer's history is one range
+    static LOYALTY_ENTRIES: RefCell<StableBTreeMap<(u64, u64), LoyaltyEntry, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+        ));
+
+    // Points each reservation has drawn from each lot, keyed by (reservation_id, lot entry id),
+    // so refunds go back to the lots they came from and keep their expiry
+    static LOYALTY_DRAWS: RefCell<StableBTreeMap<(u64, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(41)))
+        ));
+
+    static TRIP_STORAGE: RefCell<StableBTreeMap<u64, Trip, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+        ));
+
+    static CHARTER_STORAGE: RefCell<StableBTreeMap<u64, Charter, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+        ));
+
+    // Availability calendar keyed by (bus_id, start) so each bus' blocks are sorted by time
+    static BUS_CALENDAR: RefCell<StableBTreeMap<(u64, u64), BlockedInterval, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+        ));
+
+    static MAINTENANCE_STORAGE: RefCell<StableBTreeMap<u64, MaintenanceRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+        ));
+
+    // Compliance documents keyed by (bus_id, document_id)
+    static COMPLIANCE_STORAGE: RefCell<StableBTreeMap<(u64, u64), ComplianceDocument, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+        ));
+
+    static COMPLIANCE_REPORT: RefCell<Cell<ComplianceReport, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+            ComplianceReport::default(),
+        )
+        .expect("Cannot create the compliance report")
+    );
+
+    // Unique indexes from normalised registration plate / VIN to bus id
+    static PLATE_INDEX: RefCell<StableBTreeMap<StringKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        ));
+
+    static VIN_INDEX: RefCell<StableBTreeMap<StringKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+        ));
+
+    static BUS_MODEL_STORAGE: RefCell<StableBTreeMap<u64, BusModel, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+        ));
+
+    static DRIVER_STORAGE: RefCell<StableBTreeMap<u64, Driver, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+        ));
+
+    static DRIVING_RULES: RefCell<Cell<DrivingRules, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))),
+            DrivingRules::default(),
+        )
+        .expect("Cannot create the driving rules")
+    );
+
+    static STOP_STORAGE: RefCell<StableBTreeMap<u64, Stop, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+        ));
+
+    static ROUTE_STORAGE: RefCell<StableBTreeMap<u64, Route, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+        ));
+
+    // Trip events keyed by (trip_id, event_id) so a trip's history is one range
+    static TRIP_EVENTS: RefCell<StableBTreeMap<(u64, u64), TripEvent, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21)))
+        ));
+
+    // Ring buffer of positions per trip keyed by (trip_id, sequence % POSITION_BUFFER_SIZE)
+    static VEHICLE_POSITIONS: RefCell<StableBTreeMap<(u64, u64), VehiclePosition, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+        ));
+
+    // Number of positions ever pushed for each trip
+    static POSITION_COUNTS: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
         ));
+
+    static TIMETABLE_STORAGE: RefCell<StableBTreeMap<u64, Timetable, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24)))
+        ));
+
+    // Trips generated from each timetable keyed by (timetable_id, departure_time) to the trip id
+    static TIMETABLE_TRIPS: RefCell<StableBTreeMap<(u64, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33)))
+        ));
+
+    static TIMETABLE_REPORT: RefCell<Cell<TimetableReport, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))),
+            TimetableReport::default(),
+        )
+        .expect("Cannot create the timetable report")
+    );
+
+    static GTFS_IMPORTS: RefCell<StableBTreeMap<u64, GtfsImport, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+        ));
+
+    // Uploaded pieces keyed by (import_id, upload order)
+    static GTFS_CHUNKS: RefCell<StableBTreeMap<(u64, u64), GtfsChunk, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+        ));
+
+    // GTFS identifiers ("stop/<stop_id>", "route/<hash>", "trip/<trip_id>") to the ids
+    // they were imported as, so importing a feed again updates instead of duplicating
+    static GTFS_IDS: RefCell<StableBTreeMap<StringKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+        ));
+
+    static GTFS_AGENCY: RefCell<Cell<GtfsAgency, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29))),
+            GtfsAgency::default(),
+        )
+        .expect("Cannot create the GTFS agency")
+    );
+
+    // Layout version of the records in stable memory, 0 for anything written before it was kept
+    static STORAGE_VERSION_CELL: RefCell<Cell<u32, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30))), 0)
+            .expect("Cannot create the storage version")
+    );
+
+    // Finished uploads being imported, and the reports of those that are done
+    static GTFS_IMPORT_JOBS: RefCell<StableBTreeMap<u64, GtfsImportJob, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(34)))
+        ));
+
+    // Errors found by each import keyed by (import_id, order found)
+    static GTFS_IMPORT_ERRORS: RefCell<StableBTreeMap<(u64, u64), GtfsLineError, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(35)))
+        ));
+
+    // What earlier batches of an import read for later ones, keyed by (import_id, GTFS key)
+    static GTFS_STAGING: RefCell<StableBTreeMap<(u64, StringKey), GtfsStaged, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(36)))
+        ));
+
+    // Stop times of each staged trip keyed by ((import_id, trip number), stop_sequence)
+    static GTFS_STOP_TIMES: RefCell<StableBTreeMap<GtfsStopTimeKey, GtfsStopTime, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(37)))
+        ));
+
+    // Static feed snapshots keyed by (snapshot, chunk index)
+    static GTFS_FEED_CHUNKS: RefCell<StableBTreeMap<(u64, u64), GtfsFeedChunk, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(38)))
+        ));
+
+    static GTFS_FEED: RefCell<Cell<GtfsFeed, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(39))),
+            GtfsFeed::default(),
+        )
+        .expect("Cannot create the GTFS feed")
+    );
+
+    // Random bytes calendar links are derived from, drawn once; empty until then
+    static CALENDAR_SECRET: RefCell<Cell<Vec<u8>, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(40))), Vec::new())
+            .expect("Cannot create the calendar secret")
+    );
+
+    static REPORT_TOKENS: RefCell<StableBTreeMap<u64, ReportToken, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(42)))
+        ));
+
+    // Kept on the heap: an upgrade drops a build in progress and the next one starts over
+    static GTFS_FEED_BUILD: RefCell<Option<GtfsFeedBuild>> = const { RefCell::new(None) };
+
 }
 
 #[derive(candid::CandidType, Serialize, Deserialize, Default)]
@@ -62,6 +336,114 @@ struct BusPayload {
     color: String,
     owner: String,
     is_booked: bool, // Add is_booked field to payload
+    fare: u64,
+    capacity: u32, // ignored when `model_id` is set; the layout decides
+    registration_plate: String,
+    vin: String,
+    model_id: Option<u64>,
+    amenities: Vec<Amenity>,
+}
+
+const MAX_MODEL_SEATS: usize = 120;
+const MAX_CLASS_FARE_PERCENTAGE: u64 = 1000;
+
+#[derive(
+    candid::CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+enum SeatClass {
+    Standard,
+    Premium,
+    Sleeper,
+}
+
+impl SeatClass {
+    // Share of the bus' base fare a seat of this class costs, unless the bus model prices it
+    fn fare_percentage(&self) -> u64 {
+        match self {
+            SeatClass::Standard => 100,
+            SeatClass::Premium => 150,
+            SeatClass::Sleeper => 200,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum Amenity {
+    WiFi,
+    PowerOutlets,
+    Toilet,
+    AirConditioning,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct SeatTemplate {
+    seat_number: u32,
+    deck: u8,
+    row: u16,
+    column: u16,
+    seat_class: SeatClass,
+    is_wheelchair_accessible: bool,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct ClassFare {
+    seat_class: SeatClass,
+    fare_percentage: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct BusModel {
+    id: u64,
+    make: String,
+    model: String,
+    deck_count: u8,
+    seats: Vec<SeatTemplate>,
+    wheelchair_spaces: u32,
+    created_at: u64,
+    class_fares: Option<Vec<ClassFare>>, // classes left out cost their default share
+}
+
+impl BusModel {
+    fn fare_percentage(&self, seat_class: SeatClass) -> u64 {
+        self.class_fares
+            .iter()
+            .flatten()
+            .find(|class_fare| class_fare.seat_class == seat_class)
+            .map_or(seat_class.fare_percentage(), |class_fare| {
+                class_fare.fare_percentage
+            })
+    }
+}
+
+impl Storable for BusModel {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for BusModel {
+    const MAX_SIZE: u32 = 16384;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct BusModelPayload {
+    make: String,
+    model: String,
+    deck_count: u8,
+    seats: Vec<SeatTemplate>,
+    wheelchair_spaces: u32,
+    class_fares: Option<Vec<ClassFare>>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct SeatMapEntry {
+    seat: SeatTemplate,
+    is_occupied: bool,
 }
 
 #[derive(candid::CandidType, Serialize, Deserialize, Default, Clone)]
@@ -69,10 +451,11 @@ struct Customer {
     id: u64,
     name: String,
     contact: String,
+    principal: Option<Principal>, // who registered the customer, None if it was done anonymously
 }
 
 impl Storable for Customer {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -88,13 +471,64 @@ impl BoundedStorable for Customer {
 
 #[derive(candid::CandidType, Serialize, Deserialize, Default, Clone)]
 struct Reservation {
+    id: u64,
     bus_id: u64,
+    trip_id: Option<u64>, // None for bookings made directly against a bus
+    // Where the passengers board and alight on the trip's route, None when the trip has no route
+    from_stop: Option<u64>,
+    to_stop: Option<u64>,
     customer_id: u64,
     reservation_time: u64,
+    fare: u64,
+    discount: u64,
+    promo_code: Option<String>,
+    points_redeemed: u64,
+    status: ReservationStatus,
+    passengers: Vec<Passenger>,
+}
+
+impl Reservation {
+    fn amount_due(&self) -> u64 {
+        self.fare
+            .saturating_sub(self.discount)
+            .saturating_sub(self.points_redeemed * LOYALTY_POINT_VALUE)
+    }
+
+    // Takes `fare` off the reservation and returns the (discount, points redeemed) that leave with
+    // it. What stays is worked out from the fare that stays, rounded in the customer's favour and
+    // capped so the discount and points never cover more than that fare.
+    fn split_off_fare(&mut self, fare: u64) -> (u64, u64) {
+        let whole = u128::from(self.fare.max(1));
+        let kept = self.fare.saturating_sub(fare);
+        let discount = (u128::from(self.discount) * u128::from(kept))
+            .div_ceil(whole)
+            .min(u128::from(kept)) as u64;
+        let points = ((u128::from(self.points_redeemed) * u128::from(kept) / whole) as u64)
+            .min((kept - discount) / LOYALTY_POINT_VALUE);
+        let removed = (self.discount - discount, self.points_redeemed - points);
+        self.fare = kept;
+        self.discount = discount;
+        self.points_redeemed = points;
+        removed
+    }
+
+    fn active_passengers(&self) -> impl Iterator<Item = &Passenger> {
+        self.passengers
+            .iter()
+            .filter(|passenger| passenger.cancelled_at.is_none())
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default, Clone, PartialEq)]
+enum ReservationStatus {
+    #[default]
+    Confirmed,
+    Completed,
+    Refunded, // the trip was cancelled and no other trip could take the passengers
 }
 
 impl Storable for Reservation {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -104,10 +538,155 @@ impl Storable for Reservation {
 }
 
 impl BoundedStorable for Reservation {
+    const MAX_SIZE: u32 = 8192;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+const MAX_GROUP_SIZE: usize = 60;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum FareCategory {
+    Adult,
+    Child,
+    Senior,
+    Student,
+}
+
+impl FareCategory {
+    // Share of the bus' full fare this category pays
+    fn fare_percentage(&self) -> u64 {
+        match self {
+            FareCategory::Adult => 100,
+            FareCategory::Child => 50,
+            FareCategory::Senior => 70,
+            FareCategory::Student => 80,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct Passenger {
+    id: u32, // position within the reservation, starting at 1
+    name: String,
+    category: FareCategory,
+    seat_number: u32,
+    seat_class: SeatClass,
+    fare: u64,
+    cancelled_at: Option<u64>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct PassengerPayload {
+    name: String,
+    category: FareCategory,
+    seat_class: Option<SeatClass>, // defaults to standard
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct GroupReservationPayload {
+    bus_id: u64,
+    trip_id: Option<u64>,   // must run on `bus_id` when set
+    from_stop: Option<u64>, // defaults to the first stop of the trip's route
+    to_stop: Option<u64>,   // defaults to the last stop of the trip's route
+    customer_id: u64,
+    passengers: Vec<PassengerPayload>,
+    promo_code: Option<String>,
+    redeem_points: Option<u64>,
+}
+
+// String keys for stable maps (promo codes and other normalised identifiers)
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct StringKey(String);
+
+impl Storable for StringKey {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        StringKey(String::from_utf8(bytes.into_owned()).unwrap())
+    }
+}
+
+impl BoundedStorable for StringKey {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum TripStatus {
+    Scheduled,
+    InProgress,
+    Completed,
+    Cancelled,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct Trip {
+    id: u64,
+    bus_id: u64,
+    origin: String,
+    destination: String,
+    departure_time: u64,
+    arrival_time: u64,
+    status: TripStatus,
+    driver_id: Option<u64>,
+    route_id: Option<u64>,
+    delay_minutes: u32, // latest delay reported for the trip
+    actual_departure_time: Option<u64>,
+    actual_arrival_time: Option<u64>,
+    timetable_id: Option<u64>, // set on trips generated from a timetable
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for Trip {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Trip {
     const MAX_SIZE: u32 = 1024;
     const IS_FIXED_SIZE: bool = false;
 }
 
+// Origins, destinations and stop names, short enough that both ends fit Trip::MAX_SIZE
+const MAX_PLACE_NAME_LEN: usize = 256;
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct TripPayload {
+    bus_id: u64,
+    route_id: Option<u64>, // when set, origin and destination come from the route's stops
+    origin: String,
+    destination: String,
+    departure_time: u64,
+    arrival_time: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ClassAvailability {
+    seat_class: SeatClass,
+    seats_available: u32,
+    fare: u64, // adult fare for the segment
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct SegmentAvailability {
+    trip_id: u64,
+    from_stop: u64,
+    to_stop: u64,
+    departure_time: u64,
+    arrival_time: u64,
+    classes: Vec<ClassAvailability>,
+}
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
 #[ic_cdk::query]
 fn get_bus(id: u64) -> Result<Bus, Error> {
     match _get_bus(&id) {
@@ -119,14 +698,13 @@ fn get_bus(id: u64) -> Result<Bus, Error> {
 }
 
 #[ic_cdk::update]
-fn add_bus(bus: BusPayload) -> Option<Bus> {
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_value = *counter.borrow().get();
-            counter.borrow_mut().set(current_value + 1)
-        })
-        .expect("cannot increment id counter");
-    let bus = Bus {
+fn add_bus(bus: BusPayload) -> Result<Bus, Error> {
+    let bus_model_id = bus.model_id;
+    let registration_plate = normalize_plate(&bus.registration_plate)?;
+    let vin = normalize_vin(&bus.vin)?;
+    ensure_bus_identifiers_free(&registration_plate, &vin, None)?;
+    let id = next_id();
+    let mut bus = Bus {
         id,
         make: bus.make,
         model: bus.model,
@@ -136,22 +714,52 @@ fn add_bus(bus: BusPayload) -> Option<Bus> {
         updated_at: None,
         owner: bus.owner,
         is_booked: bus.is_booked, // Set is_booked from payload
+        fare: bus.fare,
+        capacity: bus.capacity,
+        odometer_km: 0,
+        registration_plate: Some(registration_plate),
+        vin: Some(vin),
+        model_id: None,
+        amenities: bus.amenities,
     };
+    if let Some(model_id) = bus_model_id {
+        apply_bus_model(&mut bus, model_id)?;
+    }
+    ensure_bus_fits(&bus)?;
+    do_index_bus(&bus);
     do_insert_bus(&bus);
-    Some(bus)
+    Ok(bus)
 }
 
 #[ic_cdk::update]
 fn update_bus(id: u64, payload: BusPayload) -> Result<Bus, Error> {
     match BUS_STORAGE.with(|service| service.borrow().get(&id)) {
-        Some(mut bus) => {
-            bus.make = payload.make;
-            bus.model = payload.model;
+        Some(current) => {
+            let registration_plate = normalize_plate(&payload.registration_plate)?;
+            let vin = normalize_vin(&payload.vin)?;
+            ensure_bus_identifiers_free(&registration_plate, &vin, Some(id))?;
+            let mut bus = current.clone();
+            match payload.model_id {
+                Some(model_id) => apply_bus_model(&mut bus, model_id)?,
+                None => {
+                    bus.model_id = None;
+                    bus.make = payload.make;
+                    bus.model = payload.model;
+                    bus.capacity = payload.capacity;
+                }
+            }
             bus.year = payload.year;
             bus.color = payload.color;
             bus.updated_at = Some(time());
             bus.owner = payload.owner;
             bus.is_booked = payload.is_booked; // Update is_booked field
+            bus.fare = payload.fare;
+            bus.amenities = payload.amenities;
+            bus.registration_plate = Some(registration_plate);
+            bus.vin = Some(vin);
+            ensure_bus_fits(&bus)?;
+            do_unindex_bus(&current);
+            do_index_bus(&bus);
             do_insert_bus(&bus);
             Ok(bus)
         }
@@ -167,21 +775,57 @@ fn update_bus(id: u64, payload: BusPayload) -> Result<Bus, Error> {
 #[ic_cdk::query]
 fn is_booked(id: u64) -> Result<bool, Error> {
     match _get_bus(&id) {
-        Some(bus) => Ok(bus.is_booked),
+        Some(bus) => {
+            let now = time();
+            Ok(bus.is_booked || _find_overlapping_block(id, now, now + 1).is_some())
+        }
         None => Err(Error::NotFound {
             msg: format!("a bus with id={} not found", id),
         }),
     }
 }
 
+// Make, model, colour and owner are free text, so check the record fits before storing it
+fn ensure_bus_fits(bus: &Bus) -> Result<(), Error> {
+    if bus.to_bytes().len() > Bus::MAX_SIZE as usize {
+        return Err(Error::InvalidInput {
+            msg: "the bus' make, model, colour, owner or amenities are too long".to_string(),
+        });
+    }
+    Ok(())
+}
+
 fn do_insert_bus(bus: &Bus) {
     BUS_STORAGE.with(|service| service.borrow_mut().insert(bus.id, bus.clone()));
 }
 
+// Refused while the bus still has work ahead of it; its past calendar blocks go with it
 #[ic_cdk::update]
 fn delete_bus(id: u64) -> Result<Bus, Error> {
+    if _get_bus(&id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!(
+                "couldn't delete a bus with id={}. bus not found.",
+                id
+            ),
+        });
+    }
+    ensure_bus_unused(id, time())?;
     match BUS_STORAGE.with(|service| service.borrow_mut().remove(&id)) {
-        Some(bus) => Ok(bus),
+        Some(bus) => {
+            do_unindex_bus(&bus);
+            BUS_CALENDAR.with(|service| {
+                let mut calendar = service.borrow_mut();
+                let starts: Vec<u64> = calendar
+                    .range((id, 0)..=(id, u64::MAX))
+                    .map(|((_, start), _)| start)
+                    .collect();
+                for start in starts {
+                    calendar.remove(&(id, start));
+                }
+            });
+            Ok(bus)
+        }
         None => Err(Error::NotFound {
             msg: format!(
                 "couldn't delete a bus with id={}. bus not found.",
@@ -191,35 +835,334 @@ fn delete_bus(id: u64) -> Result<Bus, Error> {
     }
 }
 
-#[ic_cdk::update]
-fn add_customer(name: String, contact: String) -> Option<Customer> {
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_value = *counter.borrow().get();
-            counter.borrow_mut().set(current_value + 1)
+// A bus still in use by unfinished trips, open reservations, timetables or upcoming
+// charters and maintenance can't be deleted
+fn ensure_bus_unused(bus_id: u64, now: u64) -> Result<(), Error> {
+    let has_trips = TRIP_STORAGE.with(|service| {
+        service.borrow().iter().any(|(_, trip)| {
+            trip.bus_id == bus_id
+                && matches!(trip.status, TripStatus::Scheduled | TripStatus::InProgress)
         })
-        .expect("cannot increment id counter");
-    let customer = Customer {
-        id,
-        name,
-        contact,
-    };
-    do_insert_customer(&customer);
-    Some(customer)
-}
-
-fn do_insert_customer(customer: &Customer) {
-    // Assuming MemoryId::new(2) is reserved for customer storage
-    let customer_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)));
-    StableBTreeMap::<u64, Customer, Memory>::init(customer_storage)
-        .borrow_mut()
-        .insert(customer.id, customer.clone());
+    });
+    let has_reservations = _get_reservations().iter().any(|reservation| {
+        reservation.bus_id == bus_id && reservation.status == ReservationStatus::Confirmed
+    });
+    let in_timetable = TIMETABLE_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .any(|(_, timetable)| timetable.bus_ids.contains(&bus_id))
+    });
+    let has_bookings = BUS_CALENDAR.with(|service| {
+        service
+            .borrow()
+            .range((bus_id, 0)..=(bus_id, u64::MAX))
+            .any(|(_, block)| block.end > now)
+    });
+    let in_use = [
+        (has_trips, "trips that haven't finished"),
+        (has_reservations, "confirmed reservations"),
+        (in_timetable, "timetables running it"),
+        (has_bookings, "upcoming charters or maintenance"),
+    ];
+    match in_use.iter().find(|(used, _)| *used) {
+        Some((_, what)) => Err(Error::Conflict {
+            msg: format!("bus with id={} still has {}", bus_id, what),
+        }),
+        None => Ok(()),
+    }
 }
 
-#[ic_cdk::query]
-fn get_customer(id: u64) -> Result<Customer, Error> {
-    match _get_customer(&id) {
-        Some(customer) => Ok(customer),
+#[ic_cdk::update]
+fn add_bus_model(payload: BusModelPayload) -> Result<BusModel, Error> {
+    ensure_admin()?;
+    if payload.deck_count == 0 || payload.deck_count > 2 {
+        return Err(Error::InvalidInput {
+            msg: "a bus model has one or two decks".to_string(),
+        });
+    }
+    if payload.seats.is_empty() || payload.seats.len() > MAX_MODEL_SEATS {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a seat layout must have between 1 and {} seats",
+                MAX_MODEL_SEATS
+            ),
+        });
+    }
+    let mut seat_numbers = BTreeSet::new();
+    let mut positions = BTreeSet::new();
+    for seat in &payload.seats {
+        if seat.seat_number == 0 || !seat_numbers.insert(seat.seat_number) {
+            return Err(Error::InvalidInput {
+                msg: format!("seat number {} is missing or repeated", seat.seat_number),
+            });
+        }
+        if seat.deck == 0 || seat.deck > payload.deck_count {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "seat {} is on a deck the model doesn't have",
+                    seat.seat_number
+                ),
+            });
+        }
+        if !positions.insert((seat.deck, seat.row, seat.column)) {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "seat {} shares its position with another seat",
+                    seat.seat_number
+                ),
+            });
+        }
+    }
+    let mut priced_classes = BTreeSet::new();
+    for class_fare in payload.class_fares.iter().flatten() {
+        if !priced_classes.insert(class_fare.seat_class) {
+            return Err(Error::InvalidInput {
+                msg: format!("seat class {:?} is priced twice", class_fare.seat_class),
+            });
+        }
+        if class_fare.fare_percentage == 0 || class_fare.fare_percentage > MAX_CLASS_FARE_PERCENTAGE
+        {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "a seat class must cost between 1% and {}% of the base fare",
+                    MAX_CLASS_FARE_PERCENTAGE
+                ),
+            });
+        }
+    }
+    let mut seats = payload.seats;
+    seats.sort_by_key(|seat| seat.seat_number);
+    let model = BusModel {
+        id: next_id(),
+        make: payload.make,
+        model: payload.model,
+        deck_count: payload.deck_count,
+        seats,
+        wheelchair_spaces: payload.wheelchair_spaces,
+        created_at: time(),
+        class_fares: payload.class_fares,
+    };
+    if model.to_bytes().len() > BusModel::MAX_SIZE as usize {
+        return Err(Error::InvalidInput {
+            msg: "the model's make or model name is too long".to_string(),
+        });
+    }
+    BUS_MODEL_STORAGE.with(|service| service.borrow_mut().insert(model.id, model.clone()));
+    Ok(model)
+}
+
+#[ic_cdk::query]
+fn get_bus_model(id: u64) -> Result<BusModel, Error> {
+    match _get_bus_model(&id) {
+        Some(model) => Ok(model),
+        None => Err(Error::NotFound {
+            msg: format!("a bus model with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn list_bus_models() -> Vec<BusModel> {
+    BUS_MODEL_STORAGE.with(|service| service.borrow().iter().map(|(_, model)| model).collect())
+}
+
+#[ic_cdk::update]
+fn delete_bus_model(id: u64) -> Result<BusModel, Error> {
+    ensure_admin()?;
+    let in_use = BUS_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .any(|(_, bus)| bus.model_id == Some(id))
+    });
+    if in_use {
+        return Err(Error::Conflict {
+            msg: format!("bus model with id={} is still used by a bus", id),
+        });
+    }
+    match BUS_MODEL_STORAGE.with(|service| service.borrow_mut().remove(&id)) {
+        Some(model) => Ok(model),
+        None => Err(Error::NotFound {
+            msg: format!("a bus model with id={} not found", id),
+        }),
+    }
+}
+
+// Seat occupancy is per trip; pass no trip for bookings made directly against the bus. A seat
+// shows as occupied if it's taken on any leg of the trip.
+#[ic_cdk::query]
+fn get_seat_map(bus_id: u64, trip_id: Option<u64>) -> Result<Vec<SeatMapEntry>, Error> {
+    let bus = _get_bus(&bus_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus with id={} not found", bus_id),
+    })?;
+    let occupied = _get_occupied_seats(bus_id, trip_id, ALL_LEGS);
+    Ok(_get_bus_seats(&bus)
+        .into_iter()
+        .map(|seat| SeatMapEntry {
+            is_occupied: occupied.contains(&seat.seat_number),
+            seat,
+        })
+        .collect())
+}
+
+// Copies make, model and capacity from the catalog so they can't drift from the layout
+fn apply_bus_model(bus: &mut Bus, model_id: u64) -> Result<(), Error> {
+    let model = _get_bus_model(&model_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus model with id={} not found", model_id),
+    })?;
+    bus.make = model.make;
+    bus.model = model.model;
+    bus.capacity = model.seats.len() as u32;
+    bus.model_id = Some(model_id);
+    Ok(())
+}
+
+// Buses without a catalog model get a plain single-deck layout, four seats to a row
+fn _get_bus_seats(bus: &Bus) -> Vec<SeatTemplate> {
+    match bus.model_id.and_then(|model_id| _get_bus_model(&model_id)) {
+        Some(model) => model.seats,
+        None => (1..=bus.capacity)
+            .map(|seat_number| SeatTemplate {
+                seat_number,
+                deck: 1,
+                row: ((seat_number - 1) / 4 + 1) as u16,
+                column: ((seat_number - 1) % 4 + 1) as u16,
+                seat_class: SeatClass::Standard,
+                is_wheelchair_accessible: false,
+            })
+            .collect(),
+    }
+}
+
+// What a seat of the given class costs on this bus before any passenger discount
+fn class_fare(bus: &Bus, seat_class: SeatClass) -> u64 {
+    let percentage = match bus.model_id.and_then(|model_id| _get_bus_model(&model_id)) {
+        Some(model) => model.fare_percentage(seat_class),
+        None => seat_class.fare_percentage(),
+    };
+    bus.fare * percentage / 100
+}
+
+fn _get_bus_model(id: &u64) -> Option<BusModel> {
+    BUS_MODEL_STORAGE.with(|service| service.borrow().get(id))
+}
+
+#[ic_cdk::query]
+fn get_bus_by_plate(registration_plate: String) -> Result<Bus, Error> {
+    let plate = normalize_plate(&registration_plate)?;
+    PLATE_INDEX
+        .with(|index| index.borrow().get(&StringKey(plate.clone())))
+        .and_then(|id| _get_bus(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a bus with registration plate {} not found", plate),
+        })
+}
+
+#[ic_cdk::query]
+fn get_bus_by_vin(vin: String) -> Result<Bus, Error> {
+    let vin = normalize_vin(&vin)?;
+    VIN_INDEX
+        .with(|index| index.borrow().get(&StringKey(vin.clone())))
+        .and_then(|id| _get_bus(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a bus with VIN {} not found", vin),
+        })
+}
+
+// Plates are compared without spaces, dashes or case: "ab-12 cd" and "AB12CD" are the same plate
+fn normalize_plate(plate: &str) -> Result<String, Error> {
+    let plate: String = plate
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    if plate.len() < 2 || plate.len() > 12 || !plate.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::InvalidInput {
+            msg: "a registration plate must have 2-12 letters or digits".to_string(),
+        });
+    }
+    Ok(plate)
+}
+
+// VINs are 17 characters and never use I, O or Q (ISO 3779)
+fn normalize_vin(vin: &str) -> Result<String, Error> {
+    let vin = vin.trim().to_uppercase();
+    let valid_chars = vin
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() && !matches!(c, 'I' | 'O' | 'Q'));
+    if vin.len() != 17 || !valid_chars {
+        return Err(Error::InvalidInput {
+            msg: "a VIN must be 17 letters or digits, excluding I, O and Q".to_string(),
+        });
+    }
+    Ok(vin)
+}
+
+// `bus_id` is the bus being updated, which may keep its own plate and VIN
+fn ensure_bus_identifiers_free(plate: &str, vin: &str, bus_id: Option<u64>) -> Result<(), Error> {
+    let plate_owner = PLATE_INDEX.with(|index| index.borrow().get(&StringKey(plate.to_string())));
+    if let Some(owner) = plate_owner.filter(|owner| Some(*owner) != bus_id) {
+        return Err(Error::Conflict {
+            msg: format!(
+                "registration plate {} is already used by bus with id={}",
+                plate, owner
+            ),
+        });
+    }
+    let vin_owner = VIN_INDEX.with(|index| index.borrow().get(&StringKey(vin.to_string())));
+    if let Some(owner) = vin_owner.filter(|owner| Some(*owner) != bus_id) {
+        return Err(Error::Conflict {
+            msg: format!("VIN {} is already used by bus with id={}", vin, owner),
+        });
+    }
+    Ok(())
+}
+
+fn do_index_bus(bus: &Bus) {
+    if let Some(plate) = &bus.registration_plate {
+        PLATE_INDEX.with(|index| index.borrow_mut().insert(StringKey(plate.clone()), bus.id));
+    }
+    if let Some(vin) = &bus.vin {
+        VIN_INDEX.with(|index| index.borrow_mut().insert(StringKey(vin.clone()), bus.id));
+    }
+}
+
+fn do_unindex_bus(bus: &Bus) {
+    if let Some(plate) = &bus.registration_plate {
+        PLATE_INDEX.with(|index| index.borrow_mut().remove(&StringKey(plate.clone())));
+    }
+    if let Some(vin) = &bus.vin {
+        VIN_INDEX.with(|index| index.borrow_mut().remove(&StringKey(vin.clone())));
+    }
+}
+
+#[ic_cdk::update]
+fn add_customer(name: String, contact: String) -> Option<Customer> {
+    let id = next_id();
+    let caller = ic_cdk::caller();
+    let customer = Customer {
+        id,
+        name,
+        contact,
+        principal: (caller != Principal::anonymous()).then_some(caller),
+    };
+    do_insert_customer(&customer);
+    Some(customer)
+}
+
+fn do_insert_customer(customer: &Customer) {
+    // Assuming MemoryId::new(2) is reserved for customer storage
+    let customer_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)));
+    StableBTreeMap::<u64, Customer, Memory>::init(customer_storage)
+        .borrow_mut()
+        .insert(customer.id, customer.clone());
+}
+
+#[ic_cdk::query]
+fn get_customer(id: u64) -> Result<Customer, Error> {
+    match _get_customer(&id) {
+        Some(customer) => Ok(customer),
         None => Err(Error::NotFound {
             msg: format!("a customer with id={} not found", id),
         }),
@@ -252,71 +1195,834 @@ fn delete_customer(id: u64) -> Result<Customer, Error> {
 }
 
 #[ic_cdk::update]
-fn make_reservation(bus_id: u64, customer_id: u64) -> Result<Reservation, Error> {
-    match (_get_bus(&bus_id), _get_customer(&customer_id)) {
-        (Some(_), Some(_)) => {
-            let reservation = Reservation {
-                bus_id,
-                customer_id,
-                reservation_time: time(),
-            };
-            do_insert_reservation(&reservation);
-            Ok(reservation)
+fn make_reservation(
+    bus_id: u64,
+    customer_id: u64,
+    trip_id: Option<u64>,
+    from_stop: Option<u64>,
+    to_stop: Option<u64>,
+    promo_code: Option<String>,
+    redeem_points: Option<u64>,
+) -> Result<Reservation, Error> {
+    ensure_customer_caller(customer_id)?;
+    let customer = _get_customer(&customer_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a customer with id={} not found", customer_id),
+    })?;
+    do_make_reservation(GroupReservationPayload {
+        bus_id,
+        trip_id,
+        from_stop,
+        to_stop,
+        customer_id,
+        passengers: vec![PassengerPayload {
+            name: customer.name,
+            category: FareCategory::Adult,
+            seat_class: None,
+        }],
+        promo_code,
+        redeem_points,
+    })
+}
+
+#[ic_cdk::update]
+fn make_group_reservation(payload: GroupReservationPayload) -> Result<Reservation, Error> {
+    ensure_customer_caller(payload.customer_id)?;
+    do_make_reservation(payload)
+}
+
+// Books every passenger or none of them. Validation, seat allocation and redemption happen in
+// the same message without awaiting, so concurrent calls cannot both pass the same checks.
+fn do_make_reservation(payload: GroupReservationPayload) -> Result<Reservation, Error> {
+    let (bus, _) = match (
+        _get_bus(&payload.bus_id),
+        _get_customer(&payload.customer_id),
+    ) {
+        (Some(bus), Some(customer)) => (bus, customer),
+        _ => {
+            return Err(Error::NotFound {
+                msg: "Bus or customer not found for reservation".to_string(),
+            })
         }
-        _ => Err(Error::NotFound {
-            msg: "Bus or customer not found for reservation".to_string(),
-        }),
+    };
+    if payload.passengers.is_empty() || payload.passengers.len() > MAX_GROUP_SIZE {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a reservation must have between 1 and {} passengers",
+                MAX_GROUP_SIZE
+            ),
+        });
+    }
+    if payload
+        .passengers
+        .iter()
+        .any(|passenger| passenger.name.trim().is_empty() || passenger.name.len() > 64)
+    {
+        return Err(Error::InvalidInput {
+            msg: "passenger names must be between 1 and 64 characters".to_string(),
+        });
+    }
+    let now = time();
+    let trip = match payload.trip_id {
+        Some(trip_id) => match _get_trip(&trip_id) {
+            Some(trip) if trip.bus_id != bus.id => {
+                return Err(Error::InvalidInput {
+                    msg: format!("trip with id={} doesn't run on bus id={}", trip_id, bus.id),
+                })
+            }
+            Some(trip) if trip.status != TripStatus::Scheduled || trip.departure_time <= now => {
+                return Err(Error::InvalidInput {
+                    msg: format!("trip with id={} is no longer open for booking", trip_id),
+                })
+            }
+            Some(trip) => Some(trip),
+            None => {
+                return Err(Error::NotFound {
+                    msg: format!("a trip with id={} not found", trip_id),
+                })
+            }
+        },
+        None => {
+            // Bookings on the bus itself are for travel now; trips check the calendar when assigned
+            ensure_bus_available(bus.id, now, now + 1)?;
+            None
+        }
+    };
+    // Partial legs are priced by the share of the route's distance they cover
+    let (legs, stops, (share, whole)) = match trip
+        .as_ref()
+        .and_then(|trip| trip.route_id)
+        .and_then(|route_id| _get_route(&route_id))
+    {
+        Some(route) => {
+            let (from, to) = route_legs(&route, payload.from_stop, payload.to_stop)?;
+            (
+                leg_mask(from, to)?,
+                (
+                    Some(route.stops[from].stop_id),
+                    Some(route.stops[to].stop_id),
+                ),
+                route_share(&route, from, to),
+            )
+        }
+        None if payload.from_stop.is_some() || payload.to_stop.is_some() => {
+            return Err(Error::InvalidInput {
+                msg: "boarding and alighting stops need a trip that follows a route".to_string(),
+            })
+        }
+        None => (ALL_LEGS, (None, None), (1, 1)),
+    };
+    let seat_classes: Vec<SeatClass> = payload
+        .passengers
+        .iter()
+        .map(|passenger| passenger.seat_class.unwrap_or(SeatClass::Standard))
+        .collect();
+    let seats = allocate_seats(&bus, payload.trip_id, legs, &seat_classes)?;
+    let passengers: Vec<Passenger> = payload
+        .passengers
+        .into_iter()
+        .zip(seats.into_iter().zip(seat_classes))
+        .enumerate()
+        .map(
+            |(index, (passenger, (seat_number, seat_class)))| Passenger {
+                id: index as u32 + 1,
+                name: passenger.name.trim().to_string(),
+                fare: class_fare(&bus, seat_class) * passenger.category.fare_percentage() / 100
+                    * share
+                    / whole,
+                category: passenger.category,
+                seat_number,
+                seat_class,
+                cancelled_at: None,
+            },
+        )
+        .collect();
+    let fare = passengers.iter().map(|passenger| passenger.fare).sum();
+    let redemption = match payload.promo_code {
+        Some(code) => Some(check_promo_code(
+            &code,
+            &bus,
+            trip.as_ref(),
+            payload.customer_id,
+            fare,
+            now,
+        )?),
+        None => None,
+    };
+    let discount = redemption.as_ref().map_or(0, |(_, discount)| *discount);
+    let points_redeemed = payload.redeem_points.unwrap_or(0);
+    if points_redeemed > 0 {
+        let balance = do_expire_points(payload.customer_id, now).balance;
+        if points_redeemed > balance {
+            return Err(Error::InvalidInput {
+                msg: format!("customer only has {} loyalty points", balance),
+            });
+        }
+        if points_redeemed * LOYALTY_POINT_VALUE > fare - discount {
+            return Err(Error::InvalidInput {
+                msg: "cannot redeem more points than the fare is worth".to_string(),
+            });
+        }
+    }
+    let reservation = Reservation {
+        id: next_id(),
+        bus_id: bus.id,
+        trip_id: payload.trip_id,
+        from_stop: stops.0,
+        to_stop: stops.1,
+        customer_id: payload.customer_id,
+        reservation_time: now,
+        fare,
+        discount,
+        promo_code: redemption.as_ref().map(|(promo, _)| promo.code.clone()),
+        points_redeemed,
+        status: ReservationStatus::Confirmed,
+        passengers,
+    };
+    if let Some((promo, discount)) = redemption {
+        do_redeem_promo_code(promo, reservation.customer_id, discount);
+    }
+    if points_redeemed > 0 {
+        do_redeem_points(
+            reservation.customer_id,
+            points_redeemed,
+            reservation.id,
+            now,
+        );
+    }
+    do_insert_reservation(&reservation);
+    Ok(reservation)
+}
+
+// Seats each passenger in the class they asked for (`seat_classes` is one entry per passenger).
+// Within a class, a block of adjacent seats is preferred so the group sits together, otherwise
+// the lowest free seats are taken.
+fn allocate_seats(
+    bus: &Bus,
+    trip_id: Option<u64>,
+    legs: u64,
+    seat_classes: &[SeatClass],
+) -> Result<Vec<u32>, Error> {
+    let occupied = _get_occupied_seats(bus.id, trip_id, legs);
+    let seats = _get_bus_seats(bus);
+    let mut wanted: BTreeMap<SeatClass, usize> = BTreeMap::new();
+    for seat_class in seat_classes {
+        *wanted.entry(*seat_class).or_default() += 1;
+    }
+    let mut allocated: BTreeMap<SeatClass, Vec<u32>> = BTreeMap::new();
+    for (seat_class, count) in wanted {
+        let free: Vec<u32> = seats
+            .iter()
+            .filter(|seat| seat.seat_class == seat_class && !occupied.contains(&seat.seat_number))
+            .map(|seat| seat.seat_number)
+            .collect();
+        if free.len() < count {
+            return Err(Error::Conflict {
+                msg: format!(
+                    "bus with id={} has {} free {:?} seats, {} requested",
+                    bus.id,
+                    free.len(),
+                    seat_class,
+                    count
+                ),
+            });
+        }
+        let adjacent = free
+            .windows(count)
+            .find(|block| block[count - 1] - block[0] == count as u32 - 1);
+        let mut block = match adjacent {
+            Some(block) => block.to_vec(),
+            None => free[..count].to_vec(),
+        };
+        // Hand seats out front to back as passengers are zipped in order
+        block.reverse();
+        allocated.insert(seat_class, block);
+    }
+    Ok(seat_classes
+        .iter()
+        .map(|seat_class| {
+            allocated
+                .get_mut(seat_class)
+                .and_then(|seats| seats.pop())
+                .expect("a seat was allocated for every passenger")
+        })
+        .collect())
+}
+
+// Seats taken on any of the given legs
+fn _get_occupied_seats(bus_id: u64, trip_id: Option<u64>, legs: u64) -> BTreeSet<u32> {
+    _get_seat_occupancy(bus_id, trip_id)
+        .into_iter()
+        .filter(|(_, occupied)| occupied & legs != 0)
+        .map(|(seat_number, _)| seat_number)
+        .collect()
+}
+
+// For every booked seat, a bitmap of the legs it's taken on (see `leg_mask`). Bookings without
+// a trip, or on trips without a route, take the seat for the whole journey.
+fn _get_seat_occupancy(bus_id: u64, trip_id: Option<u64>) -> BTreeMap<u32, u64> {
+    let route = trip_id
+        .and_then(|trip_id| _get_trip(&trip_id))
+        .and_then(|trip| trip.route_id)
+        .and_then(|route_id| _get_route(&route_id));
+    let mut occupancy: BTreeMap<u32, u64> = BTreeMap::new();
+    for reservation in _get_reservations() {
+        if reservation.bus_id != bus_id
+            || reservation.trip_id != trip_id
+            || reservation.status != ReservationStatus::Confirmed
+        {
+            continue;
+        }
+        let legs = route.as_ref().map_or(ALL_LEGS, |route| {
+            route_legs(route, reservation.from_stop, reservation.to_stop)
+                .and_then(|(from, to)| leg_mask(from, to))
+                .unwrap_or(ALL_LEGS)
+        });
+        for passenger in reservation.active_passengers() {
+            *occupancy.entry(passenger.seat_number).or_default() |= legs;
+        }
+    }
+    occupancy
+}
+
+// Positions of the boarding and alighting stops on the route. Missing stops default to the
+// ends of the route; the alighting stop must come after the boarding one.
+fn route_legs(
+    route: &Route,
+    from_stop: Option<u64>,
+    to_stop: Option<u64>,
+) -> Result<(usize, usize), Error> {
+    let position = |stop_id: u64, after: usize| {
+        route.stops[after..]
+            .iter()
+            .position(|route_stop| route_stop.stop_id == stop_id)
+            .map(|index| index + after)
+            .ok_or_else(|| Error::InvalidInput {
+                msg: format!("stop id={} is not on route id={}", stop_id, route.id),
+            })
+    };
+    let from = match from_stop {
+        Some(stop_id) => position(stop_id, 0)?,
+        None => 0,
+    };
+    let to = match to_stop {
+        Some(stop_id) => position(stop_id, from + 1)?,
+        None => route.stops.len() - 1,
+    };
+    if to <= from {
+        return Err(Error::InvalidInput {
+            msg: "the alighting stop must come after the boarding stop".to_string(),
+        });
+    }
+    Ok((from, to))
+}
+
+// Bit i stands for the leg from the route's stop i to stop i + 1, so only the first 64 legs of
+// a route can be told apart. Routes are capped at MAX_ROUTE_STOPS, but ones stored before the
+// cap can be longer.
+fn leg_mask(from: usize, to: usize) -> Result<u64, Error> {
+    if from >= to || to > u64::BITS as usize {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "legs from stop {} to stop {} can't be booked separately",
+                from, to
+            ),
+        });
+    }
+    Ok((u64::MAX >> (64 - to)) & (u64::MAX << from))
+}
+
+// The fraction (numerator, denominator) of the route between two stops, by distance, or by
+// number of legs for routes without distances
+fn route_share(route: &Route, from: usize, to: usize) -> (u64, u64) {
+    let total = route
+        .stops
+        .last()
+        .map_or(0, |route_stop| route_stop.distance_m);
+    if total > 0 {
+        (
+            route.stops[to].distance_m - route.stops[from].distance_m,
+            total,
+        )
+    } else {
+        ((to - from) as u64, route.stops.len() as u64 - 1)
     }
 }
 
 fn do_insert_reservation(reservation: &Reservation) {
-    // Assuming MemoryId::new(3) is reserved for reservation storage
-    let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)));
-    
+    // Assuming MemoryId::new(32) is reserved for reservation storage
+    let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)));
+
     StableBTreeMap::<u64, Reservation, Memory>::init(reservation_storage)
         .borrow_mut()
-        .insert(reservation.bus_id, reservation.clone());
+        .insert(reservation.id, reservation.clone());
 }
 
 #[ic_cdk::query]
-fn get_reservation(bus_id: u64) -> Result<Reservation, Error> {
-    match _get_reservation(&bus_id) {
+fn get_reservation(id: u64) -> Result<Reservation, Error> {
+    match _get_reservation(&id) {
         Some(reservation) => Ok(reservation),
         None => Err(Error::NotFound {
-            msg: format!("a reservation for bus_id={} not found", bus_id),
+            msg: format!("a reservation with id={} not found", id),
         }),
     }
 }
 
-fn _get_reservation(bus_id: &u64) -> Option<Reservation> {
-    // Assuming MemoryId::new(3) is reserved for reservation storage
-    let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)));
+fn _get_reservation(id: &u64) -> Option<Reservation> {
+    // Assuming MemoryId::new(32) is reserved for reservation storage
+    let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)));
     StableBTreeMap::<u64, Reservation, Memory>::init(reservation_storage)
         .borrow()
-        .get(bus_id)
+        .get(id)
+}
+
+fn _get_reservations() -> Vec<Reservation> {
+    // Assuming MemoryId::new(32) is reserved for reservation storage
+    let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)));
+    StableBTreeMap::<u64, Reservation, Memory>::init(reservation_storage)
+        .iter()
+        .map(|(_, reservation)| reservation)
+        .collect()
 }
 
 #[ic_cdk::update]
-fn cancel_reservation(bus_id: u64) -> Result<(), Error> {
-    match _get_reservation(&bus_id) {
-        Some(_) => {
-            // Assuming MemoryId::new(3) is reserved for reservation storage
-            let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)));
+fn cancel_reservation(id: u64) -> Result<(), Error> {
+    match _get_reservation(&id) {
+        Some(reservation) => {
+            ensure_customer_caller(reservation.customer_id)?;
+            ensure_reservation_confirmed(&reservation)?;
+            if let Some(code) = &reservation.promo_code {
+                do_release_promo_code(code, reservation.customer_id, reservation.discount);
+            }
+            if reservation.points_redeemed > 0 {
+                do_refund_points(
+                    reservation.customer_id,
+                    reservation.points_redeemed,
+                    id,
+                    time(),
+                );
+            }
+            // Assuming MemoryId::new(32) is reserved for reservation storage
+            let reservation_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)));
             StableBTreeMap::<u64, Reservation, Memory>::init(reservation_storage)
                 .borrow_mut()
-                .remove(&bus_id);
+                .remove(&id);
             Ok(())
         }
         None => Err(Error::NotFound {
-            msg: format!("a reservation for bus_id={} not found", bus_id),
+            msg: format!("a reservation with id={} not found", id),
+        }),
+    }
+}
+
+// Frees one passenger's seat. The passenger's share of the discount and redeemed points is
+// taken off the reservation: the discount goes back to the promo code's budget and the points
+// to the customer.
+#[ic_cdk::update]
+fn cancel_passenger(reservation_id: u64, passenger_id: u32) -> Result<Reservation, Error> {
+    let mut reservation = _get_reservation(&reservation_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a reservation with id={} not found", reservation_id),
+    })?;
+    ensure_customer_caller(reservation.customer_id)?;
+    ensure_reservation_confirmed(&reservation)?;
+    let active = reservation.active_passengers().count();
+    let passenger = reservation
+        .passengers
+        .iter_mut()
+        .find(|passenger| passenger.id == passenger_id && passenger.cancelled_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "an active passenger with id={} not found on reservation id={}",
+                passenger_id, reservation_id
+            ),
+        })?;
+    if active == 1 {
+        return Err(Error::InvalidInput {
+            msg: "cannot cancel the last passenger, cancel the reservation instead".to_string(),
+        });
+    }
+    let now = time();
+    passenger.cancelled_at = Some(now);
+    let passenger_fare = passenger.fare;
+    let (discount_share, points_share) = reservation.split_off_fare(passenger_fare);
+    if let Some(code) = reservation
+        .promo_code
+        .as_deref()
+        .filter(|_| discount_share > 0)
+    {
+        do_release_promo_discount(code, discount_share);
+    }
+    if points_share > 0 {
+        do_refund_points(reservation.customer_id, points_share, reservation_id, now);
+    }
+    do_insert_reservation(&reservation);
+    Ok(reservation)
+}
+
+// Marks the trip as travelled and credits the customer with loyalty points for what they paid
+#[ic_cdk::update]
+fn complete_reservation(id: u64) -> Result<Reservation, Error> {
+    ensure_admin()?;
+    match _get_reservation(&id) {
+        Some(mut reservation) => {
+            ensure_reservation_confirmed(&reservation)?;
+            reservation.status = ReservationStatus::Completed;
+            let points = reservation.amount_due() / LOYALTY_EARN_DIVISOR;
+            if points > 0 {
+                do_credit_points(reservation.customer_id, points, id, time());
+            }
+            do_insert_reservation(&reservation);
+            Ok(reservation)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("a reservation with id={} not found", id),
+        }),
+    }
+}
+
+fn ensure_reservation_confirmed(reservation: &Reservation) -> Result<(), Error> {
+    let state = match reservation.status {
+        ReservationStatus::Confirmed => return Ok(()),
+        ReservationStatus::Completed => "completed",
+        ReservationStatus::Refunded => "refunded",
+    };
+    Err(Error::InvalidInput {
+        msg: format!(
+            "the reservation with id={} is already {}",
+            reservation.id, state
+        ),
+    })
+}
+
+#[ic_cdk::update]
+fn add_trip(payload: TripPayload) -> Result<Trip, Error> {
+    ensure_admin()?;
+    if _get_bus(&payload.bus_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", payload.bus_id),
+        });
+    }
+    if payload.arrival_time <= payload.departure_time {
+        return Err(Error::InvalidInput {
+            msg: "a trip must arrive after it departs".to_string(),
+        });
+    }
+    let (origin, destination) = match payload.route_id {
+        Some(route_id) => {
+            let route = _get_route(&route_id).ok_or_else(|| Error::NotFound {
+                msg: format!("a route with id={} not found", route_id),
+            })?;
+            ensure_route_length(&route)?;
+            _get_route_endpoints(&route)
+        }
+        None => {
+            let (origin, destination) = (payload.origin.trim(), payload.destination.trim());
+            if [origin, destination]
+                .iter()
+                .any(|place| place.is_empty() || place.len() > MAX_PLACE_NAME_LEN)
+            {
+                return Err(Error::InvalidInput {
+                    msg: format!(
+                        "origin and destination must be between 1 and {} bytes",
+                        MAX_PLACE_NAME_LEN
+                    ),
+                });
+            }
+            (origin.to_string(), destination.to_string())
+        }
+    };
+    do_create_trip(
+        TripPayload {
+            origin,
+            destination,
+            ..payload
+        },
+        None,
+    )
+}
+
+// Books the bus' calendar for the trip once it's known to be compliant and free
+fn do_create_trip(payload: TripPayload, timetable_id: Option<u64>) -> Result<Trip, Error> {
+    ensure_bus_compliant(payload.bus_id, payload.arrival_time)?;
+    ensure_bus_available(payload.bus_id, payload.departure_time, payload.arrival_time)?;
+    let trip = Trip {
+        id: next_id(),
+        bus_id: payload.bus_id,
+        origin: payload.origin,
+        destination: payload.destination,
+        departure_time: payload.departure_time,
+        arrival_time: payload.arrival_time,
+        status: TripStatus::Scheduled,
+        driver_id: None,
+        route_id: payload.route_id,
+        delay_minutes: 0,
+        actual_departure_time: None,
+        actual_arrival_time: None,
+        timetable_id,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_block_bus(BlockedInterval {
+        bus_id: trip.bus_id,
+        start: trip.departure_time,
+        end: trip.arrival_time,
+        reason: BlockReason::Trip,
+        reference_id: trip.id,
+    });
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+#[ic_cdk::query]
+fn get_trip(id: u64) -> Result<Trip, Error> {
+    match _get_trip(&id) {
+        Some(trip) => Ok(trip),
+        None => Err(Error::NotFound {
+            msg: format!("a trip with id={} not found", id),
         }),
     }
 }
 
+// Free seats per class between two stops of the trip's route. Seats booked only on legs
+// outside the segment count as free.
+#[ic_cdk::query]
+fn get_segment_availability(
+    trip_id: u64,
+    from_stop: u64,
+    to_stop: u64,
+) -> Result<SegmentAvailability, Error> {
+    let trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    let route = trip
+        .route_id
+        .and_then(|route_id| _get_route(&route_id))
+        .ok_or_else(|| Error::InvalidInput {
+            msg: format!("trip with id={} doesn't follow a route", trip_id),
+        })?;
+    let bus = _get_bus(&trip.bus_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus with id={} not found", trip.bus_id),
+    })?;
+    let (from, to) = route_legs(&route, Some(from_stop), Some(to_stop))?;
+    let occupied = _get_occupied_seats(bus.id, Some(trip_id), leg_mask(from, to)?);
+    let (share, whole) = route_share(&route, from, to);
+    let mut free: BTreeMap<SeatClass, u32> = BTreeMap::new();
+    for seat in _get_bus_seats(&bus) {
+        let count = free.entry(seat.seat_class).or_default();
+        if !occupied.contains(&seat.seat_number) {
+            *count += 1;
+        }
+    }
+    Ok(SegmentAvailability {
+        trip_id,
+        from_stop,
+        to_stop,
+        departure_time: trip.departure_time + route.stops[from].departure_offset,
+        arrival_time: trip.departure_time + route.stops[to].arrival_offset,
+        classes: free
+            .into_iter()
+            .map(|(seat_class, seats_available)| ClassAvailability {
+                fare: class_fare(&bus, seat_class) * share / whole,
+                seat_class,
+                seats_available,
+            })
+            .collect(),
+    })
+}
+
+fn do_insert_trip(trip: &Trip) {
+    if let Some(timetable_id) = trip.timetable_id {
+        TIMETABLE_TRIPS.with(|service| {
+            service
+                .borrow_mut()
+                .insert((timetable_id, trip.departure_time), trip.id)
+        });
+    }
+    TRIP_STORAGE.with(|service| service.borrow_mut().insert(trip.id, trip.clone()));
+}
+
+fn _get_trip(id: &u64) -> Option<Trip> {
+    TRIP_STORAGE.with(|service| service.borrow().get(id))
+}
+
+fn ensure_admin() -> Result<(), Error> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: "only a controller of the canister can perform this action".to_string(),
+        })
+    }
+}
+
+// Customers act on their own bookings through the principal that registered them; admins can act
+// on anyone's
+fn ensure_customer_caller(customer_id: u64) -> Result<(), Error> {
+    let caller = ic_cdk::caller();
+    let is_customer =
+        _get_customer(&customer_id).is_some_and(|customer| customer.principal == Some(caller));
+    if is_customer || ic_cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: format!("only customer id={} or an admin can do this", customer_id),
+        })
+    }
+}
+
+fn next_id() -> u64 {
+    ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment id counter")
+}
+
+// Layout of the records in stable memory. Bump it when records already written can no longer
+// be decoded or need indexing, and migrate them in `post_upgrade`.
+const STORAGE_VERSION: u32 = 2;
+
+#[ic_cdk::init]
+fn init() {
+    STORAGE_VERSION_CELL
+        .with(|cell| cell.borrow_mut().set(STORAGE_VERSION))
+        .expect("cannot store the storage version");
+    start_timers();
+}
+
+// Timers don't survive an upgrade, so `post_upgrade` starts them again through `init`
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(COMPLIANCE_SCAN_INTERVAL), || {
+        do_scan_compliance(time());
+    });
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(TIMETABLE_RUN_INTERVAL), || {
+        do_run_timetables(time());
+    });
+    ic_cdk_timers::set_timer_interval(Duration::from_nanos(GTFS_FEED_BUILD_INTERVAL), || {
+        do_start_gtfs_feed_build(time());
+    });
+    ic_cdk_timers::set_timer(Duration::ZERO, || do_start_gtfs_feed_build(time()));
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(do_draw_calendar_secret()));
+    do_schedule_gtfs_batch();
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let version = STORAGE_VERSION_CELL.with(|cell| *cell.borrow().get());
+    if version < 1 {
+        do_migrate_records_v1();
+    }
+    if version < 2 {
+        do_index_timetable_trips_v2();
+    }
+    init();
+}
+
+// Buses and reservations as the first release stored them
+#[derive(candid::CandidType, Deserialize)]
+struct StoredBus {
+    id: u64,
+    make: String,
+    model: String,
+    year: u32,
+    color: String,
+    created_at: u64,
+    updated_at: Option<u64>,
+    owner: String,
+    is_booked: bool,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+struct StoredReservation {
+    bus_id: u64,
+    customer_id: u64,
+    reservation_time: u64,
+}
+
+impl Storable for StoredBus {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for StoredBus {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl Storable for StoredReservation {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for StoredReservation {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Version 1 moves buses and reservations out of their first-release maps, whose records are
+// capped at 1 KiB, and fills in every field added since. First-release reservations were keyed
+// by bus and get an id of their own.
+fn do_migrate_records_v1() {
+    let legacy_buses = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)));
+    let buses: Vec<StoredBus> = StableBTreeMap::<u64, StoredBus, Memory>::init(legacy_buses)
+        .iter()
+        .map(|(_, bus)| bus)
+        .collect();
+    for bus in buses {
+        do_insert_bus(&Bus {
+            id: bus.id,
+            make: bus.make,
+            model: bus.model,
+            year: bus.year,
+            color: bus.color,
+            created_at: bus.created_at,
+            updated_at: bus.updated_at,
+            owner: bus.owner,
+            is_booked: bus.is_booked,
+            ..Default::default()
+        });
+    }
+
+    let legacy_reservations = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)));
+    let reservations: Vec<StoredReservation> =
+        StableBTreeMap::<u64, StoredReservation, Memory>::init(legacy_reservations)
+            .iter()
+            .map(|(_, reservation)| reservation)
+            .collect();
+    for reservation in reservations {
+        do_insert_reservation(&Reservation {
+            id: next_id(),
+            bus_id: reservation.bus_id,
+            customer_id: reservation.customer_id,
+            reservation_time: reservation.reservation_time,
+            ..Default::default()
+        });
+    }
+}
+
+// Version 2 indexes trips by the timetable that generated them
+fn do_index_timetable_trips_v2() {
+    let trips: Vec<Trip> =
+        TRIP_STORAGE.with(|service| service.borrow().iter().map(|(_, trip)| trip).collect());
+    for trip in trips.iter().filter(|trip| trip.timetable_id.is_some()) {
+        do_insert_trip(trip);
+    }
+}
+
 #[ic_cdk::query]
 fn generate_report() -> Vec<Bus> {
-    // Assuming MemoryId::new(1) is reserved for bus storage
-    let bus_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)));
+    // Assuming MemoryId::new(31) is reserved for bus storage
+    let bus_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)));
     StableBTreeMap::<u64, Bus, Memory>::init(bus_storage)
         .borrow()
         .iter()
@@ -327,14 +2033,293 @@ fn generate_report() -> Vec<Bus> {
 #[derive(candid::CandidType, Deserialize, Serialize)]
 enum Error {
     NotFound { msg: String },
+    InvalidInput { msg: String },
+    Unauthorized { msg: String },
+    Conflict { msg: String },
 }
 
 fn _get_bus(id: &u64) -> Option<Bus> {
-    // Assuming MemoryId::new(1) is reserved for bus storage
-    let bus_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)));
+    // Assuming MemoryId::new(31) is reserved for bus storage
+    let bus_storage = MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)));
     StableBTreeMap::<u64, Bus, Memory>::init(bus_storage)
         .borrow()
         .get(id)
 }
 
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records as the first release stored them
+    #[derive(candid::CandidType, Deserialize)]
+    struct FirstReleaseBus {
+        id: u64,
+        make: String,
+        model: String,
+        year: u32,
+        color: String,
+        created_at: u64,
+        updated_at: Option<u64>,
+        owner: String,
+        is_booked: bool,
+    }
+
+    #[derive(candid::CandidType, Deserialize)]
+    struct FirstReleaseReservation {
+        bus_id: u64,
+        customer_id: u64,
+        reservation_time: u64,
+    }
+
+    impl Storable for FirstReleaseBus {
+        fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+            Cow::Owned(Encode!(self).unwrap())
+        }
+
+        fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+            Decode!(bytes.as_ref(), Self).unwrap()
+        }
+    }
+
+    impl BoundedStorable for FirstReleaseBus {
+        const MAX_SIZE: u32 = 1024;
+        const IS_FIXED_SIZE: bool = false;
+    }
+
+    impl Storable for FirstReleaseReservation {
+        fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+            Cow::Owned(Encode!(self).unwrap())
+        }
+
+        fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+            Decode!(bytes.as_ref(), Self).unwrap()
+        }
+    }
+
+    impl BoundedStorable for FirstReleaseReservation {
+        const MAX_SIZE: u32 = 1024;
+        const IS_FIXED_SIZE: bool = false;
+    }
+
+    fn memory(id: u8) -> Memory {
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(id)))
+    }
+
+    #[test]
+    fn split_off_fare_keeps_discount_and_points_within_the_fare() {
+        // Three passengers at a fare of 1 with a discount of 1 and 2 points redeemed
+        let mut reservation = Reservation {
+            fare: 3,
+            discount: 1,
+            points_redeemed: 2,
+            ..Default::default()
+        };
+        let (discount, points) = reservation.split_off_fare(1);
+        assert_eq!(reservation.fare, 2);
+        assert_eq!((reservation.discount, reservation.points_redeemed), (1, 1));
+        assert_eq!((discount, points), (0, 1));
+        assert_eq!(reservation.amount_due(), 0);
+
+        for fare in 1..=12 {
+            for discount in 0..=fare {
+                for points in 0..=(fare - discount) / LOYALTY_POINT_VALUE {
+                    for part in 0..=fare {
+                        let mut reservation = Reservation {
+                            fare,
+                            discount,
+                            points_redeemed: points,
+                            ..Default::default()
+                        };
+                        let (discount_share, points_share) = reservation.split_off_fare(part);
+                        assert_eq!(reservation.fare, fare - part);
+                        assert_eq!(reservation.discount + discount_share, discount);
+                        assert_eq!(reservation.points_redeemed + points_share, points);
+                        assert!(
+                            reservation.discount
+                                + reservation.points_redeemed * LOYALTY_POINT_VALUE
+                                <= reservation.fare
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rebooking_splits_share_out_the_whole_discount_and_points() {
+        // Passengers paying 4, 3, 2 and 1: the second moves to another trip, the third is refunded
+        let mut reservation = Reservation {
+            fare: 10,
+            discount: 3,
+            points_redeemed: 4,
+            ..Default::default()
+        };
+        let (split_discount, split_points) = reservation.split_off_fare(3);
+        let (refunded_discount, refunded_points) = reservation.split_off_fare(2);
+        assert_eq!(reservation.fare, 5);
+        assert_eq!(split_discount + refunded_discount + reservation.discount, 3);
+        assert_eq!(
+            split_points + refunded_points + reservation.points_redeemed,
+            4
+        );
+        assert!(split_discount + split_points * LOYALTY_POINT_VALUE <= 3);
+        assert!(refunded_discount + refunded_points * LOYALTY_POINT_VALUE <= 2);
+        assert!(reservation.discount + reservation.points_redeemed * LOYALTY_POINT_VALUE <= 5);
+    }
+
+    #[test]
+    fn amount_due_never_underflows() {
+        let reservation = Reservation {
+            fare: 2,
+            discount: 1,
+            points_redeemed: 2,
+            ..Default::default()
+        };
+        assert_eq!(reservation.amount_due(), 0);
+    }
+
+    #[test]
+    fn leg_mask_covers_the_legs_between_the_stops() {
+        let mask = |from, to| leg_mask(from, to).ok().unwrap();
+        assert_eq!(mask(0, 1), 0b1);
+        assert_eq!(mask(1, 3), 0b110);
+        assert_eq!(mask(0, 64), u64::MAX);
+        assert_eq!(mask(63, 64), 1 << 63);
+        assert_eq!(mask(2, 5) & mask(5, 7), 0);
+        assert_ne!(mask(2, 6) & mask(5, 7), 0);
+    }
+
+    #[test]
+    fn leg_mask_rejects_legs_it_cannot_track() {
+        assert!(leg_mask(0, 65).is_err());
+        assert!(leg_mask(64, 70).is_err());
+        assert!(leg_mask(3, 3).is_err());
+        assert!(leg_mask(4, 3).is_err());
+    }
+
+    #[test]
+    fn migrates_first_release_buses_and_reservations() {
+        StableBTreeMap::<u64, FirstReleaseBus, Memory>::init(memory(1)).insert(
+            7,
+            FirstReleaseBus {
+                id: 7,
+                make: "Volvo".to_string(),
+                model: "9700".to_string(),
+                year: 2019,
+                color: "white".to_string(),
+                created_at: 1,
+                updated_at: None,
+                owner: "Coastline".to_string(),
+                is_booked: true,
+            },
+        );
+        StableBTreeMap::<u64, FirstReleaseReservation, Memory>::init(memory(3)).insert(
+            7,
+            FirstReleaseReservation {
+                bus_id: 7,
+                customer_id: 8,
+                reservation_time: 2,
+            },
+        );
+        ID_COUNTER
+            .with(|counter| counter.borrow_mut().set(9))
+            .unwrap();
+
+        do_migrate_records_v1();
+
+        let bus = _get_bus(&7).expect("the bus is migrated");
+        assert_eq!(bus.make, "Volvo");
+        assert!(bus.is_booked);
+        assert_eq!((bus.fare, bus.capacity), (0, 0));
+        assert!(bus.registration_plate.is_none() && bus.vin.is_none());
+        let reservations = _get_reservations();
+        assert_eq!(reservations.len(), 1);
+        let reservation = &reservations[0];
+        assert_eq!(reservation.id, 9);
+        assert_eq!((reservation.bus_id, reservation.customer_id), (7, 8));
+        assert!(reservation.status == ReservationStatus::Confirmed);
+        assert!(reservation.passengers.is_empty());
+    }
+
+    #[test]
+    fn indexes_trips_by_timetable() {
+        let trip = |id, timetable_id, departure_time| Trip {
+            id,
+            bus_id: 1,
+            origin: "Harbour".to_string(),
+            destination: "Airport".to_string(),
+            departure_time,
+            arrival_time: departure_time + 60,
+            status: TripStatus::Scheduled,
+            driver_id: None,
+            route_id: None,
+            delay_minutes: 0,
+            actual_departure_time: None,
+            actual_arrival_time: None,
+            timetable_id,
+            created_at: 0,
+            updated_at: None,
+        };
+        TRIP_STORAGE.with(|service| {
+            let mut service = service.borrow_mut();
+            service.insert(1, trip(1, Some(9), 500));
+            service.insert(2, trip(2, None, 600));
+            service.insert(3, trip(3, Some(9), 100));
+            service.insert(4, trip(4, Some(10), 100));
+        });
+
+        do_index_timetable_trips_v2();
+
+        let trips = _get_timetable_trip_ids(9);
+        assert_eq!(
+            trips.into_iter().collect::<Vec<_>>(),
+            vec![(100, 3), (500, 1)]
+        );
+        assert_eq!(_get_timetable_trip_ids(10).len(), 1);
+    }
+
+    #[test]
+    fn keeps_buses_with_work_ahead_of_them() {
+        let mut trip = Trip {
+            id: 2,
+            bus_id: 1,
+            origin: "Harbour".to_string(),
+            destination: "Airport".to_string(),
+            departure_time: 100,
+            arrival_time: 200,
+            status: TripStatus::Scheduled,
+            driver_id: None,
+            route_id: None,
+            delay_minutes: 0,
+            actual_departure_time: None,
+            actual_arrival_time: None,
+            timetable_id: None,
+            created_at: 0,
+            updated_at: None,
+        };
+        do_insert_trip(&trip);
+        do_block_bus(BlockedInterval {
+            bus_id: 1,
+            start: 100,
+            end: 200,
+            reason: BlockReason::Trip,
+            reference_id: trip.id,
+        });
+        assert!(ensure_bus_unused(1, 50).is_err());
+
+        trip.status = TripStatus::Completed;
+        do_insert_trip(&trip);
+        assert!(ensure_bus_unused(1, 50).is_err());
+        assert!(ensure_bus_unused(1, 300).is_ok());
+
+        do_insert_reservation(&Reservation {
+            id: 3,
+            bus_id: 1,
+            trip_id: Some(trip.id),
+            ..Default::default()
+        });
+        assert!(ensure_bus_unused(1, 300).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use super::*;
+
+// Points are earned at one per LOYALTY_EARN_DIVISOR fare units paid, and each point
+// redeemed takes LOYALTY_POINT_VALUE fare units off a reservation.
+pub(crate) const LOYALTY_EARN_DIVISOR: u64 = 100;
+pub(crate) const LOYALTY_POINT_VALUE: u64 = 1;
+const LOYALTY_POINTS_VALIDITY: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default, Clone)]
+pub(crate) struct LoyaltyAccount {
+    customer_id: u64,
+    pub(crate) balance: u64,
+    lifetime_earned: u64,
+    lifetime_redeemed: u64,
+    lifetime_expired: u64,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for LoyaltyAccount {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for LoyaltyAccount {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) enum LoyaltyEntryKind {
+    Accrual,
+    Redemption,
+    Refund,
+    Expiry,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct LoyaltyEntry {
+    id: u64,
+    customer_id: u64,
+    kind: LoyaltyEntryKind,
+    points: u64,
+    reservation_id: Option<u64>,
+    created_at: u64,
+    expires_at: Option<u64>, // set on accruals
+    remaining: u64,          // unredeemed, unexpired points left on an accrual
+}
+
+impl Storable for LoyaltyEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for LoyaltyEntry {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct LoyaltyStatement {
+    account: LoyaltyAccount,
+    entries: Vec<LoyaltyEntry>,
+}
+
+#[ic_cdk::query]
+fn get_loyalty_account(customer_id: u64) -> Result<LoyaltyAccount, Error> {
+    ensure_customer_caller(customer_id)?;
+    match _get_customer(&customer_id) {
+        // Expiring inside a query only affects the returned view; nothing is persisted
+        Some(_) => Ok(do_expire_points(customer_id, time())),
+        None => Err(Error::NotFound {
+            msg: format!("a customer with id={} not found", customer_id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_loyalty_statement(customer_id: u64) -> Result<LoyaltyStatement, Error> {
+    ensure_customer_caller(customer_id)?;
+    match _get_customer(&customer_id) {
+        Some(_) => {
+            let account = do_expire_points(customer_id, time());
+            let entries = LOYALTY_ENTRIES.with(|service| {
+                service
+                    .borrow()
+                    .range((customer_id, 0)..=(customer_id, u64::MAX))
+                    .map(|(_, entry)| entry)
+                    .collect()
+            });
+            Ok(LoyaltyStatement { account, entries })
+        }
+        None => Err(Error::NotFound {
+            msg: format!("a customer with id={} not found", customer_id),
+        }),
+    }
+}
+
+fn _get_loyalty_account(customer_id: u64, now: u64) -> LoyaltyAccount {
+    LOYALTY_ACCOUNTS
+        .with(|service| service.borrow().get(&customer_id))
+        .unwrap_or(LoyaltyAccount {
+            customer_id,
+            created_at: now,
+            ..Default::default()
+        })
+}
+
+fn do_insert_loyalty_account(account: &LoyaltyAccount) {
+    LOYALTY_ACCOUNTS.with(|service| {
+        service
+            .borrow_mut()
+            .insert(account.customer_id, account.clone())
+    });
+}
+
+fn do_insert_loyalty_entry(entry: &LoyaltyEntry) {
+    LOYALTY_ENTRIES.with(|service| {
+        service
+            .borrow_mut()
+            .insert((entry.customer_id, entry.id), entry.clone())
+    });
+}
+
+// Accrual entries are the lots points are spent from, oldest first
+fn _get_open_point_lots(customer_id: u64) -> Vec<LoyaltyEntry> {
+    LOYALTY_ENTRIES.with(|service| {
+        service
+            .borrow()
+            .range((customer_id, 0)..=(customer_id, u64::MAX))
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.remaining > 0)
+            .collect()
+    })
+}
+
+// Earns points on a new lot that expires LOYALTY_POINTS_VALIDITY from now
+pub(crate) fn do_credit_points(customer_id: u64, points: u64, reservation_id: u64, now: u64) {
+    let mut account = do_expire_points(customer_id, now);
+    account.lifetime_earned += points;
+    account.balance += points;
+    account.updated_at = Some(now);
+    do_insert_loyalty_account(&account);
+    do_insert_loyalty_entry(&LoyaltyEntry {
+        id: next_id(),
+        customer_id,
+        kind: LoyaltyEntryKind::Accrual,
+        points,
+        reservation_id: Some(reservation_id),
+        created_at: now,
+        expires_at: Some(now + LOYALTY_POINTS_VALIDITY),
+        remaining: points,
+    });
+}
+
+// Callers must have checked the balance covers `points`
+pub(crate) fn do_redeem_points(customer_id: u64, points: u64, reservation_id: u64, now: u64) {
+    let mut account = do_expire_points(customer_id, now);
+    let mut outstanding = points;
+    for mut lot in _get_open_point_lots(customer_id) {
+        if outstanding == 0 {
+            break;
+        }
+        let taken = lot.remaining.min(outstanding);
+        lot.remaining -= taken;
+        outstanding -= taken;
+        do_insert_loyalty_entry(&lot);
+        LOYALTY_DRAWS.with(|service| service.borrow_mut().insert((reservation_id, lot.id), taken));
+    }
+    account.balance -= points;
+    account.lifetime_redeemed += points;
+    account.updated_at = Some(now);
+    do_insert_loyalty_account(&account);
+    do_insert_loyalty_entry(&LoyaltyEntry {
+        id: next_id(),
+        customer_id,
+        kind: LoyaltyEntryKind::Redemption,
+        points,
+        reservation_id: Some(reservation_id),
+        created_at: now,
+        expires_at: None,
+        remaining: 0,
+    });
+}
+
+// Gives points redeemed for the reservation back to the lots they were drawn from, so they
+// expire when they would have. Points whose lot has expired since are written off straight away.
+pub(crate) fn do_refund_points(customer_id: u64, points: u64, reservation_id: u64, now: u64) {
+    let mut account = do_expire_points(customer_id, now);
+    let mut refunded = 0;
+    for (lot_id, taken) in do_take_point_draws(reservation_id, points) {
+        let lot = LOYALTY_ENTRIES.with(|service| service.borrow().get(&(customer_id, lot_id)));
+        if let Some(mut lot) = lot {
+            lot.remaining += taken;
+            do_insert_loyalty_entry(&lot);
+            refunded += taken;
+        }
+    }
+    account.balance += refunded;
+    account.lifetime_redeemed = account.lifetime_redeemed.saturating_sub(refunded);
+    account.updated_at = Some(now);
+    do_insert_loyalty_account(&account);
+    do_insert_loyalty_entry(&LoyaltyEntry {
+        id: next_id(),
+        customer_id,
+        kind: LoyaltyEntryKind::Refund,
+        points: refunded,
+        reservation_id: Some(reservation_id),
+        created_at: now,
+        expires_at: None,
+        remaining: 0,
+    });
+    do_expire_points(customer_id, now);
+}
+
+// Hands `points` of what one reservation drew over to another split off from it
+pub(crate) fn do_move_point_draws(from_reservation_id: u64, to_reservation_id: u64, points: u64) {
+    for (lot_id, taken) in do_take_point_draws(from_reservation_id, points) {
+        LOYALTY_DRAWS.with(|service| {
+            let mut draws = service.borrow_mut();
+            let drawn = draws.get(&(to_reservation_id, lot_id)).unwrap_or(0);
+            draws.insert((to_reservation_id, lot_id), drawn + taken);
+        });
+    }
+}
+
+// Takes up to `points` off the reservation's draws, latest lot first since those expire last
+fn do_take_point_draws(reservation_id: u64, points: u64) -> Vec<(u64, u64)> {
+    let draws: Vec<(u64, u64)> = LOYALTY_DRAWS.with(|service| {
+        service
+            .borrow()
+            .range((reservation_id, 0)..=(reservation_id, u64::MAX))
+            .map(|((_, lot_id), drawn)| (lot_id, drawn))
+            .collect()
+    });
+    let mut outstanding = points;
+    let mut taken = Vec::new();
+    for (lot_id, drawn) in draws.into_iter().rev() {
+        if outstanding == 0 {
+            break;
+        }
+        let take = drawn.min(outstanding);
+        outstanding -= take;
+        LOYALTY_DRAWS.with(|service| {
+            let mut draws = service.borrow_mut();
+            if take == drawn {
+                draws.remove(&(reservation_id, lot_id));
+            } else {
+                draws.insert((reservation_id, lot_id), drawn - take);
+            }
+        });
+        taken.push((lot_id, take));
+    }
+    taken
+}
+
+// Writes off whatever is left on lots past their expiry and returns the up-to-date account
+pub(crate) fn do_expire_points(customer_id: u64, now: u64) -> LoyaltyAccount {
+    let mut account = _get_loyalty_account(customer_id, now);
+    let mut expired = 0;
+    for mut lot in _get_open_point_lots(customer_id) {
+        if lot.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            expired += lot.remaining;
+            lot.remaining = 0;
+            do_insert_loyalty_entry(&lot);
+        }
+    }
+    if expired > 0 {
+        account.balance -= expired;
+        account.lifetime_expired += expired;
+        account.updated_at = Some(now);
+        do_insert_loyalty_account(&account);
+        do_insert_loyalty_entry(&LoyaltyEntry {
+            id: next_id(),
+            customer_id,
+            kind: LoyaltyEntryKind::Expiry,
+            points: expired,
+            reservation_id: None,
+            created_at: now,
+            expires_at: None,
+            remaining: 0,
+        });
+    }
+    account
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lot_expiries(customer_id: u64) -> Vec<(Option<u64>, u64)> {
+        _get_open_point_lots(customer_id)
+            .into_iter()
+            .map(|lot| (lot.expires_at, lot.remaining))
+            .collect()
+    }
+
+    #[test]
+    fn refunds_keep_the_expiry_of_the_lots_redeemed() {
+        let validity = LOYALTY_POINTS_VALIDITY;
+        do_credit_points(1, 100, 50, 0);
+        do_credit_points(1, 100, 51, 10);
+        do_redeem_points(1, 150, 60, 20);
+        assert_eq!(lot_expiries(1), vec![(Some(validity + 10), 50)]);
+
+        // Points go back to the lots that expire last first
+        do_refund_points(1, 60, 60, 30);
+        assert_eq!(
+            lot_expiries(1),
+            vec![(Some(validity), 10), (Some(validity + 10), 100)]
+        );
+        assert_eq!(do_expire_points(1, 30).balance, 110);
+
+        // Once their lot has expired, refunded points are written off
+        assert_eq!(do_expire_points(1, validity).balance, 100);
+        do_refund_points(1, 90, 60, validity + 5);
+        let account = do_expire_points(1, validity + 5);
+        assert_eq!((account.balance, account.lifetime_expired), (100, 100));
+        assert_eq!(lot_expiries(1), vec![(Some(validity + 10), 100)]);
+    }
+
+    #[test]
+    fn split_reservations_take_their_draws_with_them() {
+        do_credit_points(2, 40, 70, 0);
+        do_redeem_points(2, 30, 80, 1);
+        do_move_point_draws(80, 81, 20);
+        do_refund_points(2, 10, 80, 2);
+        assert_eq!(do_expire_points(2, 2).balance, 20);
+        do_refund_points(2, 20, 81, 3);
+        assert_eq!(do_expire_points(2, 3).balance, 40);
+    }
+}
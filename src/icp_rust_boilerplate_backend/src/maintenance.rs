@@ -0,0 +1,296 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum ServiceType {
+    Inspection,
+    OilChange,
+    Tyres,
+    Brakes,
+    Repair,
+    Other(String),
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum MaintenanceStatus {
+    Scheduled,
+    Completed,
+    Cancelled,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct MaintenanceRecord {
+    id: u64,
+    bus_id: u64,
+    service_type: ServiceType,
+    scheduled_start: u64,
+    scheduled_end: u64,
+    status: MaintenanceStatus,
+    completed_at: Option<u64>,
+    odometer_km: Option<u64>, // reading when the work was done
+    cost: Option<u64>,
+    notes: String,
+    next_due_at: Option<u64>,
+    next_due_odometer_km: Option<u64>,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for MaintenanceRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for MaintenanceRecord {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct MaintenancePayload {
+    bus_id: u64,
+    service_type: ServiceType,
+    scheduled_start: u64,
+    scheduled_end: u64,
+    notes: String,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct MaintenanceCompletionPayload {
+    odometer_km: u64,
+    cost: u64,
+    notes: String,
+    next_due_at: Option<u64>,
+    next_due_odometer_km: Option<u64>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct OverdueBus {
+    bus: Bus,
+    last_service: MaintenanceRecord,
+    overdue_by_date: bool,
+    overdue_by_mileage: bool,
+}
+
+// Schedules a workshop visit. The bus can't be booked for anything else during the window.
+#[ic_cdk::update]
+fn schedule_maintenance(payload: MaintenancePayload) -> Result<MaintenanceRecord, Error> {
+    ensure_admin()?;
+    if _get_bus(&payload.bus_id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", payload.bus_id),
+        });
+    }
+    if payload.scheduled_end <= payload.scheduled_start {
+        return Err(Error::InvalidInput {
+            msg: "a maintenance window must end after it starts".to_string(),
+        });
+    }
+    ensure_bus_available(
+        payload.bus_id,
+        payload.scheduled_start,
+        payload.scheduled_end,
+    )?;
+    let record = MaintenanceRecord {
+        id: next_id(),
+        bus_id: payload.bus_id,
+        service_type: payload.service_type,
+        scheduled_start: payload.scheduled_start,
+        scheduled_end: payload.scheduled_end,
+        status: MaintenanceStatus::Scheduled,
+        completed_at: None,
+        odometer_km: None,
+        cost: None,
+        notes: payload.notes,
+        next_due_at: None,
+        next_due_odometer_km: None,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_block_bus(BlockedInterval {
+        bus_id: record.bus_id,
+        start: record.scheduled_start,
+        end: record.scheduled_end,
+        reason: BlockReason::Maintenance,
+        reference_id: record.id,
+    });
+    do_insert_maintenance(&record);
+    Ok(record)
+}
+
+// Closes the work order and hands the bus back, even if that is before the window ends
+#[ic_cdk::update]
+fn complete_maintenance(
+    id: u64,
+    payload: MaintenanceCompletionPayload,
+) -> Result<MaintenanceRecord, Error> {
+    ensure_admin()?;
+    let mut record = _get_scheduled_maintenance(id)?;
+    let now = time();
+    record.status = MaintenanceStatus::Completed;
+    record.completed_at = Some(now);
+    record.odometer_km = Some(payload.odometer_km);
+    record.cost = Some(payload.cost);
+    if !payload.notes.is_empty() {
+        record.notes = payload.notes;
+    }
+    record.next_due_at = payload.next_due_at;
+    record.next_due_odometer_km = payload.next_due_odometer_km;
+    record.updated_at = Some(now);
+    do_unblock_bus(record.bus_id, record.scheduled_start);
+    if let Some(mut bus) = _get_bus(&record.bus_id) {
+        if payload.odometer_km > bus.odometer_km {
+            bus.odometer_km = payload.odometer_km;
+            do_insert_bus(&bus);
+        }
+    }
+    do_insert_maintenance(&record);
+    Ok(record)
+}
+
+#[ic_cdk::update]
+fn cancel_maintenance(id: u64) -> Result<MaintenanceRecord, Error> {
+    ensure_admin()?;
+    let mut record = _get_scheduled_maintenance(id)?;
+    record.status = MaintenanceStatus::Cancelled;
+    record.updated_at = Some(time());
+    do_unblock_bus(record.bus_id, record.scheduled_start);
+    do_insert_maintenance(&record);
+    Ok(record)
+}
+
+// Holds a bus out of service. The hold is an open maintenance record, so it shows up in the
+// bus's maintenance history and is lifted the same way.
+#[ic_cdk::update]
+fn block_bus(bus_id: u64, start: u64, end: u64) -> Result<BlockedInterval, Error> {
+    let record = schedule_maintenance(MaintenancePayload {
+        bus_id,
+        service_type: ServiceType::Other("out of service".to_string()),
+        scheduled_start: start,
+        scheduled_end: end,
+        notes: String::new(),
+    })?;
+    Ok(BlockedInterval {
+        bus_id,
+        start,
+        end,
+        reason: BlockReason::Maintenance,
+        reference_id: record.id,
+    })
+}
+
+// Only maintenance holds can be lifted directly; trips and charters free the bus when cancelled
+#[ic_cdk::update]
+fn unblock_bus(bus_id: u64, start: u64) -> Result<BlockedInterval, Error> {
+    ensure_admin()?;
+    match BUS_CALENDAR.with(|service| service.borrow().get(&(bus_id, start))) {
+        Some(block) if block.reason == BlockReason::Maintenance => {
+            let record =
+                MAINTENANCE_STORAGE.with(|service| service.borrow().get(&block.reference_id));
+            match record {
+                Some(_) => {
+                    cancel_maintenance(block.reference_id)?;
+                }
+                // Holds placed before maintenance records existed have nothing to cancel
+                None => {
+                    do_unblock_bus(bus_id, start);
+                }
+            }
+            Ok(block)
+        }
+        Some(_) => Err(Error::InvalidInput {
+            msg: "only maintenance blocks can be removed directly".to_string(),
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("no block starting at {} on bus with id={}", start, bus_id),
+        }),
+    }
+}
+
+#[ic_cdk::update]
+fn record_odometer(bus_id: u64, odometer_km: u64) -> Result<Bus, Error> {
+    ensure_admin()?;
+    let mut bus = _get_bus(&bus_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a bus with id={} not found", bus_id),
+    })?;
+    if odometer_km < bus.odometer_km {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "odometer can't go backwards from {} km to {} km",
+                bus.odometer_km, odometer_km
+            ),
+        });
+    }
+    bus.odometer_km = odometer_km;
+    bus.updated_at = Some(time());
+    do_insert_bus(&bus);
+    Ok(bus)
+}
+
+#[ic_cdk::query]
+fn get_bus_maintenance(bus_id: u64) -> Vec<MaintenanceRecord> {
+    MAINTENANCE_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, record)| record)
+            .filter(|record| record.bus_id == bus_id)
+            .collect()
+    })
+}
+
+// A bus is overdue when its most recent completed service set a due date before `as_of`
+// or a due odometer reading it has already passed
+#[ic_cdk::query]
+fn get_overdue_buses(as_of: u64) -> Vec<OverdueBus> {
+    let mut last_services: BTreeMap<u64, MaintenanceRecord> = BTreeMap::new();
+    MAINTENANCE_STORAGE.with(|service| {
+        for (_, record) in service.borrow().iter() {
+            if record.status != MaintenanceStatus::Completed {
+                continue;
+            }
+            let is_newer = last_services
+                .get(&record.bus_id)
+                .is_none_or(|last| last.completed_at < record.completed_at);
+            if is_newer {
+                last_services.insert(record.bus_id, record);
+            }
+        }
+    });
+    last_services
+        .into_values()
+        .filter_map(|last_service| {
+            let bus = _get_bus(&last_service.bus_id)?;
+            let overdue_by_date = last_service.next_due_at.is_some_and(|due| due <= as_of);
+            let overdue_by_mileage = last_service
+                .next_due_odometer_km
+                .is_some_and(|due| due <= bus.odometer_km);
+            (overdue_by_date || overdue_by_mileage).then_some(OverdueBus {
+                bus,
+                last_service,
+                overdue_by_date,
+                overdue_by_mileage,
+            })
+        })
+        .collect()
+}
+
+fn _get_scheduled_maintenance(id: u64) -> Result<MaintenanceRecord, Error> {
+    match MAINTENANCE_STORAGE.with(|service| service.borrow().get(&id)) {
+        Some(record) if record.status == MaintenanceStatus::Scheduled => Ok(record),
+        Some(_) => Err(Error::InvalidInput {
+            msg: format!("maintenance record with id={} is already closed", id),
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("a maintenance record with id={} not found", id),
+        }),
+    }
+}
+
+fn do_insert_maintenance(record: &MaintenanceRecord) {
+    MAINTENANCE_STORAGE.with(|service| service.borrow_mut().insert(record.id, record.clone()));
+}
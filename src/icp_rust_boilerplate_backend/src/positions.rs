@@ -0,0 +1,229 @@
+use super::*;
+
+// Positions kept per trip; older ones are overwritten
+const POSITION_BUFFER_SIZE: u64 = 64;
+// Only positions this recent are used to estimate the bus' speed
+const SPEED_SAMPLE_WINDOW: u64 = 10 * 60 * 1_000_000_000;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct VehiclePosition {
+    trip_id: u64,
+    sequence: u64,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) speed_mps: Option<f64>,
+    pub(crate) heading: Option<f64>,
+    pub(crate) recorded_at: u64,
+}
+
+impl Storable for VehiclePosition {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for VehiclePosition {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct VehiclePositionPayload {
+    latitude: f64,
+    longitude: f64,
+    speed_mps: Option<f64>,
+    heading: Option<f64>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct VehicleEta {
+    position: VehiclePosition,
+    stop_id: u64,
+    distance_remaining_m: u64,
+    speed_mps: Option<f64>, // None when the estimate falls back to the timetable
+    estimated_arrival: u64,
+}
+
+#[ic_cdk::update]
+fn push_vehicle_position(
+    trip_id: u64,
+    payload: VehiclePositionPayload,
+) -> Result<VehiclePosition, Error> {
+    let trip = _get_trip_for_update(trip_id)?;
+    if trip.status != TripStatus::InProgress {
+        return Err(Error::InvalidInput {
+            msg: format!("trip with id={} hasn't departed yet", trip_id),
+        });
+    }
+    if !(-90.0..=90.0).contains(&payload.latitude) || !(-180.0..=180.0).contains(&payload.longitude)
+    {
+        return Err(Error::InvalidInput {
+            msg: "position coordinates are out of range".to_string(),
+        });
+    }
+    let sequence = POSITION_COUNTS.with(|counts| counts.borrow().get(&trip_id).unwrap_or(0));
+    let position = VehiclePosition {
+        trip_id,
+        sequence,
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        speed_mps: payload.speed_mps,
+        heading: payload.heading,
+        recorded_at: time(),
+    };
+    VEHICLE_POSITIONS.with(|service| {
+        service
+            .borrow_mut()
+            .insert((trip_id, sequence % POSITION_BUFFER_SIZE), position.clone())
+    });
+    POSITION_COUNTS.with(|counts| counts.borrow_mut().insert(trip_id, sequence + 1));
+    Ok(position)
+}
+
+#[ic_cdk::query]
+fn get_latest_position(trip_id: u64) -> Result<VehiclePosition, Error> {
+    _get_recent_positions(trip_id)
+        .pop()
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("no position reported for trip id={}", trip_id),
+        })
+}
+
+// Estimates when the bus reaches `stop_id` from how far along the route it is and how fast
+// it has been moving lately. Without a usable speed, the timetable plus any reported delay
+// is used instead.
+#[ic_cdk::query]
+fn get_vehicle_eta(trip_id: u64, stop_id: u64) -> Result<VehicleEta, Error> {
+    let trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    let route = trip
+        .route_id
+        .and_then(|route_id| _get_route(&route_id))
+        .ok_or_else(|| Error::InvalidInput {
+            msg: format!("trip with id={} doesn't follow a route", trip_id),
+        })?;
+    let target = route
+        .stops
+        .iter()
+        .find(|route_stop| route_stop.stop_id == stop_id)
+        .ok_or_else(|| Error::InvalidInput {
+            msg: format!(
+                "stop id={} is not on the route of trip id={}",
+                stop_id, trip_id
+            ),
+        })?;
+    let positions = _get_recent_positions(trip_id);
+    let position = positions.last().cloned().ok_or_else(|| Error::NotFound {
+        msg: format!("no position reported for trip id={}", trip_id),
+    })?;
+    let travelled = _get_distance_along_route(&route, &position);
+    if travelled > target.distance_m as f64 {
+        return Err(Error::InvalidInput {
+            msg: format!("the bus has already passed stop id={}", stop_id),
+        });
+    }
+    let distance_remaining_m = (target.distance_m as f64 - travelled) as u64;
+    let speed_mps = _get_recent_speed(&positions);
+    let estimated_arrival = match speed_mps {
+        Some(speed) => position.recorded_at + (distance_remaining_m as f64 / speed * 1e9) as u64,
+        None => trip
+            .departure_time
+            .saturating_add(target.arrival_offset)
+            .saturating_add(u64::from(trip.delay_minutes).saturating_mul(60_000_000_000)),
+    };
+    Ok(VehicleEta {
+        position,
+        stop_id,
+        distance_remaining_m,
+        speed_mps,
+        estimated_arrival,
+    })
+}
+
+// Buffered positions for the trip, oldest first
+pub(crate) fn _get_recent_positions(trip_id: u64) -> Vec<VehiclePosition> {
+    let mut positions: Vec<VehiclePosition> = VEHICLE_POSITIONS.with(|service| {
+        service
+            .borrow()
+            .range((trip_id, 0)..(trip_id, POSITION_BUFFER_SIZE))
+            .map(|(_, position)| position)
+            .collect()
+    });
+    positions.sort_by_key(|position| position.sequence);
+    positions
+}
+
+// Average speed over the path driven within SPEED_SAMPLE_WINDOW of the latest position.
+// Speeds under 0.5 m/s (a stationary bus) are not useful for an estimate.
+fn _get_recent_speed(positions: &[VehiclePosition]) -> Option<f64> {
+    let latest = positions.last()?;
+    let recent: Vec<&VehiclePosition> = positions
+        .iter()
+        .filter(|position| position.recorded_at + SPEED_SAMPLE_WINDOW >= latest.recorded_at)
+        .collect();
+    let elapsed = (latest.recorded_at - recent.first()?.recorded_at) as f64 / 1e9;
+    let speed = if elapsed > 0.0 {
+        let path: f64 = recent
+            .windows(2)
+            .map(|pair| {
+                haversine_m(
+                    pair[0].latitude,
+                    pair[0].longitude,
+                    pair[1].latitude,
+                    pair[1].longitude,
+                )
+            })
+            .sum();
+        path / elapsed
+    } else {
+        latest.speed_mps?
+    };
+    (speed >= 0.5).then_some(speed)
+}
+
+// Places the position on the route leg it fits best (where going via the position adds the
+// least to the leg's length) and interpolates the distance from the first stop
+fn _get_distance_along_route(route: &Route, position: &VehiclePosition) -> f64 {
+    let coordinates: Vec<(f64, f64)> = route
+        .stops
+        .iter()
+        .map(|route_stop| {
+            _get_stop(&route_stop.stop_id)
+                .map_or((0.0, 0.0), |stop| (stop.latitude, stop.longitude))
+        })
+        .collect();
+    let mut best: Option<(f64, f64)> = None; // (detour, distance along route)
+    for (index, pair) in route.stops.windows(2).enumerate() {
+        let (from, to) = (coordinates[index], coordinates[index + 1]);
+        let to_from = haversine_m(position.latitude, position.longitude, from.0, from.1);
+        let to_next = haversine_m(position.latitude, position.longitude, to.0, to.1);
+        let leg = haversine_m(from.0, from.1, to.0, to.1);
+        let detour = to_from + to_next - leg;
+        let share = if to_from + to_next > 0.0 {
+            to_from / (to_from + to_next)
+        } else {
+            0.0
+        };
+        let along =
+            pair[0].distance_m as f64 + share * (pair[1].distance_m - pair[0].distance_m) as f64;
+        if best.is_none_or(|(best_detour, _)| detour < best_detour) {
+            best = Some((detour, along));
+        }
+    }
+    best.map_or(0.0, |(_, along)| along)
+}
+
+// Great-circle distance in metres
+pub(crate) fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
@@ -0,0 +1,386 @@
+use super::*;
+
+// Keeps a code with every restriction filled in well within PromoCode::MAX_SIZE
+const MAX_PROMO_BUS_IDS: usize = 64;
+const MAX_PROMO_ROUTE_IDS: usize = 64;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+enum Discount {
+    Percentage(u8),
+    Fixed(u64),
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct PromoCode {
+    pub(crate) code: String,
+    discount: Discount,
+    bus_ids: Vec<u64>,   // empty means the code is valid on every bus
+    route_ids: Vec<u64>, // empty means any route, otherwise only trips on these routes
+    valid_from: u64,     // when the code can be redeemed, as opposed to when the trip runs
+    expires_at: u64,
+    travel_from: Option<u64>,  // earliest departure the code can be used for
+    travel_until: Option<u64>, // departures from here on are excluded
+    max_uses: Option<u32>,
+    max_uses_per_customer: Option<u32>,
+    budget: Option<u64>, // total discount the campaign may hand out
+    uses: u32,
+    discount_granted: u64,
+    is_active: bool,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for PromoCode {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for PromoCode {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct PromoCodePayload {
+    code: String,
+    discount: Discount,
+    bus_ids: Vec<u64>,
+    route_ids: Vec<u64>,
+    valid_from: u64,
+    expires_at: u64,
+    travel_from: Option<u64>,
+    travel_until: Option<u64>,
+    max_uses: Option<u32>,
+    max_uses_per_customer: Option<u32>,
+    budget: Option<u64>,
+}
+
+#[ic_cdk::update]
+fn create_promo_code(payload: PromoCodePayload) -> Result<PromoCode, Error> {
+    ensure_admin()?;
+    let code = normalize_promo_code(&payload.code)?;
+    match &payload.discount {
+        Discount::Percentage(percent) if *percent == 0 || *percent > 100 => {
+            return Err(Error::InvalidInput {
+                msg: "percentage discount must be between 1 and 100".to_string(),
+            })
+        }
+        Discount::Fixed(0) => {
+            return Err(Error::InvalidInput {
+                msg: "fixed discount must be greater than zero".to_string(),
+            })
+        }
+        _ => {}
+    }
+    if payload.expires_at <= payload.valid_from {
+        return Err(Error::InvalidInput {
+            msg: "promo code must expire after it becomes valid".to_string(),
+        });
+    }
+    if let (Some(from), Some(until)) = (payload.travel_from, payload.travel_until) {
+        if until <= from {
+            return Err(Error::InvalidInput {
+                msg: "promo code travel dates must end after they start".to_string(),
+            });
+        }
+    }
+    if payload.bus_ids.len() > MAX_PROMO_BUS_IDS || payload.route_ids.len() > MAX_PROMO_ROUTE_IDS {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a promo code can be limited to at most {} buses and {} routes",
+                MAX_PROMO_BUS_IDS, MAX_PROMO_ROUTE_IDS
+            ),
+        });
+    }
+    if _get_promo_code(&code).is_some() {
+        return Err(Error::Conflict {
+            msg: format!("a promo code {} already exists", code),
+        });
+    }
+    let promo = PromoCode {
+        code,
+        discount: payload.discount,
+        bus_ids: payload.bus_ids,
+        route_ids: payload.route_ids,
+        valid_from: payload.valid_from,
+        expires_at: payload.expires_at,
+        travel_from: payload.travel_from,
+        travel_until: payload.travel_until,
+        max_uses: payload.max_uses,
+        max_uses_per_customer: payload.max_uses_per_customer,
+        budget: payload.budget,
+        uses: 0,
+        discount_granted: 0,
+        is_active: true,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_insert_promo_code(&promo);
+    Ok(promo)
+}
+
+#[ic_cdk::update]
+fn deactivate_promo_code(code: String) -> Result<PromoCode, Error> {
+    ensure_admin()?;
+    let code = normalize_promo_code(&code)?;
+    match _get_promo_code(&code) {
+        Some(mut promo) => {
+            promo.is_active = false;
+            promo.updated_at = Some(time());
+            do_insert_promo_code(&promo);
+            Ok(promo)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("a promo code {} not found", code),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_promo_code(code: String) -> Result<PromoCode, Error> {
+    ensure_admin()?;
+    let code = normalize_promo_code(&code)?;
+    match _get_promo_code(&code) {
+        Some(promo) => Ok(promo),
+        None => Err(Error::NotFound {
+            msg: format!("a promo code {} not found", code),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn list_promo_codes() -> Result<Vec<PromoCode>, Error> {
+    ensure_admin()?;
+    let promos = PROMO_CODE_STORAGE
+        .with(|service| service.borrow().iter().map(|(_, promo)| promo).collect());
+    Ok(promos)
+}
+
+fn normalize_promo_code(code: &str) -> Result<String, Error> {
+    let code = code.trim().to_uppercase();
+    let valid_chars = code
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if code.len() < 3 || code.len() > 32 || !valid_chars {
+        return Err(Error::InvalidInput {
+            msg: "promo codes must be 3-32 characters of letters, digits, '-' or '_'".to_string(),
+        });
+    }
+    Ok(code)
+}
+
+// Checks every restriction on the code and returns it together with the discount it grants
+// on `fare`. Bookings without a trip travel `now`. Nothing is written; see `do_redeem_promo_code`.
+pub(crate) fn check_promo_code(
+    code: &str,
+    bus: &Bus,
+    trip: Option<&Trip>,
+    customer_id: u64,
+    fare: u64,
+    now: u64,
+) -> Result<(PromoCode, u64), Error> {
+    let code = normalize_promo_code(code)?;
+    let promo = _get_promo_code(&code).ok_or_else(|| Error::NotFound {
+        msg: format!("a promo code {} not found", code),
+    })?;
+    let invalid = |msg: &str| Error::InvalidInput {
+        msg: format!("promo code {} {}", code, msg),
+    };
+    if !promo.is_active {
+        return Err(invalid("is no longer active"));
+    }
+    if now < promo.valid_from {
+        return Err(invalid("is not valid yet"));
+    }
+    if now >= promo.expires_at {
+        return Err(invalid("has expired"));
+    }
+    if !promo.bus_ids.is_empty() && !promo.bus_ids.contains(&bus.id) {
+        return Err(invalid("is not valid on this bus"));
+    }
+    let route_id = trip.and_then(|trip| trip.route_id);
+    if !promo.route_ids.is_empty() && !route_id.is_some_and(|id| promo.route_ids.contains(&id)) {
+        return Err(invalid("is not valid on this route"));
+    }
+    let travel_time = trip.map_or(now, |trip| trip.departure_time);
+    if promo.travel_from.is_some_and(|from| travel_time < from)
+        || promo.travel_until.is_some_and(|until| travel_time >= until)
+    {
+        return Err(invalid("is not valid for this travel date"));
+    }
+    if promo.max_uses.is_some_and(|max| promo.uses >= max) {
+        return Err(invalid("has reached its maximum number of uses"));
+    }
+    let customer_uses = _get_promo_redemptions(&code, customer_id);
+    if promo
+        .max_uses_per_customer
+        .is_some_and(|max| customer_uses >= max)
+    {
+        return Err(invalid(
+            "has already been used the maximum number of times by this customer",
+        ));
+    }
+    let mut discount = match promo.discount {
+        Discount::Percentage(percent) => {
+            fare.checked_mul(u64::from(percent))
+                .ok_or_else(|| invalid("cannot be applied to a fare this large"))?
+                / 100
+        }
+        Discount::Fixed(amount) => amount,
+    }
+    .min(fare);
+    if let Some(budget) = promo.budget {
+        let remaining = budget.saturating_sub(promo.discount_granted);
+        if remaining == 0 {
+            return Err(invalid("has exhausted its budget"));
+        }
+        discount = discount.min(remaining);
+    }
+    Ok((promo, discount))
+}
+
+pub(crate) fn do_redeem_promo_code(mut promo: PromoCode, customer_id: u64, discount: u64) {
+    let key = (StringKey(promo.code.clone()), customer_id);
+    PROMO_REDEMPTIONS.with(|service| {
+        let mut redemptions = service.borrow_mut();
+        let uses = redemptions.get(&key).unwrap_or(0);
+        redemptions.insert(key, uses + 1);
+    });
+    promo.uses += 1;
+    promo.discount_granted += discount;
+    do_insert_promo_code(&promo);
+}
+
+// Gives a redemption back to the code, e.g. when the reservation it was used for is cancelled
+pub(crate) fn do_release_promo_code(code: &str, customer_id: u64, discount: u64) {
+    let key = (StringKey(code.to_string()), customer_id);
+    PROMO_REDEMPTIONS.with(|service| {
+        let mut redemptions = service.borrow_mut();
+        match redemptions.get(&key) {
+            Some(uses) if uses > 1 => {
+                redemptions.insert(key, uses - 1);
+            }
+            _ => {
+                redemptions.remove(&key);
+            }
+        }
+    });
+    if let Some(mut promo) = _get_promo_code(code) {
+        promo.uses = promo.uses.saturating_sub(1);
+        promo.discount_granted = promo.discount_granted.saturating_sub(discount);
+        do_insert_promo_code(&promo);
+    }
+}
+
+// Gives part of a discount back to the code's budget while the redemption itself stands, e.g.
+// when some of the passengers it was granted for are cancelled
+pub(crate) fn do_release_promo_discount(code: &str, discount: u64) {
+    if let Some(mut promo) = _get_promo_code(code) {
+        promo.discount_granted = promo.discount_granted.saturating_sub(discount);
+        do_insert_promo_code(&promo);
+    }
+}
+
+fn do_insert_promo_code(promo: &PromoCode) {
+    PROMO_CODE_STORAGE.with(|service| {
+        service
+            .borrow_mut()
+            .insert(StringKey(promo.code.clone()), promo.clone())
+    });
+}
+
+fn _get_promo_code(code: &str) -> Option<PromoCode> {
+    PROMO_CODE_STORAGE.with(|service| service.borrow().get(&StringKey(code.to_string())))
+}
+
+fn _get_promo_redemptions(code: &str, customer_id: u64) -> u32 {
+    PROMO_REDEMPTIONS.with(|service| {
+        service
+            .borrow()
+            .get(&(StringKey(code.to_string()), customer_id))
+            .unwrap_or(0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn promo(
+        route_ids: Vec<u64>,
+        travel_from: Option<u64>,
+        travel_until: Option<u64>,
+    ) -> PromoCode {
+        PromoCode {
+            code: "SUMMER".to_string(),
+            discount: Discount::Percentage(10),
+            bus_ids: Vec::new(),
+            route_ids,
+            valid_from: 0,
+            expires_at: 1_000,
+            travel_from,
+            travel_until,
+            max_uses: None,
+            max_uses_per_customer: None,
+            budget: None,
+            uses: 0,
+            discount_granted: 0,
+            is_active: true,
+            created_at: 0,
+            updated_at: None,
+        }
+    }
+
+    fn trip(route_id: Option<u64>, departure_time: u64) -> Trip {
+        Trip {
+            id: 1,
+            bus_id: 1,
+            origin: "Harbour".to_string(),
+            destination: "Airport".to_string(),
+            departure_time,
+            arrival_time: departure_time + 60,
+            status: TripStatus::Scheduled,
+            driver_id: None,
+            route_id,
+            delay_minutes: 0,
+            actual_departure_time: None,
+            actual_arrival_time: None,
+            timetable_id: None,
+            created_at: 0,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn limits_codes_to_routes_and_travel_dates() {
+        do_insert_promo_code(&promo(vec![5], Some(2_000), Some(3_000)));
+        let bus = Bus {
+            id: 1,
+            ..Default::default()
+        };
+        let check = |trip: Option<&Trip>| check_promo_code("summer", &bus, trip, 1, 500, 100);
+
+        assert_eq!(check(Some(&trip(Some(5), 2_500))).ok().unwrap().1, 50);
+        assert!(check(Some(&trip(Some(6), 2_500))).is_err());
+        assert!(check(Some(&trip(Some(5), 1_999))).is_err());
+        assert!(check(Some(&trip(Some(5), 3_000))).is_err());
+        assert!(check(None).is_err());
+    }
+
+    #[test]
+    fn a_code_with_every_restriction_fits_its_record() {
+        let mut promo = promo(vec![u64::MAX; MAX_PROMO_ROUTE_IDS], Some(1), Some(2));
+        promo.code = "X".repeat(32);
+        promo.bus_ids = vec![u64::MAX; MAX_PROMO_BUS_IDS];
+        promo.max_uses = Some(u32::MAX);
+        promo.max_uses_per_customer = Some(u32::MAX);
+        promo.budget = Some(u64::MAX);
+        promo.updated_at = Some(u64::MAX);
+        assert!(promo.to_bytes().len() <= PromoCode::MAX_SIZE as usize);
+    }
+}
@@ -0,0 +1,273 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+enum RebookingOutcome {
+    Rebooked {
+        reservation_id: u64,
+        trip_id: u64,
+        seat_number: u32,
+        departure_time: u64,
+    },
+    Refunded {
+        amount: u64,
+    },
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct PassengerRebooking {
+    reservation_id: u64, // the booking the passenger had on the cancelled trip
+    passenger_id: u32,
+    name: String,
+    outcome: RebookingOutcome,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct TripCancellationReport {
+    trip: Trip,
+    passengers: Vec<PassengerRebooking>,
+}
+
+// Cancels the trip, frees the bus for the trip window and moves its passengers to later trips
+// on the same route, refunding those that can't be seated anywhere
+#[ic_cdk::update]
+fn cancel_trip(trip_id: u64, reason: String) -> Result<TripCancellationReport, Error> {
+    ensure_trip_event_reason(&reason)?;
+    let mut trip = _get_trip_for_update(trip_id)?;
+    if trip.status != TripStatus::Scheduled {
+        return Err(Error::InvalidInput {
+            msg: format!("trip with id={} has already started", trip_id),
+        });
+    }
+    trip.status = TripStatus::Cancelled;
+    trip.updated_at = Some(time());
+    do_unblock_bus(trip.bus_id, trip.departure_time);
+    do_record_trip_event(&trip, TripEventKind::Cancelled, None, None, Some(reason));
+    do_insert_trip(&trip);
+    let alternatives = _get_alternative_trips(&trip);
+    let mut passengers = Vec::new();
+    for reservation in _get_reservations() {
+        if reservation.trip_id == Some(trip.id)
+            && reservation.status == ReservationStatus::Confirmed
+        {
+            passengers.extend(do_rebook_reservation(reservation, &alternatives));
+        }
+    }
+    Ok(TripCancellationReport { trip, passengers })
+}
+
+// Scheduled trips on the same route (or between the same places when the trip has no route)
+// leaving no earlier than the cancelled one, soonest first
+fn _get_alternative_trips(cancelled: &Trip) -> Vec<Trip> {
+    let now = time();
+    let mut trips: Vec<Trip> = TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, trip)| trip)
+            .filter(|trip| {
+                trip.id != cancelled.id
+                    && trip.status == TripStatus::Scheduled
+                    && trip.departure_time >= cancelled.departure_time
+                    && trip.departure_time > now
+                    && match cancelled.route_id {
+                        Some(route_id) => trip.route_id == Some(route_id),
+                        None => {
+                            trip.origin == cancelled.origin
+                                && trip.destination == cancelled.destination
+                        }
+                    }
+            })
+            .collect()
+    });
+    trips.sort_by_key(|trip| (trip.departure_time, trip.id));
+    trips
+}
+
+// Seats the reservation's passengers on the earliest alternative with room in their seat class.
+// Passengers keep the fare they paid. The first group to be seated stays on the original
+// reservation and any others are split into reservations of their own, taking their share of
+// the discount and redeemed points along (see `Reservation::split_off_fare`). Whoever can't be
+// seated is refunded.
+fn do_rebook_reservation(
+    mut reservation: Reservation,
+    alternatives: &[Trip],
+) -> Vec<PassengerRebooking> {
+    let now = time();
+    let mut remaining: Vec<Passenger> = reservation.active_passengers().cloned().collect();
+    let mut moves: Vec<(&Trip, Vec<Passenger>)> = Vec::new();
+    for trip in alternatives {
+        if remaining.is_empty() {
+            break;
+        }
+        let Some(bus) = _get_bus(&trip.bus_id) else {
+            continue;
+        };
+        // Passengers keep their boarding and alighting stops when moved along the same route
+        let legs = trip
+            .route_id
+            .and_then(|route_id| _get_route(&route_id))
+            .and_then(|route| {
+                route_legs(&route, reservation.from_stop, reservation.to_stop)
+                    .and_then(|(from, to)| leg_mask(from, to))
+                    .ok()
+            })
+            .unwrap_or(ALL_LEGS);
+        let occupied = _get_occupied_seats(bus.id, Some(trip.id), legs);
+        let mut free: BTreeMap<SeatClass, usize> = BTreeMap::new();
+        for seat in _get_bus_seats(&bus) {
+            if !occupied.contains(&seat.seat_number) {
+                *free.entry(seat.seat_class).or_default() += 1;
+            }
+        }
+        let (mut seated, rest): (Vec<Passenger>, Vec<Passenger>) =
+            remaining.into_iter().partition(|passenger| {
+                match free.get_mut(&passenger.seat_class) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        true
+                    }
+                    _ => false,
+                }
+            });
+        remaining = rest;
+        if seated.is_empty() {
+            continue;
+        }
+        let seat_classes: Vec<SeatClass> = seated
+            .iter()
+            .map(|passenger| passenger.seat_class)
+            .collect();
+        let Ok(seats) = allocate_seats(&bus, Some(trip.id), legs, &seat_classes) else {
+            remaining.append(&mut seated);
+            continue;
+        };
+        for (passenger, seat_number) in seated.iter_mut().zip(seats) {
+            passenger.seat_number = seat_number;
+        }
+        moves.push((trip, seated));
+    }
+
+    let mut report = Vec::new();
+    if moves.is_empty() {
+        // Nobody could be moved, so the whole booking is given back
+        if let Some(code) = &reservation.promo_code {
+            do_release_promo_code(code, reservation.customer_id, reservation.discount);
+        }
+        if reservation.points_redeemed > 0 {
+            do_refund_points(
+                reservation.customer_id,
+                reservation.points_redeemed,
+                reservation.id,
+                now,
+            );
+        }
+        let fare = reservation.fare.max(1);
+        let amount_due = reservation.amount_due();
+        for passenger in reservation.active_passengers() {
+            report.push(PassengerRebooking {
+                reservation_id: reservation.id,
+                passenger_id: passenger.id,
+                name: passenger.name.clone(),
+                outcome: RebookingOutcome::Refunded {
+                    amount: amount_due * passenger.fare / fare,
+                },
+            });
+        }
+        reservation.status = ReservationStatus::Refunded;
+        do_insert_reservation(&reservation);
+        return report;
+    }
+
+    let mut moves = moves.into_iter();
+    let (first_trip, first_group) = moves.next().expect("at least one group was seated");
+    for (trip, group) in moves {
+        let fare: u64 = group.iter().map(|passenger| passenger.fare).sum();
+        let (discount, points_redeemed) = reservation.split_off_fare(fare);
+        let split = Reservation {
+            id: next_id(),
+            bus_id: trip.bus_id,
+            trip_id: Some(trip.id),
+            fare,
+            discount,
+            // The code's redemption stays with the original booking
+            promo_code: None,
+            points_redeemed,
+            passengers: group,
+            ..reservation.clone()
+        };
+        if points_redeemed > 0 {
+            do_move_point_draws(reservation.id, split.id, points_redeemed);
+        }
+        for passenger in &split.passengers {
+            report.push(PassengerRebooking {
+                reservation_id: reservation.id,
+                passenger_id: passenger.id,
+                name: passenger.name.clone(),
+                outcome: RebookingOutcome::Rebooked {
+                    reservation_id: split.id,
+                    trip_id: trip.id,
+                    seat_number: passenger.seat_number,
+                    departure_time: trip.departure_time,
+                },
+            });
+        }
+        reservation.passengers.retain(|passenger| {
+            !split
+                .passengers
+                .iter()
+                .any(|moved| moved.id == passenger.id)
+        });
+        do_insert_reservation(&split);
+    }
+    for passenger in reservation.passengers.iter_mut() {
+        if let Some(moved) = first_group.iter().find(|moved| moved.id == passenger.id) {
+            passenger.seat_number = moved.seat_number;
+            report.push(PassengerRebooking {
+                reservation_id: reservation.id,
+                passenger_id: passenger.id,
+                name: passenger.name.clone(),
+                outcome: RebookingOutcome::Rebooked {
+                    reservation_id: reservation.id,
+                    trip_id: first_trip.id,
+                    seat_number: moved.seat_number,
+                    departure_time: first_trip.departure_time,
+                },
+            });
+        }
+    }
+    for refunded in &remaining {
+        let (discount, points_redeemed) = reservation.split_off_fare(refunded.fare);
+        if let Some(passenger) = reservation
+            .passengers
+            .iter_mut()
+            .find(|passenger| passenger.id == refunded.id)
+        {
+            passenger.cancelled_at = Some(now);
+        }
+        if let Some(code) = reservation.promo_code.as_deref().filter(|_| discount > 0) {
+            do_release_promo_discount(code, discount);
+        }
+        if points_redeemed > 0 {
+            do_refund_points(
+                reservation.customer_id,
+                points_redeemed,
+                reservation.id,
+                now,
+            );
+        }
+        report.push(PassengerRebooking {
+            reservation_id: reservation.id,
+            passenger_id: refunded.id,
+            name: refunded.name.clone(),
+            outcome: RebookingOutcome::Refunded {
+                amount: refunded
+                    .fare
+                    .saturating_sub(discount + points_redeemed * LOYALTY_POINT_VALUE),
+            },
+        });
+    }
+    reservation.bus_id = first_trip.bus_id;
+    reservation.trip_id = Some(first_trip.id);
+    do_insert_reservation(&reservation);
+    report
+}
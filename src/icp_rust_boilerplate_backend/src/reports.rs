@@ -0,0 +1,353 @@
+use super::*;
+use sha2::{Digest, Sha256};
+
+// A CSV report: its header, then rows in the order of a key that stays put as records change,
+// so a download streamed over several calls neither repeats nor skips rows
+pub(crate) struct CsvReport {
+    header: String,
+    rows: Vec<(Vec<u64>, String)>,
+}
+
+// Lets a spreadsheet or BI tool fetch reports over HTTP, where every caller is anonymous. Only
+// the SHA-256 of the token is kept, so it's shown just once, when it's created.
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct ReportToken {
+    id: u64,
+    label: String, // who or what the token was handed to
+    token_hash: String,
+    created_at: u64,
+}
+
+impl Storable for ReportToken {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for ReportToken {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+const MAX_REPORT_TOKEN_LABEL_LEN: usize = 128;
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct NewReportToken {
+    report_token: ReportToken,
+    token: String, // goes in the `token` query parameter of /reports/*.csv
+}
+
+#[ic_cdk::update]
+async fn create_report_token(label: String) -> Result<NewReportToken, Error> {
+    ensure_admin()?;
+    let label = label.trim().to_string();
+    if label.is_empty() || label.len() > MAX_REPORT_TOKEN_LABEL_LEN {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a report token needs a label of at most {} bytes",
+                MAX_REPORT_TOKEN_LABEL_LEN
+            ),
+        });
+    }
+    let (bytes,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(_, msg)| Error::Conflict {
+            msg: format!("couldn't draw a report token, try again: {}", msg),
+        })?;
+    let token = hex(&bytes);
+    let report_token = do_add_report_token(label, &token, time());
+    Ok(NewReportToken {
+        report_token,
+        token,
+    })
+}
+
+#[ic_cdk::query]
+fn list_report_tokens() -> Result<Vec<ReportToken>, Error> {
+    ensure_admin()?;
+    Ok(REPORT_TOKENS.with(|service| service.borrow().iter().map(|(_, token)| token).collect()))
+}
+
+#[ic_cdk::update]
+fn revoke_report_token(id: u64) -> Result<ReportToken, Error> {
+    ensure_admin()?;
+    REPORT_TOKENS
+        .with(|service| service.borrow_mut().remove(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a report token with id={} not found", id),
+        })
+}
+
+pub(crate) fn do_add_report_token(label: String, token: &str, now: u64) -> ReportToken {
+    let report_token = ReportToken {
+        id: next_id(),
+        label,
+        token_hash: hex(&Sha256::digest(token.as_bytes())),
+        created_at: now,
+    };
+    REPORT_TOKENS.with(|service| {
+        service
+            .borrow_mut()
+            .insert(report_token.id, report_token.clone())
+    });
+    report_token
+}
+
+// Every stored hash is compared in full, so timing doesn't reveal which one came close
+fn ensure_report_token(token: &str) -> Result<(), Error> {
+    let hash = hex(&Sha256::digest(token.as_bytes()));
+    let known = REPORT_TOKENS.with(|service| {
+        service.borrow().iter().fold(false, |known, (_, report_token)| {
+            known | constant_time_eq(report_token.token_hash.as_bytes(), hash.as_bytes())
+        })
+    });
+    if known {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: "the report token is not valid".to_string(),
+        })
+    }
+}
+
+// A single body stays well below the response size limit; longer reports are streamed
+const CSV_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub(crate) fn csv_report_response(report: &str, call: &HttpCall) -> Result<HttpResponse, Error> {
+    let csv = build_csv_report(report, call)?;
+    let (body, next) = csv_chunk(&csv, None);
+    let mut response = HttpResponse::ok("text/csv", body);
+    response.headers.push((
+        "Content-Disposition".to_string(),
+        format!("attachment; filename=\"{}.csv\"", report),
+    ));
+    response.streaming_strategy = next.map(|key| StreamingStrategy::Callback {
+        callback: StreamingCallback::new(
+            ic_cdk::id(),
+            "http_request_streaming_callback".to_string(),
+        ),
+        token: StreamingCallbackToken {
+            report: report.to_string(),
+            query: call
+                .query
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("&"),
+            key,
+        },
+    });
+    Ok(response)
+}
+
+// The header, or the rows after the one with key `after`, up to the chunk size, and the key of
+// the last row sent if any are left
+pub(crate) fn csv_chunk(csv: &CsvReport, after: Option<&[u64]>) -> (Vec<u8>, Option<Vec<u64>>) {
+    let (mut body, start) = match after {
+        Some(after) => {
+            let start = csv.rows.partition_point(|(key, _)| key.as_slice() <= after);
+            (Vec::new(), start)
+        }
+        None => (csv.header.clone().into_bytes(), 0),
+    };
+    for (index, (_, line)) in csv.rows.iter().enumerate().skip(start) {
+        if index > start && body.len() + line.len() > CSV_CHUNK_SIZE {
+            return (body, Some(csv.rows[index - 1].0.clone()));
+        }
+        body.extend_from_slice(line.as_bytes());
+    }
+    (body, None)
+}
+
+// Reservations matching the filters of a reservation or revenue report
+fn _get_report_reservations(call: &HttpCall) -> Result<Vec<Reservation>, Error> {
+    let from = call.param(&PARAM_FROM)?.unwrap_or(0);
+    let to = call.param(&PARAM_TO)?.unwrap_or(u64::MAX);
+    let bus_id = call.param(&PARAM_BUS_ID)?;
+    let trip_id = call.param(&PARAM_TRIP_ID)?;
+    let customer_id = call.param(&PARAM_CUSTOMER_ID)?;
+    Ok(_get_reservations()
+        .into_iter()
+        .filter(|reservation| {
+            (from..to).contains(&reservation.reservation_time)
+                && bus_id.is_none_or(|id| reservation.bus_id == id)
+                && trip_id.is_none_or(|id| reservation.trip_id == Some(id))
+                && customer_id.is_none_or(|id| reservation.customer_id == id)
+        })
+        .collect())
+}
+
+// Rows are keyed by id, apart from revenue which is keyed by day and bus
+pub(crate) fn build_csv_report(report: &str, call: &HttpCall) -> Result<CsvReport, Error> {
+    ensure_report_token(&call.require(&PARAM_TOKEN)?)?;
+    let mut csv = CsvReport {
+        header: String::new(),
+        rows: Vec::new(),
+    };
+    match report {
+        "buses" => {
+            let model_id = call.param(&PARAM_MODEL_ID)?;
+            csv.header = csv_row(&[
+                "id",
+                "registration_plate",
+                "vin",
+                "make",
+                "model",
+                "year",
+                "color",
+                "owner",
+                "capacity",
+                "fare",
+                "odometer_km",
+                "is_booked",
+                "created_at",
+            ]);
+            for bus in generate_report()
+                .into_iter()
+                .filter(|bus| model_id.is_none_or(|id| bus.model_id == Some(id)))
+            {
+                let line = csv_row(&[
+                    &bus.id.to_string(),
+                    bus.registration_plate.as_deref().unwrap_or_default(),
+                    bus.vin.as_deref().unwrap_or_default(),
+                    &bus.make,
+                    &bus.model,
+                    &bus.year.to_string(),
+                    &bus.color,
+                    &bus.owner,
+                    &bus.capacity.to_string(),
+                    &bus.fare.to_string(),
+                    &bus.odometer_km.to_string(),
+                    &bus.is_booked.to_string(),
+                    &bus.created_at.to_string(),
+                ]);
+                csv.rows.push((vec![bus.id], line));
+            }
+        }
+        "reservations" => {
+            csv.header = csv_row(&[
+                "id",
+                "reservation_time",
+                "status",
+                "customer_id",
+                "bus_id",
+                "trip_id",
+                "from_stop",
+                "to_stop",
+                "passengers",
+                "fare",
+                "discount",
+                "promo_code",
+                "points_redeemed",
+                "amount_due",
+            ]);
+            let optional = |value: Option<u64>| value.map(|id| id.to_string()).unwrap_or_default();
+            for reservation in _get_report_reservations(call)? {
+                let status = match reservation.status {
+                    ReservationStatus::Confirmed => "confirmed",
+                    ReservationStatus::Completed => "completed",
+                    ReservationStatus::Refunded => "refunded",
+                };
+                let line = csv_row(&[
+                    &reservation.id.to_string(),
+                    &reservation.reservation_time.to_string(),
+                    status,
+                    &reservation.customer_id.to_string(),
+                    &reservation.bus_id.to_string(),
+                    &optional(reservation.trip_id),
+                    &optional(reservation.from_stop),
+                    &optional(reservation.to_stop),
+                    &reservation.active_passengers().count().to_string(),
+                    &reservation.fare.to_string(),
+                    &reservation.discount.to_string(),
+                    reservation.promo_code.as_deref().unwrap_or_default(),
+                    &reservation.points_redeemed.to_string(),
+                    &reservation.amount_due().to_string(),
+                ]);
+                csv.rows.push((vec![reservation.id], line));
+            }
+        }
+        "revenue" => {
+            // (reservations, passengers, fare, discount, points value, refunded) per day and bus
+            let mut totals: BTreeMap<(u64, u64), [u64; 6]> = BTreeMap::new();
+            for reservation in _get_report_reservations(call)? {
+                let day = reservation.reservation_time / NANOS_PER_DAY;
+                let total = totals.entry((day, reservation.bus_id)).or_default();
+                total[0] += 1;
+                total[1] += reservation.active_passengers().count() as u64;
+                total[2] += reservation.fare;
+                total[3] += reservation.discount;
+                total[4] += reservation.points_redeemed * LOYALTY_POINT_VALUE;
+                if reservation.status == ReservationStatus::Refunded {
+                    total[5] += reservation.amount_due();
+                }
+            }
+            csv.header = csv_row(&[
+                "date",
+                "bus_id",
+                "reservations",
+                "passengers",
+                "fare",
+                "discount",
+                "points_value",
+                "refunded",
+                "net_revenue",
+            ]);
+            for ((day, bus_id), total) in totals {
+                let key = vec![day, bus_id];
+                let (year, month, day) = civil_from_days(day as i64);
+                let net = total[2] - total[3] - total[4] - total[5];
+                let mut fields = vec![
+                    format!("{:04}-{:02}-{:02}", year, month, day),
+                    bus_id.to_string(),
+                ];
+                fields.extend(total.iter().map(|value| value.to_string()));
+                fields.push(net.to_string());
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                csv.rows.push((key, csv_row(&fields)));
+            }
+        }
+        _ => {
+            return Err(Error::NotFound {
+                msg: format!("there is no report {}", report),
+            })
+        }
+    }
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_csv_reports_by_row_key() {
+        let row = |id: u64| (vec![id], format!("{},{}\r\n", id, "x".repeat(400 * 1024)));
+        let mut csv = CsvReport {
+            header: "id,data\r\n".to_string(),
+            rows: vec![row(2), row(4), row(6)],
+        };
+        let (body, next) = csv_chunk(&csv, None);
+        assert!(body.starts_with(b"id,data\r\n2,"));
+        assert_eq!(next, Some(vec![4]));
+
+        // Rows added before the last one sent don't shift what comes next
+        csv.rows.insert(0, row(1));
+        csv.rows.insert(2, row(3));
+        let (body, next) = csv_chunk(&csv, next.as_deref());
+        assert!(body.starts_with(b"6,"));
+        assert_eq!(next, None);
+
+        // A row longer than a chunk still goes out on its own
+        let csv = CsvReport {
+            header: String::new(),
+            rows: vec![(vec![1], "y".repeat(CSV_CHUNK_SIZE + 1)), row(2)],
+        };
+        let (body, next) = csv_chunk(&csv, None);
+        assert_eq!((body.len(), next), (CSV_CHUNK_SIZE + 1, Some(vec![1])));
+    }
+}
@@ -0,0 +1,229 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Stop {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) created_at: u64,
+}
+
+impl Storable for Stop {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Stop {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct StopPayload {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+// Leg bitmaps are u64, so a route has at most 63 legs
+pub(crate) const MAX_ROUTE_STOPS: usize = 64;
+pub(crate) const ALL_LEGS: u64 = u64::MAX;
+
+// A stop on a route. Offsets are measured from the trip's departure from the first stop.
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct RouteStop {
+    pub(crate) stop_id: u64,
+    pub(crate) distance_m: u64, // along the route from the first stop
+    pub(crate) arrival_offset: u64,
+    pub(crate) departure_offset: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Route {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub(crate) stops: Vec<RouteStop>,
+    pub(crate) created_at: u64,
+    pub(crate) updated_at: Option<u64>,
+}
+
+impl Storable for Route {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Route {
+    const MAX_SIZE: u32 = 8192;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct RoutePayload {
+    name: String,
+    stops: Vec<RouteStop>,
+}
+
+#[ic_cdk::update]
+fn add_stop(payload: StopPayload) -> Result<Stop, Error> {
+    ensure_admin()?;
+    if payload.name.trim().is_empty() {
+        return Err(Error::InvalidInput {
+            msg: "a stop needs a name".to_string(),
+        });
+    }
+    if payload.name.trim().len() > MAX_PLACE_NAME_LEN {
+        return Err(Error::InvalidInput {
+            msg: format!("a stop name can be at most {} bytes", MAX_PLACE_NAME_LEN),
+        });
+    }
+    if !(-90.0..=90.0).contains(&payload.latitude) || !(-180.0..=180.0).contains(&payload.longitude)
+    {
+        return Err(Error::InvalidInput {
+            msg: "stop coordinates are out of range".to_string(),
+        });
+    }
+    let stop = Stop {
+        id: next_id(),
+        name: payload.name.trim().to_string(),
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        created_at: time(),
+    };
+    STOP_STORAGE.with(|service| service.borrow_mut().insert(stop.id, stop.clone()));
+    Ok(stop)
+}
+
+#[ic_cdk::query]
+fn get_stop(id: u64) -> Result<Stop, Error> {
+    match _get_stop(&id) {
+        Some(stop) => Ok(stop),
+        None => Err(Error::NotFound {
+            msg: format!("a stop with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn list_stops() -> Vec<Stop> {
+    STOP_STORAGE.with(|service| service.borrow().iter().map(|(_, stop)| stop).collect())
+}
+
+#[ic_cdk::update]
+fn add_route(payload: RoutePayload) -> Result<Route, Error> {
+    ensure_admin()?;
+    validate_route_stops(&payload.stops)?;
+    let route = Route {
+        id: next_id(),
+        name: payload.name,
+        stops: payload.stops,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_insert_route(&route);
+    Ok(route)
+}
+
+#[ic_cdk::query]
+fn get_route(id: u64) -> Result<Route, Error> {
+    match _get_route(&id) {
+        Some(route) => Ok(route),
+        None => Err(Error::NotFound {
+            msg: format!("a route with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn list_routes() -> Vec<Route> {
+    ROUTE_STORAGE.with(|service| service.borrow().iter().map(|(_, route)| route).collect())
+}
+
+// Stops must exist and be listed in travel order: distances and times never go backwards
+pub(crate) fn validate_route_stops(stops: &[RouteStop]) -> Result<(), Error> {
+    if stops.len() < 2 || stops.len() > MAX_ROUTE_STOPS {
+        return Err(Error::InvalidInput {
+            msg: format!("a route needs between 2 and {} stops", MAX_ROUTE_STOPS),
+        });
+    }
+    if let Some(stop) = stops.iter().find(|stop| _get_stop(&stop.stop_id).is_none()) {
+        return Err(Error::NotFound {
+            msg: format!("a stop with id={} not found", stop.stop_id),
+        });
+    }
+    let first = &stops[0];
+    if first.distance_m != 0 || first.arrival_offset != 0 {
+        return Err(Error::InvalidInput {
+            msg: "the first stop of a route is at distance and time zero".to_string(),
+        });
+    }
+    for stop in stops {
+        if stop.departure_offset < stop.arrival_offset {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "the bus can't leave stop id={} before arriving",
+                    stop.stop_id
+                ),
+            });
+        }
+    }
+    for pair in stops.windows(2) {
+        if pair[1].distance_m < pair[0].distance_m
+            || pair[1].arrival_offset < pair[0].departure_offset
+        {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "stop id={} comes before stop id={} on the route",
+                    pair[1].stop_id, pair[0].stop_id
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Routes stored before stops were capped can't have their legs booked separately, so no new
+// trips are scheduled on them
+pub(crate) fn ensure_route_length(route: &Route) -> Result<(), Error> {
+    if route.stops.len() > MAX_ROUTE_STOPS {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "route with id={} has more than {} stops",
+                route.id, MAX_ROUTE_STOPS
+            ),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn _get_route_endpoints(route: &Route) -> (String, String) {
+    let name = |route_stop: Option<&RouteStop>| {
+        route_stop
+            .and_then(|route_stop| _get_stop(&route_stop.stop_id))
+            .map(|stop| stop.name)
+            .unwrap_or_default()
+    };
+    (name(route.stops.first()), name(route.stops.last()))
+}
+
+pub(crate) fn do_insert_route(route: &Route) {
+    ROUTE_STORAGE.with(|service| service.borrow_mut().insert(route.id, route.clone()));
+}
+
+pub(crate) fn _get_route(id: &u64) -> Option<Route> {
+    ROUTE_STORAGE.with(|service| service.borrow().get(id))
+}
+
+pub(crate) fn _get_stop(id: &u64) -> Option<Stop> {
+    STOP_STORAGE.with(|service| service.borrow().get(id))
+}
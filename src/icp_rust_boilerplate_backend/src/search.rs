@@ -0,0 +1,362 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct TripSearchPayload {
+    origin: Option<String>,
+    destination: Option<String>,
+    departs_after: u64,
+    departs_before: u64,
+    required_amenities: Vec<Amenity>,
+    seat_class: Option<SeatClass>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct TripSearchResult {
+    trip: Trip,
+    amenities: Vec<Amenity>,
+    seat_class: SeatClass,
+    seats_available: u32,
+    fare: u64, // adult fare for `seat_class`
+}
+
+// Shortest time allowed between arriving on one leg and leaving on the next
+const MIN_TRANSFER_TIME: u64 = 10 * 60 * 1_000_000_000;
+const MAX_TRANSFERS: u8 = 2;
+const MAX_JOURNEY_RESULTS: usize = 20;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct JourneyLeg {
+    trip_id: u64,
+    route_id: u64,
+    bus_id: u64,
+    from_stop: u64,
+    to_stop: u64,
+    departure_time: u64,
+    arrival_time: u64,
+    seats_available: u32,
+    fare: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Itinerary {
+    legs: Vec<JourneyLeg>,
+    departure_time: u64,
+    arrival_time: u64,
+    transfers: u8,
+    seats_available: u32, // the fewest free seats on any leg
+    fare: u64,
+}
+
+// A trip with the expected times at each stop of its route
+struct TimedTrip {
+    trip: Trip,
+    route: Route,
+    stops: Vec<(u64, u64, u64)>, // (stop_id, arrival, departure)
+    standard_seats: Vec<u64>,    // legs each standard seat is taken on
+    fare: u64,
+}
+
+// Lists bookable trips in the departure window whose bus has every required amenity and at
+// least one free seat of the requested class (standard if none is given)
+#[ic_cdk::query]
+fn search_trips(payload: TripSearchPayload) -> Vec<TripSearchResult> {
+    let now = time();
+    let seat_class = payload.seat_class.unwrap_or(SeatClass::Standard);
+    let matches_place = |wanted: &Option<String>, actual: &str| {
+        wanted
+            .as_ref()
+            .is_none_or(|wanted| wanted.trim().eq_ignore_ascii_case(actual.trim()))
+    };
+    let trips: Vec<Trip> = TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, trip)| trip)
+            .filter(|trip| {
+                trip.status == TripStatus::Scheduled
+                    && trip.departure_time > now
+                    && trip.departure_time >= payload.departs_after
+                    && trip.departure_time < payload.departs_before
+                    && matches_place(&payload.origin, &trip.origin)
+                    && matches_place(&payload.destination, &trip.destination)
+            })
+            .collect()
+    });
+    trips
+        .into_iter()
+        .filter_map(|trip| {
+            let bus = _get_bus(&trip.bus_id)?;
+            let has_amenities = payload
+                .required_amenities
+                .iter()
+                .all(|amenity| bus.amenities.contains(amenity));
+            if !has_amenities {
+                return None;
+            }
+            let occupied = _get_occupied_seats(bus.id, Some(trip.id), ALL_LEGS);
+            let seats_available = _get_bus_seats(&bus)
+                .iter()
+                .filter(|seat| {
+                    seat.seat_class == seat_class && !occupied.contains(&seat.seat_number)
+                })
+                .count() as u32;
+            (seats_available > 0).then(|| TripSearchResult {
+                fare: class_fare(&bus, seat_class),
+                amenities: bus.amenities,
+                trip,
+                seat_class,
+                seats_available,
+            })
+        })
+        .collect()
+}
+
+// Finds ways from `from_stop` to `to_stop` leaving on the day containing `date`, changing buses
+// at most `max_transfers` times. Standard seats and adult fares are used for availability and
+// price. Results are ordered by arrival, then by number of transfers.
+#[ic_cdk::query]
+fn search_journeys(
+    from_stop: u64,
+    to_stop: u64,
+    date: u64,
+    max_transfers: u8,
+) -> Result<Vec<Itinerary>, Error> {
+    for stop_id in [from_stop, to_stop] {
+        if _get_stop(&stop_id).is_none() {
+            return Err(Error::NotFound {
+                msg: format!("a stop with id={} not found", stop_id),
+            });
+        }
+    }
+    if from_stop == to_stop {
+        return Err(Error::InvalidInput {
+            msg: "origin and destination must be different stops".to_string(),
+        });
+    }
+    if max_transfers > MAX_TRANSFERS {
+        return Err(Error::InvalidInput {
+            msg: format!("at most {} transfers are supported", MAX_TRANSFERS),
+        });
+    }
+    let day_start = date / NANOS_PER_DAY * NANOS_PER_DAY;
+    // Later legs may run past midnight, so trips of the following day are considered too
+    let trips = _get_timed_trips(day_start, day_start + 2 * NANOS_PER_DAY);
+    // Where each stop appears on the trips: (index into `trips`, position on the trip's route)
+    let mut calls: BTreeMap<u64, Vec<(usize, usize)>> = BTreeMap::new();
+    for (index, timed) in trips.iter().enumerate() {
+        for (position, (stop_id, _, _)) in timed.stops.iter().enumerate() {
+            calls.entry(*stop_id).or_default().push((index, position));
+        }
+    }
+    let earliest = day_start.max(time());
+    let mut itineraries = Vec::new();
+    let mut legs = Vec::new();
+    let mut visited = vec![from_stop];
+    search_legs(
+        &trips,
+        &calls,
+        from_stop,
+        to_stop,
+        earliest,
+        day_start + NANOS_PER_DAY,
+        max_transfers + 1,
+        &mut legs,
+        &mut visited,
+        &mut itineraries,
+    );
+    Ok(itineraries)
+}
+
+// Depth-first over boardable legs. `ready` is the earliest time the traveller can leave
+// `at_stop`; a first leg must also leave before `board_before`. Once MAX_JOURNEY_RESULTS
+// itineraries are kept, legs arriving after the last of them can't lead to a better one and
+// are not followed.
+#[allow(clippy::too_many_arguments)]
+fn search_legs(
+    trips: &[TimedTrip],
+    calls: &BTreeMap<u64, Vec<(usize, usize)>>,
+    at_stop: u64,
+    to_stop: u64,
+    ready: u64,
+    board_before: u64,
+    legs_left: u8,
+    legs: &mut Vec<JourneyLeg>,
+    visited: &mut Vec<u64>,
+    itineraries: &mut Vec<Itinerary>,
+) {
+    if legs_left == 0 {
+        return;
+    }
+    let mut boarded: Option<usize> = None;
+    for &(index, board) in calls.get(&at_stop).into_iter().flatten() {
+        // A trip calling at the stop more than once is boarded at its first usable call
+        if boarded == Some(index) {
+            continue;
+        }
+        let timed = &trips[index];
+        let departure = timed.stops[board].2;
+        if departure < ready
+            || (legs.is_empty() && departure >= board_before)
+            || legs.iter().any(|leg| leg.trip_id == timed.trip.id)
+        {
+            continue;
+        }
+        boarded = Some(index);
+        for (alight, &(stop_id, arrival, _)) in timed.stops.iter().enumerate().skip(board + 1) {
+            if arrival > _get_latest_useful_arrival(itineraries) {
+                break;
+            }
+            if visited.contains(&stop_id) {
+                continue;
+            }
+            let Ok(taken) = leg_mask(board, alight) else {
+                break;
+            };
+            let seats_available = timed
+                .standard_seats
+                .iter()
+                .filter(|occupied| *occupied & taken == 0)
+                .count() as u32;
+            if seats_available == 0 {
+                continue;
+            }
+            let (share, whole) = route_share(&timed.route, board, alight);
+            legs.push(JourneyLeg {
+                trip_id: timed.trip.id,
+                route_id: timed.route.id,
+                bus_id: timed.trip.bus_id,
+                from_stop: at_stop,
+                to_stop: stop_id,
+                departure_time: timed.stops[board].2,
+                arrival_time: arrival,
+                seats_available,
+                fare: timed.fare * share / whole,
+            });
+            if stop_id == to_stop {
+                keep_itinerary(
+                    itineraries,
+                    Itinerary {
+                        departure_time: legs[0].departure_time,
+                        arrival_time: arrival,
+                        transfers: legs.len() as u8 - 1,
+                        seats_available: legs
+                            .iter()
+                            .map(|leg| leg.seats_available)
+                            .min()
+                            .unwrap_or(0),
+                        fare: legs.iter().map(|leg| leg.fare).sum(),
+                        legs: legs.clone(),
+                    },
+                );
+            } else {
+                visited.push(stop_id);
+                search_legs(
+                    trips,
+                    calls,
+                    stop_id,
+                    to_stop,
+                    arrival + MIN_TRANSFER_TIME,
+                    board_before,
+                    legs_left - 1,
+                    legs,
+                    visited,
+                    itineraries,
+                );
+                visited.pop();
+            }
+            legs.pop();
+        }
+    }
+}
+
+// Keeps `itineraries` ordered by arrival, then by number of transfers, then latest departure,
+// and no longer than MAX_JOURNEY_RESULTS. Of itineraries riding the same trips only the best is
+// kept.
+fn keep_itinerary(itineraries: &mut Vec<Itinerary>, itinerary: Itinerary) {
+    let order = |itinerary: &Itinerary| {
+        (
+            itinerary.arrival_time,
+            itinerary.transfers,
+            u64::MAX - itinerary.departure_time,
+        )
+    };
+    let same_trips = |kept: &Itinerary| {
+        kept.legs
+            .iter()
+            .map(|leg| leg.trip_id)
+            .eq(itinerary.legs.iter().map(|leg| leg.trip_id))
+    };
+    if let Some(index) = itineraries.iter().position(same_trips) {
+        if order(&itineraries[index]) <= order(&itinerary) {
+            return;
+        }
+        itineraries.remove(index);
+    }
+    let index = itineraries.partition_point(|kept| order(kept) <= order(&itinerary));
+    if index < MAX_JOURNEY_RESULTS {
+        itineraries.insert(index, itinerary);
+        itineraries.truncate(MAX_JOURNEY_RESULTS);
+    }
+}
+
+fn _get_latest_useful_arrival(itineraries: &[Itinerary]) -> u64 {
+    match itineraries.get(MAX_JOURNEY_RESULTS - 1) {
+        Some(last) => last.arrival_time,
+        None => u64::MAX,
+    }
+}
+
+// Scheduled trips on a route departing in [from, to) with standard seats. Stop times include
+// any delay the driver has reported.
+fn _get_timed_trips(from: u64, to: u64) -> Vec<TimedTrip> {
+    let trips: Vec<Trip> = TRIP_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, trip)| trip)
+            .filter(|trip| {
+                trip.status == TripStatus::Scheduled
+                    && trip.route_id.is_some()
+                    && trip.departure_time >= from
+                    && trip.departure_time < to
+            })
+            .collect()
+    });
+    trips
+        .into_iter()
+        .filter_map(|trip| {
+            let route = _get_route(&trip.route_id?)?;
+            let bus = _get_bus(&trip.bus_id)?;
+            let occupancy = _get_seat_occupancy(bus.id, Some(trip.id));
+            let standard_seats: Vec<u64> = _get_bus_seats(&bus)
+                .iter()
+                .filter(|seat| seat.seat_class == SeatClass::Standard)
+                .map(|seat| occupancy.get(&seat.seat_number).copied().unwrap_or(0))
+                .collect();
+            if standard_seats.is_empty() {
+                return None;
+            }
+            let start = trip
+                .departure_time
+                .saturating_add(u64::from(trip.delay_minutes).saturating_mul(60_000_000_000));
+            let stops = route
+                .stops
+                .iter()
+                .map(|route_stop| {
+                    (
+                        route_stop.stop_id,
+                        start.saturating_add(route_stop.arrival_offset),
+                        start.saturating_add(route_stop.departure_offset),
+                    )
+                })
+                .collect();
+            Some(TimedTrip {
+                trip,
+                route,
+                stops,
+                standard_seats,
+                fare: bus.fare,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,397 @@
+use super::*;
+
+// Trips are generated this many days ahead of time
+const TIMETABLE_HORIZON_DAYS: u64 = 14;
+pub(crate) const TIMETABLE_RUN_INTERVAL: u64 = 60 * 60 * 1_000_000_000;
+const MAX_TIMETABLE_DEPARTURES: usize = 48;
+pub(crate) const MAX_TIMETABLE_BUSES: usize = 32;
+pub(crate) const MAX_TIMETABLE_EXCEPTIONS: usize = 128;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    fn of(timestamp: u64) -> Weekday {
+        // 1 January 1970 was a Thursday
+        match (timestamp / NANOS_PER_DAY + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+// Times of day are UTC, in nanoseconds after midnight
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct Timetable {
+    pub(crate) id: u64,
+    route_id: u64,
+    departure_times: Vec<u64>,
+    days_of_week: Vec<Weekday>,
+    valid_from: u64,
+    valid_until: u64,
+    pub(crate) bus_ids: Vec<u64>,    // the pool, tried in order for every departure
+    exceptions: Vec<u64>, // start of each day without service, e.g. holidays
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Storable for Timetable {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Timetable {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct TimetablePayload {
+    pub(crate) route_id: u64,
+    pub(crate) departure_times: Vec<u64>,
+    pub(crate) days_of_week: Vec<Weekday>,
+    pub(crate) valid_from: u64,
+    pub(crate) valid_until: u64,
+    pub(crate) bus_ids: Vec<u64>,
+    pub(crate) exceptions: Vec<u64>, // any time on the day is accepted
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+struct SkippedDeparture {
+    timetable_id: u64,
+    departure_time: u64,
+    reason: String,
+}
+
+// Result of the last timetable run
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct TimetableReport {
+    generated_at: u64,
+    trips_created: u32,
+    skipped: Vec<SkippedDeparture>,
+}
+
+impl Storable for TimetableReport {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[ic_cdk::update]
+fn add_timetable(payload: TimetablePayload) -> Result<Timetable, Error> {
+    ensure_admin()?;
+    validate_timetable_payload(&payload)?;
+    let timetable = do_create_timetable(payload);
+    do_run_timetables(time());
+    Ok(timetable)
+}
+
+// Trips that already have bookings are kept as they are; the timetable's other future trips
+// are withdrawn and generated again from the new definition
+#[ic_cdk::update]
+fn update_timetable(id: u64, payload: TimetablePayload) -> Result<Timetable, Error> {
+    ensure_admin()?;
+    let mut timetable = _get_timetable(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a timetable with id={} not found", id),
+    })?;
+    validate_timetable_payload(&payload)?;
+    let now = time();
+    do_update_timetable(&mut timetable, payload, now);
+    do_run_timetables(now);
+    Ok(timetable)
+}
+
+// Stops generating trips; future trips without bookings or a driver are withdrawn
+#[ic_cdk::update]
+fn delete_timetable(id: u64) -> Result<Timetable, Error> {
+    ensure_admin()?;
+    match TIMETABLE_STORAGE.with(|service| service.borrow_mut().remove(&id)) {
+        Some(timetable) => {
+            do_withdraw_timetable_trips(id, time());
+            Ok(timetable)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("a timetable with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn get_timetable(id: u64) -> Result<Timetable, Error> {
+    match _get_timetable(&id) {
+        Some(timetable) => Ok(timetable),
+        None => Err(Error::NotFound {
+            msg: format!("a timetable with id={} not found", id),
+        }),
+    }
+}
+
+#[ic_cdk::query]
+fn list_timetables() -> Vec<Timetable> {
+    TIMETABLE_STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, timetable)| timetable)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_timetable_report() -> TimetableReport {
+    TIMETABLE_REPORT.with(|report| report.borrow().get().clone())
+}
+
+// Generates trips now instead of waiting for the next scheduled run
+#[ic_cdk::update]
+fn run_timetables() -> Result<TimetableReport, Error> {
+    ensure_admin()?;
+    Ok(do_run_timetables(time()))
+}
+
+pub(crate) fn validate_timetable_payload(payload: &TimetablePayload) -> Result<(), Error> {
+    let route = _get_route(&payload.route_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a route with id={} not found", payload.route_id),
+    })?;
+    ensure_route_length(&route)?;
+    if payload.departure_times.is_empty()
+        || payload.departure_times.len() > MAX_TIMETABLE_DEPARTURES
+        || payload
+            .departure_times
+            .iter()
+            .any(|time| *time >= NANOS_PER_DAY)
+    {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a timetable needs between 1 and {} departure times within a day",
+                MAX_TIMETABLE_DEPARTURES
+            ),
+        });
+    }
+    if payload.days_of_week.is_empty() {
+        return Err(Error::InvalidInput {
+            msg: "a timetable must run on at least one day of the week".to_string(),
+        });
+    }
+    if payload.valid_until <= payload.valid_from {
+        return Err(Error::InvalidInput {
+            msg: "a timetable must end after it starts".to_string(),
+        });
+    }
+    if payload.bus_ids.is_empty() || payload.bus_ids.len() > MAX_TIMETABLE_BUSES {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a timetable needs between 1 and {} buses",
+                MAX_TIMETABLE_BUSES
+            ),
+        });
+    }
+    if let Some(bus_id) = payload
+        .bus_ids
+        .iter()
+        .find(|bus_id| _get_bus(bus_id).is_none())
+    {
+        return Err(Error::NotFound {
+            msg: format!("a bus with id={} not found", bus_id),
+        });
+    }
+    if payload.exceptions.len() > MAX_TIMETABLE_EXCEPTIONS {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a timetable can have at most {} exceptions",
+                MAX_TIMETABLE_EXCEPTIONS
+            ),
+        });
+    }
+    Ok(())
+}
+
+// Creates the missing trips of every timetable for the next TIMETABLE_HORIZON_DAYS days.
+// Departures that already have a trip (in any state) are left alone, so runs can repeat.
+pub(crate) fn do_run_timetables(now: u64) -> TimetableReport {
+    let mut report = TimetableReport {
+        generated_at: now,
+        ..Default::default()
+    };
+    for timetable in list_timetables() {
+        let Some(route) = _get_route(&timetable.route_id) else {
+            continue;
+        };
+        let (origin, destination) = _get_route_endpoints(&route);
+        let duration = route.stops.last().map_or(0, |stop| stop.arrival_offset);
+        let existing: BTreeSet<u64> = _get_timetable_trip_ids(timetable.id).into_keys().collect();
+        let holidays: BTreeSet<u64> = timetable
+            .exceptions
+            .iter()
+            .map(|day| day / NANOS_PER_DAY)
+            .collect();
+        let today = now / NANOS_PER_DAY;
+        for day in today..=today + TIMETABLE_HORIZON_DAYS {
+            let day_start = day * NANOS_PER_DAY;
+            if holidays.contains(&day) || !timetable.days_of_week.contains(&Weekday::of(day_start))
+            {
+                continue;
+            }
+            for departure_time in timetable
+                .departure_times
+                .iter()
+                .map(|time| day_start + time)
+            {
+                if departure_time <= now
+                    || departure_time < timetable.valid_from
+                    || departure_time >= timetable.valid_until
+                    || existing.contains(&departure_time)
+                {
+                    continue;
+                }
+                let mut reason = String::new();
+                for bus_id in &timetable.bus_ids {
+                    let created = do_create_trip(
+                        TripPayload {
+                            bus_id: *bus_id,
+                            route_id: Some(route.id),
+                            origin: origin.clone(),
+                            destination: destination.clone(),
+                            departure_time,
+                            arrival_time: departure_time + duration,
+                        },
+                        Some(timetable.id),
+                    );
+                    match created {
+                        Ok(_) => {
+                            reason.clear();
+                            report.trips_created += 1;
+                            break;
+                        }
+                        Err(error) => reason = error_message(error),
+                    }
+                }
+                if !reason.is_empty() {
+                    report.skipped.push(SkippedDeparture {
+                        timetable_id: timetable.id,
+                        departure_time,
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+    TIMETABLE_REPORT
+        .with(|cell| cell.borrow_mut().set(report.clone()))
+        .expect("cannot store the timetable report");
+    report
+}
+
+// Removes the timetable's scheduled future trips that nobody has booked or been assigned to
+// drive, along with their events, and frees their buses. Trips that are staffed or booked stay
+// and have to be cancelled with `cancel_trip`.
+fn do_withdraw_timetable_trips(timetable_id: u64, now: u64) {
+    let booked: BTreeSet<u64> = _get_reservations()
+        .into_iter()
+        .filter(|reservation| reservation.status == ReservationStatus::Confirmed)
+        .filter_map(|reservation| reservation.trip_id)
+        .collect();
+    let withdrawn: Vec<Trip> = _get_timetable_trip_ids(timetable_id)
+        .into_values()
+        .filter_map(|trip_id| _get_trip(&trip_id))
+        .filter(|trip| {
+            trip.status == TripStatus::Scheduled
+                && trip.departure_time > now
+                && trip.driver_id.is_none()
+                && !booked.contains(&trip.id)
+        })
+        .collect();
+    for trip in withdrawn {
+        do_unblock_bus(trip.bus_id, trip.departure_time);
+        let events: Vec<(u64, u64)> = TRIP_EVENTS.with(|service| {
+            service
+                .borrow()
+                .range((trip.id, 0)..=(trip.id, u64::MAX))
+                .map(|(key, _)| key)
+                .collect()
+        });
+        TRIP_EVENTS.with(|service| {
+            let mut service = service.borrow_mut();
+            for key in events {
+                service.remove(&key);
+            }
+        });
+        TIMETABLE_TRIPS.with(|service| {
+            service
+                .borrow_mut()
+                .remove(&(timetable_id, trip.departure_time))
+        });
+        TRIP_STORAGE.with(|service| service.borrow_mut().remove(&trip.id));
+    }
+}
+
+// Departure times of the trips generated from the timetable, with the trip ids
+pub(crate) fn _get_timetable_trip_ids(timetable_id: u64) -> BTreeMap<u64, u64> {
+    TIMETABLE_TRIPS.with(|service| {
+        service
+            .borrow()
+            .range((timetable_id, 0)..=(timetable_id, u64::MAX))
+            .map(|((_, departure_time), trip_id)| (departure_time, trip_id))
+            .collect()
+    })
+}
+
+pub(crate) fn do_create_timetable(payload: TimetablePayload) -> Timetable {
+    let timetable = Timetable {
+        id: next_id(),
+        route_id: payload.route_id,
+        departure_times: payload.departure_times,
+        days_of_week: payload.days_of_week,
+        valid_from: payload.valid_from,
+        valid_until: payload.valid_until,
+        bus_ids: payload.bus_ids,
+        exceptions: payload.exceptions,
+        created_at: time(),
+        updated_at: None,
+    };
+    do_insert_timetable(&timetable);
+    timetable
+}
+
+pub(crate) fn do_update_timetable(timetable: &mut Timetable, payload: TimetablePayload, now: u64) {
+    timetable.route_id = payload.route_id;
+    timetable.departure_times = payload.departure_times;
+    timetable.days_of_week = payload.days_of_week;
+    timetable.valid_from = payload.valid_from;
+    timetable.valid_until = payload.valid_until;
+    timetable.bus_ids = payload.bus_ids;
+    timetable.exceptions = payload.exceptions;
+    timetable.updated_at = Some(now);
+    do_insert_timetable(timetable);
+    do_withdraw_timetable_trips(timetable.id, now);
+}
+
+fn do_insert_timetable(timetable: &Timetable) {
+    TIMETABLE_STORAGE.with(|service| service.borrow_mut().insert(timetable.id, timetable.clone()));
+}
+
+pub(crate) fn _get_timetable(id: &u64) -> Option<Timetable> {
+    TIMETABLE_STORAGE.with(|service| service.borrow().get(id))
+}
@@ -0,0 +1,219 @@
+use super::*;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) enum TripEventKind {
+    Departed,
+    Arrived,
+    Delayed,
+    Cancelled,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Clone)]
+pub(crate) struct TripEvent {
+    pub(crate) id: u64,
+    pub(crate) trip_id: u64,
+    pub(crate) kind: TripEventKind,
+    pub(crate) stop_id: Option<u64>,
+    pub(crate) delay_minutes: Option<u32>,
+    pub(crate) reason: Option<String>,
+    pub(crate) recorded_by: Principal,
+    pub(crate) recorded_at: u64,
+}
+
+impl Storable for TripEvent {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for TripEvent {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Leaves room for the rest of the event within its MAX_SIZE
+const MAX_TRIP_EVENT_REASON_LEN: usize = 512;
+
+// A trip running a day late is cancelled and its passengers rebooked instead
+const MAX_DELAY_MINUTES: u32 = 24 * 60;
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub(crate) struct TripProgress {
+    trip: Trip,
+    events: Vec<TripEvent>,
+    departure_delay_minutes: Option<u64>, // how late the bus actually left its first stop
+}
+
+// Departure from a stop. Leaving the first stop (or any stop on a trip without a route)
+// starts the trip.
+#[ic_cdk::update]
+fn record_departure(trip_id: u64, stop_id: Option<u64>) -> Result<Trip, Error> {
+    let mut trip = _get_trip_for_update(trip_id)?;
+    let position = _get_trip_stop_position(&trip, stop_id)?;
+    let now = time();
+    if position.is_none_or(|index| index == 0) && trip.actual_departure_time.is_none() {
+        trip.actual_departure_time = Some(now);
+    }
+    trip.status = TripStatus::InProgress;
+    trip.updated_at = Some(now);
+    do_record_trip_event(&trip, TripEventKind::Departed, stop_id, None, None);
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+// Arrival at a stop. Arriving at the last stop (or any arrival on a trip without a route)
+// completes the trip.
+#[ic_cdk::update]
+fn record_arrival(trip_id: u64, stop_id: Option<u64>) -> Result<Trip, Error> {
+    let mut trip = _get_trip_for_update(trip_id)?;
+    if trip.status == TripStatus::Scheduled {
+        return Err(Error::InvalidInput {
+            msg: format!("trip with id={} hasn't departed yet", trip_id),
+        });
+    }
+    let position = _get_trip_stop_position(&trip, stop_id)?;
+    let stop_count = trip
+        .route_id
+        .and_then(|route_id| _get_route(&route_id))
+        .map_or(0, |route| route.stops.len());
+    let now = time();
+    if position.is_none_or(|index| index + 1 == stop_count) {
+        trip.status = TripStatus::Completed;
+        trip.actual_arrival_time = Some(now);
+    } else {
+        trip.status = TripStatus::InProgress;
+    }
+    trip.updated_at = Some(now);
+    do_record_trip_event(&trip, TripEventKind::Arrived, stop_id, None, None);
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+#[ic_cdk::update]
+fn report_delay(trip_id: u64, delay_minutes: u32, reason: String) -> Result<Trip, Error> {
+    ensure_trip_event_reason(&reason)?;
+    if delay_minutes > MAX_DELAY_MINUTES {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a delay can be at most {} minutes; cancel the trip instead",
+                MAX_DELAY_MINUTES
+            ),
+        });
+    }
+    let mut trip = _get_trip_for_update(trip_id)?;
+    trip.delay_minutes = delay_minutes;
+    trip.updated_at = Some(time());
+    do_record_trip_event(
+        &trip,
+        TripEventKind::Delayed,
+        None,
+        Some(delay_minutes),
+        Some(reason),
+    );
+    do_insert_trip(&trip);
+    Ok(trip)
+}
+
+#[ic_cdk::query]
+pub(crate) fn get_trip_progress(trip_id: u64) -> Result<TripProgress, Error> {
+    let trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    let events = _get_trip_events(trip_id);
+    let departure_delay_minutes = trip
+        .actual_departure_time
+        .map(|departed| departed.saturating_sub(trip.departure_time) / 60_000_000_000);
+    Ok(TripProgress {
+        trip,
+        events,
+        departure_delay_minutes,
+    })
+}
+
+// Trip updates come from the trip's assigned driver or a controller
+pub(crate) fn _get_trip_for_update(trip_id: u64) -> Result<Trip, Error> {
+    let trip = _get_trip(&trip_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a trip with id={} not found", trip_id),
+    })?;
+    if ensure_admin().is_err() {
+        let driver = _get_caller_driver()?;
+        if trip.driver_id != Some(driver.id) {
+            return Err(Error::Unauthorized {
+                msg: format!("the caller is not driving trip id={}", trip_id),
+            });
+        }
+    }
+    if trip.status == TripStatus::Completed || trip.status == TripStatus::Cancelled {
+        return Err(Error::InvalidInput {
+            msg: format!("trip with id={} is already finished", trip_id),
+        });
+    }
+    Ok(trip)
+}
+
+// Index of `stop_id` on the trip's route, or None when no stop was given or the trip has no route
+fn _get_trip_stop_position(trip: &Trip, stop_id: Option<u64>) -> Result<Option<usize>, Error> {
+    let (Some(stop_id), Some(route_id)) = (stop_id, trip.route_id) else {
+        return Ok(None);
+    };
+    _get_route(&route_id)
+        .and_then(|route| {
+            route
+                .stops
+                .iter()
+                .position(|route_stop| route_stop.stop_id == stop_id)
+        })
+        .map(Some)
+        .ok_or_else(|| Error::InvalidInput {
+            msg: format!(
+                "stop id={} is not on the route of trip id={}",
+                stop_id, trip.id
+            ),
+        })
+}
+
+pub(crate) fn ensure_trip_event_reason(reason: &str) -> Result<(), Error> {
+    if reason.len() > MAX_TRIP_EVENT_REASON_LEN {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "a reason can be at most {} bytes long",
+                MAX_TRIP_EVENT_REASON_LEN
+            ),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn do_record_trip_event(
+    trip: &Trip,
+    kind: TripEventKind,
+    stop_id: Option<u64>,
+    delay_minutes: Option<u32>,
+    reason: Option<String>,
+) {
+    let event = TripEvent {
+        id: next_id(),
+        trip_id: trip.id,
+        kind,
+        stop_id,
+        delay_minutes,
+        reason,
+        recorded_by: ic_cdk::caller(),
+        recorded_at: time(),
+    };
+    TRIP_EVENTS.with(|service| service.borrow_mut().insert((trip.id, event.id), event));
+}
+
+pub(crate) fn _get_trip_events(trip_id: u64) -> Vec<TripEvent> {
+    TRIP_EVENTS.with(|service| {
+        service
+            .borrow()
+            .range((trip_id, 0)..=(trip_id, u64::MAX))
+            .map(|(_, event)| event)
+            .collect()
+    })
+}